@@ -10,5 +10,5 @@ mod profiler;
 mod render;
 mod report;
 
-pub use log::{FrameName, TransactionGasLog};
+pub use log::{CallFrame, ExecutionGasEvent, FrameName, TransactionGasLog};
 pub use profiler::GasProfiler;
@@ -3,6 +3,7 @@
 
 pub mod components;
 pub mod simulate;
+pub mod state_view;
 mod utils;
 pub mod validate;
 
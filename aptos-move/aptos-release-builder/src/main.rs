@@ -8,16 +8,28 @@ use aptos_gas_schedule::LATEST_GAS_FEATURE_VERSION;
 use aptos_release_builder::{
     components::fetch_config,
     initialize_aptos_core_path,
-    simulate::simulate_all_proposals,
+    simulate::{
+        diff_releases, load_features_snapshot, parse_feature_flag, parse_framework_dir,
+        parse_header, simulate_all_proposals, simulate_watch, AssertionMode, GasReportFormat,
+        GenesisConfig, LimitAction, OutputFormat, Redacted, RemoteFetchLimiter, RetryPolicy,
+        SenderAccount, SenderFunding, SimulationOptions, DEFAULT_DUMMY_PROPOSAL_ID,
+        DEFAULT_MAX_EVENTS_PER_SCRIPT, DEFAULT_MAX_GAS_AMOUNT, DEFAULT_REMOTE_REQUEST_TIMEOUT,
+        DEFAULT_REMOTE_RETRIES, DEFAULT_SENDER_BALANCE,
+    },
+    state_view::OverlayFormat,
     validate::{DEFAULT_RESOLUTION_TIME, FAST_RESOLUTION_TIME},
 };
 use aptos_types::{
     account_address::AccountAddress,
     chain_id::ChainId,
     jwks::{ObservedJWKs, SupportedOIDCProviders},
+    on_chain_config::FeatureFlag,
 };
 use clap::{Parser, Subcommand};
-use std::{path::PathBuf, str::FromStr};
+use move_core_types::{identifier::Identifier, language_storage::ModuleId};
+use move_model::metadata::{CompilerVersion, LanguageVersion};
+use std::{path::PathBuf, str::FromStr, time::Duration};
+use tracing_subscriber::EnvFilter;
 use url::Url;
 
 #[derive(Parser)]
@@ -104,6 +116,310 @@ pub enum Commands {
         /// Set this flag to enable the gas profiler
         #[clap(long, default_value_t = false)]
         profile_gas: bool,
+
+        /// Set this flag to open the generated gas reports in the default browser.
+        /// Has no effect in CI or headless environments
+        #[clap(long, default_value_t = false, requires = "profile_gas")]
+        open_report: bool,
+
+        /// Set this flag to also profile the forced epoch change before each script
+        #[clap(long, default_value_t = false, requires = "profile_gas")]
+        profile_force_end_epoch: bool,
+
+        /// The format of the gas reports written when profiling gas
+        #[clap(long, value_enum, default_value_t = GasReportFormat::Html, requires = "profile_gas")]
+        gas_report_format: GasReportFormat,
+
+        /// Maximum number of scripts of a proposal to compile in parallel.
+        /// Defaults to the number of available CPUs.
+        #[clap(long)]
+        compile_concurrency: Option<usize>,
+
+        /// Maximum number of proposals to simulate in parallel. Proposals run one at a time by
+        /// default
+        #[clap(long, default_value_t = 1)]
+        proposal_concurrency: usize,
+
+        /// Set this flag to compile all scripts of a proposal even if some fail, and report each
+        /// distinct compilation error once along with the scripts it affects
+        #[clap(long, default_value_t = false)]
+        aggregate_compile_errors: bool,
+
+        /// Version of the bytecode to compile the scripts to. Inferred from the language version if
+        /// not set
+        #[clap(long)]
+        bytecode_version: Option<u32>,
+
+        /// Version of the Move language to compile the scripts with
+        #[clap(long, value_parser = clap::value_parser!(LanguageVersion))]
+        language_version: Option<LanguageVersion>,
+
+        /// Version of the compiler to compile the scripts with, e.g. to reproduce the bytecode of a
+        /// release build. Precompiled scripts are used as is
+        #[clap(long, value_parser = clap::value_parser!(CompilerVersion))]
+        compiler_version: Option<CompilerVersion>,
+
+        /// Path to a local framework package to compile the scripts against, e.g. one with changes
+        /// the scripts depend on. Defaults to the framework in this repository
+        #[clap(long, value_parser = parse_framework_dir)]
+        framework_dir: Option<PathBuf>,
+
+        /// Set this flag to record the functions executed by the governance scripts
+        #[clap(long, default_value_t = false)]
+        capture_coverage: bool,
+
+        /// Set this flag to watch the proposals under the path and re-simulate a proposal
+        /// whenever one of its scripts changes
+        #[clap(long, default_value_t = false)]
+        watch: bool,
+
+        /// Set this flag to skip the forced epoch change before the first script, which is only
+        /// needed when the remote state has buffered configuration changes
+        #[clap(long, default_value_t = false)]
+        skip_initial_epoch_force: bool,
+
+        /// Set this flag to never force the epoch to end between scripts, leaving buffered
+        /// configuration changes pending
+        #[clap(
+            long,
+            default_value_t = false,
+            conflicts_with = "skip_initial_epoch_force"
+        )]
+        no_epoch_force: bool,
+
+        /// Set this flag to execute each proposal twice from the same state and report the state
+        /// items that differ between the runs
+        #[clap(long, default_value_t = false)]
+        check_idempotency: bool,
+
+        /// Set this flag to warn if the hash of the first script of a proposal matches none of the
+        /// execution hashes approved on chain
+        #[clap(long, default_value_t = false)]
+        verify_approved_hashes: bool,
+
+        /// The governance module whose resolve function gets patched to skip the governance
+        /// process, e.g. `0x1::aptos_governance`. Useful for experimental governance paths, which
+        /// must be published at `0x1`.
+        #[clap(long)]
+        governance_module: Option<ModuleId>,
+
+        /// The function of the governance module that gets patched, e.g.
+        /// `resolve_multi_step_proposal`.
+        #[clap(long)]
+        resolve_function: Option<String>,
+
+        /// How the APT balance of the sender account is stored. By default, this follows the
+        /// on-chain coin-to-fungible-asset migration state.
+        #[clap(long, value_enum, default_value_t = SenderFunding::Auto)]
+        sender_funding: SenderFunding,
+
+        /// The APT balance the sender account starts with, in octas
+        #[clap(long, default_value_t = DEFAULT_SENDER_BALANCE)]
+        sender_balance: u64,
+
+        /// Address of the sender account. Defaults to an account derived from a fixed seed
+        #[clap(long)]
+        sender_address: Option<AccountAddress>,
+
+        /// Maximum gas amount of the transactions the scripts are executed in
+        #[clap(long, default_value_t = DEFAULT_MAX_GAS_AMOUNT)]
+        max_gas_amount: u64,
+
+        /// Maximum gas units a proposal, and all proposals combined, may use before the
+        /// simulation fails
+        #[clap(long)]
+        max_total_gas: Option<u64>,
+
+        /// Maximum gas units a single script may use before the simulation fails
+        #[clap(long)]
+        max_gas_per_script: Option<u64>,
+
+        /// Maximum number of write ops the write set of a single script may contain
+        #[clap(long)]
+        max_write_ops_per_script: Option<usize>,
+
+        /// Whether a script exceeding the write op limit fails the simulation or only warns
+        #[clap(long, value_enum, default_value_t = LimitAction::Fail)]
+        write_ops_limit_action: LimitAction,
+
+        /// Maximum number of remote state fetches in flight at the same time, to avoid
+        /// overwhelming the REST endpoint. Unlimited by default.
+        #[clap(long)]
+        max_concurrent_remote_fetches: Option<usize>,
+
+        /// Number of times a failed request to the REST endpoint is retried, with exponential
+        /// backoff
+        #[clap(long, default_value_t = DEFAULT_REMOTE_RETRIES)]
+        remote_retries: u32,
+
+        /// Timeout of a single request to the REST endpoint, in seconds
+        #[clap(long, default_value_t = DEFAULT_REMOTE_REQUEST_TIMEOUT.as_secs())]
+        remote_timeout_secs: u64,
+
+        /// API key sent as a bearer token to the REST endpoint
+        #[clap(long, env = "APTOS_API_KEY", hide_env_values = true)]
+        remote_api_key: Option<Redacted<String>>,
+
+        /// Additional header sent to the REST endpoint, as `<name>:<value>`. Can be given
+        /// multiple times
+        #[clap(long = "remote-header", value_parser = parse_header)]
+        remote_headers: Vec<(String, Redacted<String>)>,
+
+        /// Maximum number of events retained per script. Further events are only counted.
+        #[clap(long, default_value_t = DEFAULT_MAX_EVENTS_PER_SCRIPT)]
+        max_events_per_script: usize,
+
+        /// Set this flag to print the events emitted by each script, with decoded payloads
+        #[clap(long, default_value_t = false)]
+        print_events: bool,
+
+        /// Set this flag to save the remote state read while simulating each proposal to
+        /// `state_snapshot.bcs` in the proposal directory, for later use with `--offline`
+        #[clap(long, default_value_t = false)]
+        record_state_snapshot: bool,
+
+        /// Set this flag to export all state items changed by simulating each proposal to
+        /// `overlay.bcs` or `overlay.json` in the proposal directory
+        #[clap(long, default_value_t = false)]
+        export_overlay: bool,
+
+        /// Format the state overlay is exported in
+        #[clap(long, value_enum, default_value_t = OverlayFormat::Bcs)]
+        overlay_format: OverlayFormat,
+
+        /// State overlay exported by an earlier simulation to apply before any script is
+        /// executed, as a `.bcs` or `.json` file. Can be given multiple times
+        #[clap(long = "import-overlay")]
+        import_overlays: Vec<PathBuf>,
+
+        /// Set this flag to write the state keys each proposal reads from the remote state to
+        /// `remote_reads.txt` in the proposal directory, also if the proposal fails
+        #[clap(long, default_value_t = false)]
+        record_remote_reads: bool,
+
+        /// Set this flag to write the patched governance modules to `patched-modules` in the
+        /// proposal directory before they are verified
+        #[clap(long, default_value_t = false)]
+        dump_patched_modules: bool,
+
+        /// Set this flag to simulate each proposal on top of the state snapshot in its directory
+        /// rather than the remote state, which requires no network access
+        #[clap(
+            long,
+            default_value_t = false,
+            conflicts_with = "record_state_snapshot"
+        )]
+        offline: bool,
+
+        /// Simulate each proposal on top of a genesis state built locally with the given
+        /// framework instead of the remote state
+        #[clap(long, value_enum, conflicts_with_all = &["offline", "record_state_snapshot"])]
+        local_genesis: Option<GenesisConfig>,
+
+        /// Path to a feature flags snapshot, in the format printed by `print-configs`, to use
+        /// instead of the feature flags on chain
+        #[clap(long)]
+        features_snapshot: Option<PathBuf>,
+
+        /// Path to a feature flags snapshot, in the format printed by `print-configs`, to derive
+        /// the bytecode deserializer config from when patching modules, e.g. to test a proposal
+        /// that enables a new bytecode feature itself
+        #[clap(long)]
+        deserializer_features_snapshot: Option<PathBuf>,
+
+        /// Feature flag to force on before each script, e.g. `vm_binary_format_v7`. Can be
+        /// given multiple times
+        #[clap(long = "enable-feature", value_parser = parse_feature_flag)]
+        enable_features: Vec<FeatureFlag>,
+
+        /// Feature flag to force off before each script. Can be given multiple times
+        #[clap(long = "disable-feature", value_parser = parse_feature_flag)]
+        disable_features: Vec<FeatureFlag>,
+
+        /// Path to write a JUnit XML report of the simulated proposals to
+        #[clap(long)]
+        junit_report: Option<PathBuf>,
+
+        /// How to output the simulation results. With `json`, the results of each proposal are
+        /// additionally written to `simulation.json` in the proposal directory
+        #[clap(long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+
+        /// Version of the remote state to simulate on, for proposals whose manifest does not
+        /// specify one. Defaults to the latest version
+        #[clap(long)]
+        at_version: Option<u64>,
+
+        /// Set this flag to record and report the arguments the patched resolve function
+        /// received in each step
+        #[clap(long, default_value_t = false)]
+        record_resolve_args: bool,
+
+        /// Proposal id passed to the scripts. It is ignored by the patched resolve function, so it
+        /// only needs changing for scripts that use the id for other purposes
+        #[clap(long, default_value_t = DEFAULT_DUMMY_PROPOSAL_ID)]
+        dummy_proposal_id: u64,
+
+        /// Set this flag to force the VM's paranoid type checks on
+        #[clap(long, default_value_t = false)]
+        force_paranoid: bool,
+
+        /// Set this flag to construct a fresh VM environment for every script, instead of reusing
+        /// it while the configs it is derived from are unchanged
+        #[clap(long, default_value_t = false)]
+        no_environment_reuse: bool,
+
+        /// Set this flag to summarize the changes made to well-known staking resources
+        #[clap(long, default_value_t = false)]
+        summarize_staking: bool,
+
+        /// Set this flag to write a diff of the resources changed by each proposal to
+        /// `resource_diff.txt` in the proposal directory
+        #[clap(long, default_value_t = false)]
+        resource_diff: bool,
+
+        /// Set this flag to report the changes made to the on-chain consensus config
+        #[clap(long, default_value_t = false)]
+        summarize_consensus_config: bool,
+
+        /// Set this flag to format gas and fee figures with thousands separators
+        #[clap(long, default_value_t = false)]
+        group_digits: bool,
+
+        /// Set this flag to warn about scripts that change no on-chain config and publish no
+        /// modules. Steps can opt out via `allow_no_op` in the proposal's `proposal.json`.
+        #[clap(long, default_value_t = false)]
+        warn_no_op: bool,
+
+        /// Set this flag to fail scripts that do not trigger a reconfiguration. Steps can opt out
+        /// via `allow_no_reconfiguration` in the proposal's `proposal.json`
+        #[clap(long, default_value_t = false)]
+        require_reconfiguration: bool,
+
+        /// Set this flag to report each function called directly by the scripts, e.g. the
+        /// individual actions of a single-script proposal
+        #[clap(long, default_value_t = false)]
+        report_actions: bool,
+
+        /// Whether to stop at the first failed assertion, e.g. an exceeded gas budget, or to
+        /// report all of them at the end
+        #[clap(long, value_enum, default_value_t = AssertionMode::CollectAll)]
+        assertion_mode: AssertionMode,
+
+        /// Set this flag to keep simulating the remaining proposals after one fails, and report
+        /// which proposals passed and which failed at the end
+        #[clap(long, default_value_t = false)]
+        keep_going: bool,
+    },
+    /// Compare the proposals of two release directories without simulating them.
+    DiffReleases {
+        /// Path to the old release directory
+        #[clap(long)]
+        old_path: PathBuf,
+
+        /// Path to the new release directory
+        #[clap(long)]
+        new_path: PathBuf,
     },
     /// Generate sets of governance proposals with default release config.
     WriteDefault {
@@ -191,6 +507,17 @@ async fn main() -> anyhow::Result<()> {
     let args = Argument::parse();
     initialize_aptos_core_path(args.aptos_core_path.clone());
 
+    // Progress is reported through `tracing`. Print it as plain lines, like the rest of the
+    // output, unless the verbosity is changed via `RUST_LOG`.
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .without_time()
+        .with_level(false)
+        .with_target(false)
+        .init();
+
     // TODO: Being able to parse the release config from a TOML file to generate the proposals.
     match args.cmd {
         Commands::GenerateProposals {
@@ -207,10 +534,12 @@ async fn main() -> anyhow::Result<()> {
 
             match simulate {
                 Some(network) => {
-                    let profile_gas = profile_gas.unwrap_or(false);
+                    let options = SimulationOptions {
+                        profile_gas: profile_gas.unwrap_or(false),
+                        ..SimulationOptions::default()
+                    };
                     let remote_endpoint = network.to_url()?;
-                    simulate_all_proposals(remote_endpoint, output_dir.as_path(), profile_gas)
-                        .await?;
+                    simulate_all_proposals(remote_endpoint, output_dir.as_path(), &options).await?;
                 },
                 None => {
                     if profile_gas.is_some() {
@@ -225,8 +554,161 @@ async fn main() -> anyhow::Result<()> {
             network,
             path,
             profile_gas,
+            open_report,
+            profile_force_end_epoch,
+            gas_report_format,
+            compile_concurrency,
+            proposal_concurrency,
+            aggregate_compile_errors,
+            bytecode_version,
+            language_version,
+            compiler_version,
+            framework_dir,
+            capture_coverage,
+            watch,
+            skip_initial_epoch_force,
+            no_epoch_force,
+            check_idempotency,
+            verify_approved_hashes,
+            governance_module,
+            resolve_function,
+            sender_funding,
+            sender_balance,
+            sender_address,
+            max_gas_amount,
+            max_total_gas,
+            max_gas_per_script,
+            max_write_ops_per_script,
+            write_ops_limit_action,
+            max_concurrent_remote_fetches,
+            remote_retries,
+            remote_timeout_secs,
+            remote_api_key,
+            remote_headers,
+            max_events_per_script,
+            print_events,
+            record_state_snapshot,
+            export_overlay,
+            overlay_format,
+            import_overlays,
+            record_remote_reads,
+            dump_patched_modules,
+            offline,
+            local_genesis,
+            features_snapshot,
+            deserializer_features_snapshot,
+            enable_features,
+            disable_features,
+            junit_report,
+            format,
+            at_version,
+            record_resolve_args,
+            dummy_proposal_id,
+            force_paranoid,
+            no_environment_reuse,
+            summarize_staking,
+            resource_diff,
+            summarize_consensus_config,
+            group_digits,
+            warn_no_op,
+            require_reconfiguration,
+            report_actions,
+            assertion_mode,
+            keep_going,
         } => {
-            simulate_all_proposals(network.to_url()?, &path, profile_gas).await?;
+            let mut options = SimulationOptions {
+                profile_gas,
+                open_report,
+                profile_force_end_epoch,
+                gas_report_format,
+                aggregate_compile_errors,
+                bytecode_version,
+                language_version,
+                compiler_version,
+                framework_dir,
+                force_paranoid,
+                reuse_environment: !no_environment_reuse,
+                summarize_staking,
+                resource_diff,
+                summarize_consensus_config,
+                group_digits,
+                warn_no_op,
+                require_reconfiguration,
+                report_actions,
+                assertion_mode,
+                keep_going,
+                capture_coverage,
+                skip_initial_epoch_force,
+                no_epoch_force,
+                check_idempotency,
+                verify_approved_hashes,
+                sender_funding,
+                sender_balance,
+                sender_account: sender_address
+                    .map(SenderAccount::Address)
+                    .unwrap_or_default(),
+                max_gas_amount,
+                max_total_gas,
+                max_gas_per_script,
+                max_write_ops_per_script,
+                write_ops_limit_action,
+                remote_fetch_limiter: max_concurrent_remote_fetches.map(RemoteFetchLimiter::new),
+                remote_request_timeout: Duration::from_secs(remote_timeout_secs),
+                remote_retry_policy: RetryPolicy {
+                    max_retries: remote_retries,
+                    ..Default::default()
+                },
+                remote_api_key,
+                remote_headers,
+                max_events_per_script,
+                print_events,
+                record_state_snapshot,
+                export_overlay,
+                overlay_format,
+                import_overlays,
+                record_remote_reads,
+                dump_patched_modules,
+                offline,
+                local_genesis,
+                features_override: features_snapshot
+                    .as_deref()
+                    .map(load_features_snapshot)
+                    .transpose()?,
+                deserializer_features_override: deserializer_features_snapshot
+                    .as_deref()
+                    .map(load_features_snapshot)
+                    .transpose()?,
+                enabled_features: enable_features,
+                disabled_features: disable_features,
+                junit_report_path: junit_report,
+                output_format: format,
+                at_version,
+                dummy_proposal_id,
+                proposal_concurrency,
+                ..SimulationOptions::default()
+            };
+            if let Some(compile_concurrency) = compile_concurrency {
+                options.compile_concurrency = compile_concurrency;
+            }
+            if let Some(governance_module) = governance_module {
+                options.patch_plan.governance_module = governance_module;
+            }
+            options.patch_plan.record_resolve_args = record_resolve_args;
+            if let Some(resolve_function) = resolve_function {
+                options.patch_plan.resolve_function = Identifier::new(resolve_function.as_str())
+                    .with_context(|| {
+                        format!("invalid resolve function name: {}", resolve_function)
+                    })?;
+            }
+            if watch {
+                simulate_watch(network.to_url()?, &path, &options).await?;
+            } else {
+                simulate_all_proposals(network.to_url()?, &path, &options).await?;
+            }
+            Ok(())
+        },
+        Commands::DiffReleases { old_path, new_path } => {
+            diff_releases(&old_path, &new_path)?.print();
             Ok(())
         },
         Commands::WriteDefault { output_path } => {
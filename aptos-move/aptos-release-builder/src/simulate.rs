@@ -1,8 +1,8 @@
 // Copyright © Aptos Foundation
 // SPDX-License-Identifier: Apache-2.0
 
-//! This module implements the simulation of governance proposals.
-//! Currently, it supports only multi-step proposals.
+//! This module implements the simulation of governance proposals, both multi-step ones and
+//! single-step ones resolved through `aptos_governance::resolve`.
 //!
 //! It utilizes the remote debugger infrastructure to fetch real chain states
 //! for local simulation, but adds another in-memory database to store the new side effects
@@ -13,71 +13,126 @@
 //! (e.g., staking pool, delegated voter), which can be quite complex.
 //!
 //! This simulation bypasses these challenges by patching specific Move functions
-//! with mock versions, most notably `fun resolve_multi_step_proposal`, thus allowing
+//! with mock versions, most notably `fun resolve_multi_step_proposal` and `fun resolve`, thus allowing
 //! the governance process to be skipped altogether.
 //!
 //! In other words, this simulation is intended for checking whether a governance
 //! proposal will execute successfully, assuming it gets approved, not whether the
 //! governance framework itself is working as intended.
 
-use crate::aptos_framework_path;
+use crate::{
+    aptos_framework_path,
+    components::feature_flags,
+    state_view::{OverlayFormat, SimulationStateView},
+};
 use anyhow::{anyhow, bail, Context, Result};
 use aptos::{
     common::types::PromptOptions, governance::compile_in_temp_dir, move_tool::FrameworkPackageArgs,
 };
 use aptos_crypto::HashValue;
-use aptos_gas_profiling::GasProfiler;
+use aptos_framework::get_metadata_from_compiled_module;
+use aptos_gas_profiling::{
+    CallFrame, ExecutionGasEvent, FrameName, GasProfiler, TransactionGasLog,
+};
 use aptos_gas_schedule::{AptosGasParameters, FromOnChainGasSchedule};
-use aptos_language_e2e_tests::account::AccountData;
+use aptos_language_e2e_tests::{
+    account::{Account, AccountData},
+    data_store::{
+        FakeDataStore, GENESIS_CHANGE_SET_HEAD, GENESIS_CHANGE_SET_MAINNET,
+        GENESIS_CHANGE_SET_TESTNET,
+    },
+};
 use aptos_move_debugger::aptos_debugger::AptosDebugger;
-use aptos_rest_client::Client;
+use aptos_resource_viewer::AptosValueAnnotator;
+use aptos_rest_client::{
+    aptos_api_types::{AptosError, AptosErrorCode},
+    error::{AptosErrorResponse, RestError},
+    AptosBaseUrl, Client,
+};
 use aptos_types::{
+    access_path,
     account_address::AccountAddress,
-    account_config::ChainIdResource,
-    on_chain_config::{ApprovedExecutionHashes, Features, GasScheduleV2, OnChainConfig},
+    account_config::{
+        primary_apt_store, AccountResource, ChainIdResource, CoinInfoResource, CoinStoreResource,
+        ConcurrentSupplyResource, ObjectGroupResource, NEW_EPOCH_EVENT_MOVE_TYPE_TAG,
+        NEW_EPOCH_EVENT_V2_MOVE_TYPE_TAG,
+    },
+    chain_id::ChainId,
+    contract_event::ContractEvent,
+    dkg::DKG_START_EVENT_MOVE_TYPE_TAG,
+    event::EventHandle,
+    fee_statement::FeeStatement,
+    on_chain_config::{
+        ApprovedExecutionHashes, ConfigurationResource, FeatureFlag, Features, GasSchedule,
+        GasScheduleV2, OnChainConfig, OnChainConsensusConfig, StorageGasSchedule,
+        TimedFeaturesBuilder, ValidatorSet,
+    },
     state_store::{
-        state_key::StateKey, state_storage_usage::StateStorageUsage, state_value::StateValue,
+        errors::StateViewError,
+        state_key::{inner::StateKeyInner, StateKey},
+        state_storage_usage::StateStorageUsage,
+        state_value::StateValue,
         StateView, StateViewResult as StateStoreResult, TStateView,
     },
-    transaction::{ExecutionStatus, Script, TransactionArgument, TransactionStatus},
-    write_set::{TransactionWrite, WriteSet},
+    transaction::{
+        AbortInfo, EntryFunction, ExecutionStatus, Multisig, MultisigTransactionPayload, Script,
+        TransactionArgument, TransactionOutput, TransactionPayload, TransactionStatus,
+    },
+    write_set::{TransactionWrite, WriteOpKind, WriteSet},
+    AptosCoinType,
+};
+use aptos_vm::{
+    data_cache::AsMoveResolver, gas::make_prod_gas_meter, move_vm_ext::SessionId, AptosVM,
 };
-use aptos_vm::{data_cache::AsMoveResolver, move_vm_ext::SessionId, AptosVM};
 use aptos_vm_environment::{
     environment::AptosEnvironment, prod_configs::aptos_prod_deserializer_config,
 };
 use aptos_vm_logging::log_schema::AdapterLogSchema;
 use aptos_vm_types::{
     module_and_script_storage::AsAptosCodeStorage, module_write_set::ModuleWriteSet,
-    storage::change_set_configs::ChangeSetConfigs,
+    resolver::NoopBlockSynchronizationKillSwitch, storage::change_set_configs::ChangeSetConfigs,
 };
 use clap::Parser;
+use git2::{Repository, StatusOptions};
 use move_binary_format::{
-    access::ModuleAccess,
+    access::{ModuleAccess, ScriptAccess},
     deserializer::DeserializerConfig,
     file_format::{
-        AddressIdentifierIndex, Bytecode, FunctionDefinition, FunctionHandle, FunctionHandleIndex,
-        IdentifierIndex, ModuleHandle, ModuleHandleIndex, Signature, SignatureIndex,
-        SignatureToken, Visibility,
+        Ability, AbilitySet, AddressIdentifierIndex, Bytecode, Constant, ConstantPoolIndex,
+        FieldDefinition, FunctionDefinition, FunctionHandle, FunctionHandleIndex, IdentifierIndex,
+        ModuleHandle, ModuleHandleIndex, Signature, SignatureIndex, SignatureToken,
+        StructDefinition, StructDefinitionIndex, StructFieldInformation, StructHandle,
+        StructHandleIndex, TypeSignature, Visibility,
     },
-    CompiledModule,
+    CompiledModule, CompiledScript,
 };
 use move_core_types::{
+    ident_str,
     identifier::{IdentStr, Identifier},
-    language_storage::{ModuleId, StructTag},
-    move_resource::MoveResource,
-    value::MoveValue,
+    language_storage::{ModuleId, StructTag, TypeTag},
+    move_resource::{MoveResource, MoveStructType},
+    value::{MoveTypeLayout, MoveValue},
+    vm_status::{AbortLocation, StatusCode, VMStatus},
 };
+use move_model::metadata::{CompilerVersion, LanguageVersion};
+use move_package::source_package::manifest_parser::parse_move_manifest_from_file;
 use move_vm_runtime::module_traversal::{TraversalContext, TraversalStorage};
-use move_vm_types::gas::UnmeteredGasMeter;
+use move_vm_types::gas::{GasMeter, UnmeteredGasMeter};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    io::Write,
+    collections::{btree_map, BTreeMap, BTreeSet, HashMap},
+    ops::RangeInclusive,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
 };
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::{debug, error, info, warn};
 use url::Url;
 use walkdir::WalkDir;
 
@@ -99,29 +154,134 @@ fn find_function_def_by_name<'a>(
     None
 }
 
-fn get_or_add<T: PartialEq>(pool: &mut Vec<T>, val: T) -> usize {
-    match pool.iter().position(|elem| elem == &val) {
-        Some(idx) => idx,
-        None => {
-            let idx = pool.len();
-            pool.push(val);
-            idx
-        },
+/// Describes a function missing from a module that is about to be patched, listing the functions
+/// the module does define whose names contain the given hint, or all of them if none does. This
+/// points out functions that were renamed or not yet introduced in the framework at hand.
+fn describe_missing_function(m: &CompiledModule, func_name: &IdentStr, hint: &str) -> String {
+    let func_names: Vec<&str> = m
+        .function_defs
+        .iter()
+        .map(|func_def| {
+            m.identifier_at(m.function_handle_at(func_def.function).name)
+                .as_str()
+        })
+        .collect();
+    let candidates: Vec<&str> = func_names
+        .iter()
+        .copied()
+        .filter(|name| name.contains(hint))
+        .collect();
+    format!(
+        "failed to locate `fun {}` in module {}, which defines: {}. The simulator may be \
+         incompatible with this version of the framework",
+        func_name,
+        m.self_id(),
+        if candidates.is_empty() {
+            func_names.join(", ")
+        } else {
+            candidates.join(", ")
+        }
+    )
+}
+
+/// Maps the entries of a module pool to their indices, so that existing entries can be found
+/// without scanning the whole pool.
+///
+/// Entries appended to the pool by other means are indexed lazily on the next lookup.
+struct PoolIndex<T> {
+    indices: HashMap<T, usize>,
+    num_indexed: usize,
+}
+
+impl<T> Default for PoolIndex<T> {
+    fn default() -> Self {
+        Self {
+            indices: HashMap::new(),
+            num_indexed: 0,
+        }
+    }
+}
+
+impl<T: Clone + Eq + std::hash::Hash> PoolIndex<T> {
+    /// Returns the index of the first occurrence of the value in the pool, appending the value
+    /// if it does not occur yet.
+    fn get_or_add(&mut self, pool: &mut Vec<T>, val: T) -> usize {
+        if pool.len() < self.num_indexed {
+            // The pool has been truncated behind our back, so the index cannot be trusted.
+            *self = Self::default();
+        }
+        for (idx, elem) in pool.iter().enumerate().skip(self.num_indexed) {
+            self.indices.entry(elem.clone()).or_insert(idx);
+        }
+        self.num_indexed = pool.len();
+
+        match self.indices.get(&val) {
+            Some(idx) => *idx,
+            None => {
+                let idx = pool.len();
+                pool.push(val.clone());
+                self.indices.insert(val, idx);
+                self.num_indexed += 1;
+                idx
+            },
+        }
+    }
+}
+
+/// A module being patched, along with indices over the pools that patches frequently add
+/// entries to. Dereferences to the module itself.
+struct IndexedModule<'a> {
+    module: &'a mut CompiledModule,
+    address_identifier_index: PoolIndex<AccountAddress>,
+    identifier_index: PoolIndex<Identifier>,
+    module_handle_index: PoolIndex<ModuleHandle>,
+    signature_index: PoolIndex<Signature>,
+    constant_index: PoolIndex<Constant>,
+}
+
+impl<'a> IndexedModule<'a> {
+    fn new(module: &'a mut CompiledModule) -> Self {
+        Self {
+            module,
+            address_identifier_index: PoolIndex::default(),
+            identifier_index: PoolIndex::default(),
+            module_handle_index: PoolIndex::default(),
+            signature_index: PoolIndex::default(),
+            constant_index: PoolIndex::default(),
+        }
+    }
+}
+
+impl std::ops::Deref for IndexedModule<'_> {
+    type Target = CompiledModule;
+
+    fn deref(&self) -> &Self::Target {
+        self.module
+    }
+}
+
+impl std::ops::DerefMut for IndexedModule<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.module
     }
 }
 
-#[allow(dead_code)]
-fn get_or_add_addr(m: &mut CompiledModule, addr: AccountAddress) -> AddressIdentifierIndex {
-    AddressIdentifierIndex::new(get_or_add(&mut m.address_identifiers, addr) as u16)
+fn get_or_add_addr(m: &mut IndexedModule, addr: AccountAddress) -> AddressIdentifierIndex {
+    let idx = m
+        .address_identifier_index
+        .get_or_add(&mut m.module.address_identifiers, addr);
+    AddressIdentifierIndex::new(idx as u16)
 }
 
-fn get_or_add_ident(m: &mut CompiledModule, ident: Identifier) -> IdentifierIndex {
-    IdentifierIndex::new(get_or_add(&mut m.identifiers, ident) as u16)
+fn get_or_add_ident(m: &mut IndexedModule, ident: Identifier) -> IdentifierIndex {
+    let idx = m
+        .identifier_index
+        .get_or_add(&mut m.module.identifiers, ident);
+    IdentifierIndex::new(idx as u16)
 }
 
-#[allow(dead_code)]
 fn get_or_add_module_handle(
-    m: &mut CompiledModule,
+    m: &mut IndexedModule,
     addr: AccountAddress,
     name: Identifier,
 ) -> ModuleHandleIndex {
@@ -131,11 +291,24 @@ fn get_or_add_module_handle(
         address: addr,
         name,
     };
-    ModuleHandleIndex::new(get_or_add(&mut m.module_handles, module_handle) as u16)
+    let idx = m
+        .module_handle_index
+        .get_or_add(&mut m.module.module_handles, module_handle);
+    ModuleHandleIndex::new(idx as u16)
+}
+
+fn get_or_add_signature(m: &mut IndexedModule, sig: Vec<SignatureToken>) -> SignatureIndex {
+    let idx = m
+        .signature_index
+        .get_or_add(&mut m.module.signatures, Signature(sig));
+    SignatureIndex::new(idx as u16)
 }
 
-fn get_or_add_signature(m: &mut CompiledModule, sig: Vec<SignatureToken>) -> SignatureIndex {
-    SignatureIndex::new(get_or_add(&mut m.signatures, Signature(sig)) as u16)
+fn get_or_add_constant(m: &mut IndexedModule, constant: Constant) -> ConstantPoolIndex {
+    let idx = m
+        .constant_index
+        .get_or_add(&mut m.module.constant_pool, constant);
+    ConstantPoolIndex::new(idx as u16)
 }
 
 fn find_function_handle_by_name(
@@ -156,8 +329,11 @@ fn find_function_handle_by_name(
     None
 }
 
+/// Adds a private, non-generic native function with the given signature to the module, unless a
+/// function with the same name is already defined, in which case it must be native and its
+/// signature must match.
 fn add_simple_native_function(
-    m: &mut CompiledModule,
+    m: &mut IndexedModule,
     func_name: Identifier,
     params: Vec<SignatureToken>,
     returns: Vec<SignatureToken>,
@@ -165,6 +341,39 @@ fn add_simple_native_function(
     if let Some(func_handle_idx) =
         find_function_handle_by_name(m, *m.self_addr(), m.self_name(), &func_name)
     {
+        // Reusing a function with another signature would produce calls that fail verification,
+        // or worse, call something else than intended.
+        let func_handle = m.function_handle_at(func_handle_idx);
+        let existing_params = &m.signature_at(func_handle.parameters).0;
+        let existing_returns = &m.signature_at(func_handle.return_).0;
+        if !func_handle.type_parameters.is_empty()
+            || *existing_params != params
+            || *existing_returns != returns
+        {
+            bail!(
+                "`fun {}` in module {} is already defined with parameters {:?}, return types {:?} \
+                 and {} type parameter(s), expected parameters {:?} and return types {:?}",
+                func_name,
+                m.self_id(),
+                existing_params,
+                existing_returns,
+                func_handle.type_parameters.len(),
+                params,
+                returns
+            );
+        }
+        // Reusing a function with a body would call that body instead of the native.
+        let is_native = m
+            .function_defs()
+            .iter()
+            .any(|func_def| func_def.function == func_handle_idx && func_def.code.is_none());
+        if !is_native {
+            bail!(
+                "`fun {}` in module {} is already defined, but is not native",
+                func_name,
+                m.self_id()
+            );
+        }
         return Ok(func_handle_idx);
     }
 
@@ -194,111 +403,546 @@ fn add_simple_native_function(
 
     Ok(func_handle_idx)
 }
+
+/// Adds a non-generic struct with the given fields to the module, unless a struct with the same
+/// name is already defined.
+fn get_or_add_struct(
+    m: &mut IndexedModule,
+    struct_name: Identifier,
+    abilities: AbilitySet,
+    fields: Vec<(Identifier, SignatureToken)>,
+) -> StructDefinitionIndex {
+    for (idx, struct_def) in m.struct_defs().iter().enumerate() {
+        let struct_handle = m.struct_handle_at(struct_def.struct_handle);
+        if m.identifier_at(struct_handle.name) == struct_name.as_ident_str() {
+            return StructDefinitionIndex(idx as u16);
+        }
+    }
+
+    let name = get_or_add_ident(m, struct_name);
+    let struct_handle = StructHandle {
+        module: m.self_handle_idx(),
+        name,
+        abilities,
+        type_parameters: vec![],
+    };
+    let struct_handle_idx = StructHandleIndex(m.struct_handles.len() as u16);
+    m.struct_handles.push(struct_handle);
+
+    let fields = fields
+        .into_iter()
+        .map(|(field_name, field_type)| FieldDefinition {
+            name: get_or_add_ident(m, field_name),
+            signature: TypeSignature(field_type),
+        })
+        .collect();
+    let struct_def_idx = StructDefinitionIndex(m.struct_defs.len() as u16);
+    m.struct_defs.push(StructDefinition {
+        struct_handle: struct_handle_idx,
+        field_information: StructFieldInformation::Declared(fields),
+    });
+
+    struct_def_idx
+}
+
+/// Adds a handle to a function defined in another module, so that it can be called.
+fn get_or_add_imported_function_handle(
+    m: &mut IndexedModule,
+    module_id: &ModuleId,
+    func_name: Identifier,
+    params: Vec<SignatureToken>,
+    returns: Vec<SignatureToken>,
+) -> FunctionHandleIndex {
+    if let Some(func_handle_idx) =
+        find_function_handle_by_name(m, *module_id.address(), module_id.name(), &func_name)
+    {
+        return func_handle_idx;
+    }
+
+    let module = get_or_add_module_handle(m, *module_id.address(), module_id.name().to_owned());
+    let name = get_or_add_ident(m, func_name);
+    let parameters = get_or_add_signature(m, params);
+    let return_ = get_or_add_signature(m, returns);
+    let func_handle = FunctionHandle {
+        module,
+        name,
+        parameters,
+        return_,
+        type_parameters: vec![],
+        access_specifiers: None,
+        attributes: vec![],
+    };
+    let func_handle_idx = FunctionHandleIndex(m.function_handles.len() as u16);
+    m.function_handles.push(func_handle);
+
+    func_handle_idx
+}
 /***************************************************************************************************
- * Simulation State View
+ * State Snapshots
  *
  **************************************************************************************************/
-/// A state view specifically designed for managing the side effects generated by
-///  the governance scripts.
+/// Name of the file in a proposal directory the remote state read by the simulation is saved to.
+const STATE_SNAPSHOT_FILE_NAME: &str = "state_snapshot.bcs";
+
+/// The remote state values read while simulating a proposal, which allow the simulation to be
+/// repeated deterministically and without network access.
+#[derive(Deserialize, Serialize)]
+struct StateSnapshot {
+    version: u64,
+    states: Vec<(StateKey, Option<StateValue>)>,
+}
+
+impl StateSnapshot {
+    fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("failed to read state snapshot {}", path.display()))?;
+        bcs::from_bytes(&bytes)
+            .with_context(|| format!("failed to deserialize state snapshot {}", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, bcs::to_bytes(self)?)
+            .with_context(|| format!("failed to write state snapshot to {}", path.display()))
+    }
+}
+
+/// Remote state values fetched at a single version.
+type CachedStates = Mutex<HashMap<StateKey, Option<StateValue>>>;
+
+/// A read-through cache of the remote state values fetched at a single version.
 ///
-/// It comprises two components:
-/// - A remote debugger state view to enable on-demand data fetching.
-/// - A local state store to allow new changes to be stacked on top of the remote state.
-struct SimulationStateView<'a, S> {
-    remote: &'a S,
-    states: Mutex<HashMap<StateKey, Option<StateValue>>>,
+/// `simulate_all_proposals` creates one for the version all proposals of a release are simulated
+/// at, so that framework modules and configs are only fetched once. Since the state at a given
+/// version never changes, entries never go stale, and the cache is dropped with the options that
+/// own it.
+pub struct RemoteStateCache {
+    version: u64,
+    states: CachedStates,
+    num_hits: AtomicUsize,
+    num_misses: AtomicUsize,
 }
 
-impl<'a, S> SimulationStateView<'a, S>
-where
-    S: StateView,
-{
-    fn set_state_value(&self, state_key: StateKey, state_val: StateValue) {
-        self.states.lock().insert(state_key, Some(state_val));
+impl RemoteStateCache {
+    pub fn new(version: u64) -> Self {
+        Self {
+            version,
+            states: Mutex::new(HashMap::new()),
+            num_hits: AtomicUsize::new(0),
+            num_misses: AtomicUsize::new(0),
+        }
     }
 
-    fn set_on_chain_config<C>(&self, config: &C) -> Result<()>
-    where
-        C: OnChainConfig + Serialize,
-    {
-        let addr = AccountAddress::from_hex_literal(C::ADDRESS).unwrap();
+    /// Returns the number of cache hits and misses so far.
+    pub fn stats(&self) -> (usize, usize) {
+        (
+            self.num_hits.load(Ordering::Relaxed),
+            self.num_misses.load(Ordering::Relaxed),
+        )
+    }
+}
 
-        self.set_state_value(
-            StateKey::resource(&addr, &StructTag {
-                address: addr,
-                module: Identifier::new(C::MODULE_IDENTIFIER).unwrap(),
-                name: Identifier::new(C::TYPE_IDENTIFIER).unwrap(),
-                type_args: vec![],
-            })?,
-            StateValue::new_legacy(bcs::to_bytes(&config)?.into()),
-        );
+impl std::fmt::Debug for RemoteStateCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteStateCache")
+            .field("version", &self.version)
+            .field("num_states", &self.states.lock().len())
+            .finish_non_exhaustive()
+    }
+}
 
-        Ok(())
+/// Limits the number of remote state fetches that can be in flight at the same time, to avoid
+/// overwhelming a single REST endpoint. Clones share the same limit, e.g. across the proposals of
+/// a release simulated in parallel.
+#[derive(Clone, Debug)]
+pub struct RemoteFetchLimiter(Arc<Semaphore>);
+
+impl RemoteFetchLimiter {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self(Arc::new(Semaphore::new(max_in_flight.max(1))))
     }
 
-    fn modify_on_chain_config<C, F>(&self, modify: F) -> Result<()>
-    where
-        C: OnChainConfig + Serialize,
-        F: FnOnce(&mut C) -> Result<()>,
-    {
-        let mut config = C::fetch_config(self).ok_or_else(|| {
-            anyhow!(
-                "failed to fetch on-chain config: {:?}",
-                std::any::type_name::<C>()
-            )
-        })?;
+    /// Waits until a fetch may be started. State views are synchronous, so this blocks the
+    /// calling thread, like the fetch itself. The permit must only be held for the duration of a
+    /// single request, not across retries.
+    fn acquire(&self) -> SemaphorePermit<'_> {
+        futures::executor::block_on(self.0.acquire()).expect("the semaphore is never closed")
+    }
+}
 
-        modify(&mut config)?;
+/// Default timeout of a single request to the REST endpoint.
+pub const DEFAULT_REMOTE_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
 
-        self.set_on_chain_config(&config)?;
+/// Default number of times a failed request to the REST endpoint is retried.
+pub const DEFAULT_REMOTE_RETRIES: u32 = 3;
 
-        Ok(())
+/// How requests to the REST endpoint are retried after transient failures, e.g. on flaky public
+/// endpoints. The backoff doubles after every failed attempt, up to `max_backoff`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Number of retries after the initial attempt. Zero disables retrying.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_REMOTE_RETRIES,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
     }
+}
 
-    #[allow(dead_code)]
-    fn remove_state_value(&mut self, state_key: &StateKey) {
-        self.states.lock().remove(state_key);
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
     }
 
-    fn apply_write_set(&self, write_set: WriteSet) {
-        let mut states = self.states.lock();
+    /// Runs a blocking request, retrying it according to the policy.
+    fn retry<T, E: RequestError>(
+        &self,
+        what: &str,
+        mut request: impl FnMut() -> Result<T, E>,
+    ) -> Result<T, E> {
+        futures::executor::block_on(self.retry_with_sleep(
+            what,
+            || std::future::ready(request()),
+            |backoff| {
+                std::thread::sleep(backoff);
+                std::future::ready(())
+            },
+        ))
+    }
 
-        for (state_key, write_op) in write_set {
-            match write_op.as_state_value() {
-                None => {
-                    states.remove(&state_key);
-                },
-                Some(state_val) => {
-                    states.insert(state_key, Some(state_val));
+    /// Like [`Self::retry`], but for async requests.
+    async fn retry_async<T, E, F, Fut>(&self, what: &str, request: F) -> Result<T, E>
+    where
+        E: RequestError,
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, E>>,
+    {
+        self.retry_with_sleep(what, request, tokio::time::sleep)
+            .await
+    }
+
+    /// Runs a request, retrying it after transient failures, and waiting out the backoff with
+    /// the given function. Other failures are returned right away, as retrying cannot fix them.
+    async fn retry_with_sleep<T, E, F, Fut, S, SleepFut>(
+        &self,
+        what: &str,
+        mut request: F,
+        sleep: S,
+    ) -> Result<T, E>
+    where
+        E: RequestError,
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, E>>,
+        S: Fn(Duration) -> SleepFut,
+        SleepFut: std::future::Future<Output = ()>,
+    {
+        let mut attempt = 0;
+        loop {
+            match request().await {
+                Ok(res) => return Ok(res),
+                Err(err) if attempt < self.max_retries && err.is_transient() => {
+                    let backoff = self.backoff(attempt);
+                    warn!(
+                        "Warning: failed to {}, retrying in {:.1}s: {}",
+                        what,
+                        backoff.as_secs_f64(),
+                        err
+                    );
+                    sleep(backoff).await;
+                    attempt += 1;
                 },
+                Err(err) => return Err(err),
             }
         }
     }
+}
 
-    #[allow(dead_code)]
-    fn read_resource<T: MoveResource>(&self, addr: &AccountAddress) -> T {
-        let data_blob = self
-            .get_state_value_bytes(
-                &StateKey::resource_typed::<T>(addr).expect("failed to create StateKey"),
-            )
-            .expect("account must exist in data store")
-            .unwrap_or_else(|| panic!("Can't fetch {} resource for {}", T::STRUCT_NAME, addr));
+/// An error of a request to the REST endpoint, which tells whether retrying the request may
+/// succeed.
+trait RequestError: std::fmt::Display {
+    /// Returns true if the request timed out or the server failed, as opposed to e.g. the
+    /// request being invalid.
+    fn is_transient(&self) -> bool;
+}
 
-        bcs::from_bytes(&data_blob).expect("failed to deserialize resource")
+impl RequestError for RestError {
+    fn is_transient(&self) -> bool {
+        match self {
+            RestError::Api(response) => response.status_code.is_server_error(),
+            RestError::Http(status_code, _) => status_code.is_server_error(),
+            // Failures to connect, e.g. refused or reset connections, are not worth failing the
+            // simulation over either.
+            RestError::Unknown(err) => err
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|err| err.is_timeout() || err.is_connect()),
+            _ => false,
+        }
     }
 }
 
-impl<'a, S> TStateView for SimulationStateView<'a, S>
-where
-    S: StateView,
-{
+/// Fetches state values from the REST endpoint for the synchronous state view, keeping the typed
+/// errors of the REST client so that transient failures can be retried.
+struct RestStateFetcher {
+    client: Client,
+    runtime: tokio::runtime::Handle,
+}
+
+impl RestStateFetcher {
+    /// Creates a fetcher that runs its requests on the current runtime.
+    fn new(client: Client) -> Self {
+        Self {
+            client,
+            runtime: tokio::runtime::Handle::current(),
+        }
+    }
+
+    /// Fetches the state value right before the transaction at the given version, like the state
+    /// view of the debugger does.
+    fn fetch(&self, state_key: &StateKey, version: u64) -> Result<Option<StateValue>, RestError> {
+        let version = version
+            .checked_sub(1)
+            .ok_or_else(|| RestError::Unknown(anyhow!("cannot fetch state before genesis")))?;
+
+        // Run the request on the runtime and block on its outcome, which works regardless of
+        // whether the calling thread belongs to the runtime.
+        let client = self.client.clone();
+        let owned_state_key = state_key.clone();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.runtime.spawn(async move {
+            let res = client.get_raw_state_value(&owned_state_key, version).await;
+            let _ = sender.send(res);
+        });
+        let res = receiver
+            .recv()
+            .map_err(|err| RestError::Unknown(err.into()))?;
+
+        match res {
+            Ok(response) => Ok(Some(bcs::from_bytes(&response.into_inner())?)),
+            Err(RestError::Api(AptosErrorResponse {
+                error:
+                    AptosError {
+                        error_code:
+                            AptosErrorCode::StateValueNotFound | AptosErrorCode::TableItemNotFound,
+                        ..
+                    },
+                ..
+            })) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// A genesis state built locally, to simulate proposals on without any network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum GenesisConfig {
+    /// Genesis with the framework compiled from head.
+    Head,
+    /// Genesis with the framework as released to testnet.
+    Testnet,
+    /// Genesis with the framework as released to mainnet.
+    Mainnet,
+}
+
+impl GenesisConfig {
+    /// Builds the genesis state, including the chain id of the corresponding network.
+    fn build_state(self) -> FakeDataStore {
+        let (change_set, chain_id) = match self {
+            Self::Head => (&*GENESIS_CHANGE_SET_HEAD, ChainId::test()),
+            Self::Testnet => (&*GENESIS_CHANGE_SET_TESTNET, ChainId::testnet()),
+            Self::Mainnet => (&*GENESIS_CHANGE_SET_MAINNET, ChainId::mainnet()),
+        };
+        let mut data_store = FakeDataStore::default();
+        data_store.set_chain_id(chain_id);
+        data_store.add_write_set(change_set.write_set());
+        data_store
+    }
+}
+
+/// The state a simulation is based on, either fetched from a REST endpoint, loaded from a state
+/// snapshot or built locally from genesis.
+pub enum RemoteStateView {
+    /// A live remote state. If `recorded` is set, every state value read is kept, so that it can
+    /// be saved as a state snapshot.
+    Live {
+        fetcher: RestStateFetcher,
+        version: u64,
+        cache: Arc<RemoteStateCache>,
+        recorded: Option<CachedStates>,
+        retry_policy: RetryPolicy,
+        fetch_limiter: Option<RemoteFetchLimiter>,
+    },
+    /// A state snapshot saved by an earlier simulation. Reading a state value the snapshot does
+    /// not contain is an error, as it cannot be told apart from a non-existent one.
+    Snapshot {
+        version: u64,
+        states: HashMap<StateKey, Option<StateValue>>,
+    },
+    /// A genesis state built locally. State values it does not contain do not exist.
+    Genesis(FakeDataStore),
+}
+
+impl RemoteStateView {
+    /// Returns the version of the remote state, or `None` for a local genesis.
+    pub fn version(&self) -> Option<u64> {
+        match self {
+            Self::Live { version, .. } | Self::Snapshot { version, .. } => Some(*version),
+            Self::Genesis(_) => None,
+        }
+    }
+
+    /// Returns the state values read so far, or `None` if they are not being recorded.
+    fn recorded_snapshot(&self) -> Option<StateSnapshot> {
+        match self {
+            Self::Live {
+                version,
+                recorded: Some(recorded),
+                ..
+            } => Some(StateSnapshot {
+                version: *version,
+                states: recorded
+                    .lock()
+                    .iter()
+                    .map(|(state_key, state_val)| (state_key.clone(), state_val.clone()))
+                    .collect(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Fetches the given state keys concurrently and stores them in the remote state cache, so
+    /// that later reads of them are local. Keys that are already cached are skipped.
+    ///
+    /// The values go into the cache rather than into the overlay of the simulation state view, so
+    /// that they are not mistaken for changes made by the simulation.
+    ///
+    /// Returns the number of state values fetched.
+    fn prefetch(&self, state_keys: &[StateKey]) -> Result<usize> {
+        let Self::Live {
+            fetcher,
+            version,
+            cache,
+            recorded,
+            retry_policy,
+            fetch_limiter,
+        } = self
+        else {
+            return Ok(0);
+        };
+
+        let missing: Vec<&StateKey> = {
+            let cache = cache.states.lock();
+            state_keys
+                .iter()
+                .filter(|state_key| !cache.contains_key(*state_key))
+                .collect()
+        };
+        let fetched = std::thread::scope(|s| {
+            let handles: Vec<_> = missing
+                .iter()
+                .map(|state_key| {
+                    s.spawn(move || {
+                        retry_policy.retry("prefetch remote state value", || {
+                            let _permit = fetch_limiter
+                                .as_ref()
+                                .map(|fetch_limiter| fetch_limiter.acquire());
+                            fetcher.fetch(state_key, *version)
+                        })
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .zip(&missing)
+                .map(|(handle, state_key)| {
+                    let state_val = handle
+                        .join()
+                        .map_err(|_| anyhow!("prefetch thread panicked"))?
+                        .with_context(|| format!("failed to prefetch {:?}", state_key))?;
+                    Ok(((*state_key).clone(), state_val))
+                })
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        cache.num_misses.fetch_add(fetched.len(), Ordering::Relaxed);
+        let mut states = cache.states.lock();
+        for (state_key, state_val) in &fetched {
+            states.insert(state_key.clone(), state_val.clone());
+            if let Some(recorded) = recorded {
+                recorded.lock().insert(state_key.clone(), state_val.clone());
+            }
+        }
+        Ok(fetched.len())
+    }
+}
+
+/// Returns the state keys every simulation reads before executing the first script: the
+/// governance module that gets patched, and the on-chain configs used to set up execution.
+fn prefetch_state_keys(patch_plan: &PatchPlan) -> Result<Vec<StateKey>> {
+    Ok(vec![
+        StateKey::module_id(&patch_plan.governance_module),
+        StateKey::on_chain_config::<GasScheduleV2>()?,
+        StateKey::on_chain_config::<Features>()?,
+        StateKey::on_chain_config::<ChainIdResource>()?,
+        StateKey::on_chain_config::<ApprovedExecutionHashes>()?,
+    ])
+}
+
+impl TStateView for RemoteStateView {
     type Key = StateKey;
 
     fn get_state_value(&self, state_key: &Self::Key) -> StateStoreResult<Option<StateValue>> {
-        if let Some(res) = self.states.lock().get(state_key) {
-            return Ok(res.clone());
+        match self {
+            Self::Live {
+                fetcher,
+                version,
+                cache,
+                recorded,
+                retry_policy,
+                fetch_limiter,
+            } => {
+                let cached_val = cache.states.lock().get(state_key).cloned();
+                let state_val = match cached_val {
+                    Some(state_val) => {
+                        cache.num_hits.fetch_add(1, Ordering::Relaxed);
+                        state_val
+                    },
+                    None => {
+                        cache.num_misses.fetch_add(1, Ordering::Relaxed);
+                        debug!("Fetching remote state value {:?}", state_key);
+                        // Only throttle the actual requests, not cache hits or backoffs.
+                        let state_val = retry_policy
+                            .retry("fetch remote state value", || {
+                                let _permit = fetch_limiter
+                                    .as_ref()
+                                    .map(|fetch_limiter| fetch_limiter.acquire());
+                                fetcher.fetch(state_key, *version)
+                            })
+                            .map_err(|err| StateViewError::Other(format!("{:#}", err)))?;
+                        cache
+                            .states
+                            .lock()
+                            .insert(state_key.clone(), state_val.clone());
+                        state_val
+                    },
+                };
+                if let Some(recorded) = recorded {
+                    recorded.lock().insert(state_key.clone(), state_val.clone());
+                }
+                Ok(state_val)
+            },
+            Self::Snapshot { states, .. } => states.get(state_key).cloned().ok_or_else(|| {
+                StateViewError::NotFound(format!("{:?} in the state snapshot", state_key))
+            }),
+            Self::Genesis(data_store) => data_store.get_state_value(state_key),
         }
-        self.remote.get_state_value(state_key)
     }
 
     fn get_usage(&self) -> StateStoreResult<StateStorageUsage> {
@@ -323,9 +967,39 @@ static FUNC_NAME_CREATE_SIGNER: Lazy<Identifier> =
 static FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL: Lazy<Identifier> =
     Lazy::new(|| Identifier::new("resolve_multi_step_proposal").unwrap());
 
-const DUMMY_PROPOSAL_ID: u64 = u64::MAX;
+static FUNC_NAME_RESOLVE: Lazy<Identifier> = Lazy::new(|| Identifier::new("resolve").unwrap());
+
+static STRUCT_NAME_RESOLVE_ARGS: Lazy<Identifier> =
+    Lazy::new(|| Identifier::new("SimulatedResolveArgs").unwrap());
+
+/// Default proposal id passed to the scripts. The patched resolve function ignores it, as the
+/// simulated proposals are never actually created.
+pub const DEFAULT_DUMMY_PROPOSAL_ID: u64 = u64::MAX;
+
+/// Abort code of the patched resolve function if the last step of a proposal sets a next
+/// execution hash.
+///
+/// Framework abort codes are built with `std::error`, which only uses the lower 24 bits, so the
+/// high bits are set to avoid collisions. On top of that, an abort is only attributed to the
+/// patch if it originates from the patched governance module, see
+/// [`is_next_execution_hash_abort`].
+const MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK: u64 = 0x5349_4D55_DEAD_BEEF;
 
-const MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK: u64 = 0xDEADBEEF;
+/// Returns true if the status is the abort injected into the resolve function to catch a next
+/// execution hash in the last step of a proposal.
+///
+/// Scripts may abort with arbitrary codes, so both the code and the location need to match.
+fn is_next_execution_hash_abort(status: &ExecutionStatus, patch_plan: &PatchPlan) -> bool {
+    matches!(
+        status,
+        ExecutionStatus::MoveAbort {
+            location: AbortLocation::Module(module_id),
+            code,
+            ..
+        } if *code == MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK
+            && *module_id == patch_plan.governance_module
+    )
+}
 
 /// Helper to load a module from the state view, deserialize it, modify it with
 /// the provided callback, reserialize it and finally write it back.
@@ -334,440 +1008,8036 @@ fn patch_module<F>(
     deserializer_config: &DeserializerConfig,
     module_id: &ModuleId,
     modify_module: F,
+    dump_dir: Option<&Path>,
 ) -> Result<()>
 where
-    F: FnOnce(&mut CompiledModule) -> Result<()>,
+    F: FnOnce(&mut IndexedModule) -> Result<()>,
 {
-    let blob = state_view
-        .get_state_value_bytes(&StateKey::module_id(module_id))?
-        .ok_or_else(|| anyhow!("module {} does not exist", module_id))?;
+    patch_modules(
+        state_view,
+        deserializer_config,
+        vec![(module_id.clone(), Box::new(modify_module))],
+        dump_dir,
+    )
+}
 
-    let mut m = CompiledModule::deserialize_with_config(&blob, deserializer_config)?;
+/// Calls the given function and then discards the local change made to the module in the meantime,
+/// so that a patch applied by the function only lasts for the duration of the call.
+fn with_temporary_patch<T>(
+    state_view: &SimulationStateView<impl StateView>,
+    module_id: &ModuleId,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let state_key = StateKey::module_id(module_id);
+    let unpatched_module = state_view.states.lock().get(&state_key).cloned();
+    let res = f();
+    match unpatched_module {
+        Some(state_value) => {
+            state_view.states.lock().insert(state_key, state_value);
+        },
+        None => state_view.remove_state_value(&state_key),
+    }
+    res
+}
 
-    modify_module(&mut m)?;
+/// A modification of a single module, see [`patch_modules`].
+type ModulePatch<'a> = Box<dyn FnOnce(&mut IndexedModule) -> Result<()> + 'a>;
 
-    // Sanity check to ensure the correctness of the check
-    move_bytecode_verifier::verify_module(&m).map_err(|err| {
-        anyhow!(
-            "patched module failed to verify -- check if the patch is correct: {}",
-            err
-        )
-    })?;
+/// Applies a batch of patches, in order, to the modules they are paired with.
+///
+/// Each module is loaded and deserialized once, even if it is patched multiple times, and is
+/// only verified once all patches have been applied. Nothing is written back unless all patched
+/// modules pass verification.
+///
+/// If a dump directory is given, the patched modules are written to it before verification, see
+/// [`dump_patched_module`].
+fn patch_modules(
+    state_view: &SimulationStateView<impl StateView>,
+    deserializer_config: &DeserializerConfig,
+    patches: Vec<(ModuleId, ModulePatch)>,
+    dump_dir: Option<&Path>,
+) -> Result<()> {
+    let mut modules: BTreeMap<ModuleId, CompiledModule> = BTreeMap::new();
 
-    let mut blob = vec![];
-    m.serialize(&mut blob)?;
+    for (module_id, modify_module) in patches {
+        let m = match modules.entry(module_id) {
+            btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            btree_map::Entry::Vacant(entry) => {
+                let module_id = entry.key();
+                let blob = state_view
+                    .get_state_value_bytes(&StateKey::module_id(module_id))?
+                    .ok_or_else(|| anyhow!("module {} does not exist on chain", module_id))?;
+                entry.insert(CompiledModule::deserialize_with_config(
+                    &blob,
+                    deserializer_config,
+                )?)
+            },
+        };
+        modify_module(&mut IndexedModule::new(m))?;
+    }
 
-    state_view.set_state_value(
-        StateKey::module_id(module_id),
-        StateValue::new_legacy(blob.into()),
-    );
+    let mut blobs = vec![];
+    for (module_id, m) in &modules {
+        if let Some(dump_dir) = dump_dir {
+            dump_patched_module(dump_dir, module_id, m)?;
+        }
+
+        // Sanity check to ensure the correctness of the check
+        move_bytecode_verifier::verify_module(m).map_err(|err| {
+            anyhow!(
+                "patched module {} failed to verify -- check if the patch is correct: {}",
+                module_id,
+                err
+            )
+        })?;
+
+        let mut blob = vec![];
+        m.serialize(&mut blob)?;
+        blobs.push((StateKey::module_id(module_id), blob));
+    }
+
+    for (state_key, blob) in blobs {
+        state_view.set_state_value(state_key, StateValue::new_legacy(blob.into()));
+    }
 
     Ok(())
 }
 
-/// Patches `aptos_framework::aptos_governance::resolve_multi_step_proposal` so that
-/// it returns the requested signer directly, skipping the governance process altogether.
-fn patch_aptos_governance(
-    state_view: &SimulationStateView<impl StateView>,
-    deserializer_config: &DeserializerConfig,
-    forbid_next_execution_hash: bool,
-) -> Result<()> {
-    use Bytecode::*;
+/// Writes a patched module to `<address>.<name>.mv` in the given directory, e.g. to inspect a
+/// patch that fails verification with `aptos move disassemble --bytecode-path <file>`, along with
+/// its debug representation to `<address>.<name>.txt`.
+fn dump_patched_module(dump_dir: &Path, module_id: &ModuleId, m: &CompiledModule) -> Result<()> {
+    std::fs::create_dir_all(dump_dir)
+        .with_context(|| format!("failed to create directory {}", dump_dir.display()))?;
+    let file_stem = format!(
+        "{}.{}",
+        module_id.address().short_str_lossless(),
+        module_id.name()
+    );
 
-    patch_module(
-        state_view,
-        deserializer_config,
-        &MODULE_ID_APTOS_GOVERNANCE,
-        |m| {
-            // Inject `native fun create_signer`.
-            let create_signer_handle_idx = add_simple_native_function(
-                m,
-                FUNC_NAME_CREATE_SIGNER.clone(),
-                vec![SignatureToken::Address],
-                vec![SignatureToken::Signer],
-            )?;
+    let mut blob = vec![];
+    m.serialize(&mut blob)?;
+    let bytecode_path = dump_dir.join(format!("{}.mv", file_stem));
+    std::fs::write(&bytecode_path, blob)
+        .with_context(|| format!("failed to write {}", bytecode_path.display()))?;
 
-            // Patch `fun resolve_multi_step_proposal`.
-            let sig_u8_idx = get_or_add_signature(m, vec![SignatureToken::U8]);
+    let debug_path = dump_dir.join(format!("{}.txt", file_stem));
+    std::fs::write(&debug_path, format!("{:#?}", m))
+        .with_context(|| format!("failed to write {}", debug_path.display()))?;
+    Ok(())
+}
 
-            let func_def = find_function_def_by_name(m, &FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL)
-                .ok_or_else(|| {
-                    anyhow!(
-                        "failed to locate `fun {}`",
-                        &*FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL
-                    )
-                })?;
-            func_def.acquires_global_resources = vec![];
-            let code = func_def.code.as_mut().ok_or_else(|| {
-                anyhow!(
-                    "`fun {}` must have a Move-defined body",
-                    &*FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL
-                )
-            })?;
+/// Describes which governance function gets patched to hand out the requested signer directly.
+///
+/// By default, this is `0x1::aptos_governance::resolve_multi_step_proposal`, but it can be
+/// pointed at an experimental governance module, e.g. on a custom deployment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchPlan {
+    /// The module defining the function to patch.
+    pub governance_module: ModuleId,
+    /// The function to patch. It must take the proposal id and the signer address as its first
+    /// two parameters, followed by the next execution hash.
+    pub resolve_function: Identifier,
+    /// Set this flag to make the patched function store the arguments it received, so that they
+    /// can be reported after each step.
+    pub record_resolve_args: bool,
+}
 
-            code.code.clear();
-            if forbid_next_execution_hash {
-                // If it is needed to forbid a next execution hash, inject additional Move
-                // code at the beginning that aborts with a magic number if the vector
-                // representing the hash is not empty.
-                //
-                //     if (!vector::is_empty(&next_execution_hash)) {
-                //         abort MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK;
-                //     }
-                //
-                // The magic number can later be checked in Rust to determine if such violation
-                // has happened.
-                code.code.extend([
-                    ImmBorrowLoc(2),
-                    VecLen(sig_u8_idx),
-                    LdU64(0),
-                    Eq,
-                    BrTrue(7),
-                    LdU64(MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK),
-                    Abort,
-                ]);
-            }
-            // Replace the original logic with `create_signer(signer_address)`, bypassing
-            // the governance process.
-            code.code
-                .extend([MoveLoc(1), Call(create_signer_handle_idx), Ret]);
-
-            Ok(())
-        },
-    )
+impl Default for PatchPlan {
+    fn default() -> Self {
+        Self {
+            governance_module: MODULE_ID_APTOS_GOVERNANCE.clone(),
+            resolve_function: FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL.clone(),
+            record_resolve_args: false,
+        }
+    }
 }
 
-// Add the hash of the script to the list of approved hashes, so to enable the
-// alternative (higher) execution limits.
+/// The arguments received by the patched resolve function, as recorded in the
+/// `SimulatedResolveArgs` resource injected into the governance module.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolveArgs {
+    pub proposal_id: u64,
+    pub signer_address: AccountAddress,
+}
+
+/// Looks up the resolve arguments recorded by the patched governance module in a write set.
+fn find_recorded_resolve_args(
+    governance_module: &ModuleId,
+    write_set: &WriteSet,
+) -> Result<Option<ResolveArgs>> {
+    let struct_tag = StructTag {
+        address: *governance_module.address(),
+        module: governance_module.name().to_owned(),
+        name: STRUCT_NAME_RESOLVE_ARGS.clone(),
+        type_args: vec![],
+    };
+
+    for (state_key, write_op) in write_set.iter() {
+        if let StateKeyInner::AccessPath(path) = state_key.inner() {
+            if matches!(path.get_path(), access_path::Path::Resource(tag) if tag == struct_tag) {
+                if let Some(state_value) = write_op.as_state_value() {
+                    let args = bcs::from_bytes(state_value.bytes())
+                        .context("failed to deserialize the recorded resolve arguments")?;
+                    return Ok(Some(args));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Replaces the body of the resolve function so that it returns `create_signer(signer_address)`,
+/// skipping the governance process altogether.
+///
+/// If `record_args` is set, the received proposal id and signer address are additionally stored
+/// in a `SimulatedResolveArgs` resource under the signer address.
+fn patch_resolve_function(
+    m: &mut IndexedModule,
+    func_name: &IdentStr,
+    create_signer_handle_idx: FunctionHandleIndex,
+    forbid_next_execution_hash: bool,
+    record_args: bool,
+) -> Result<()> {
+    use Bytecode::*;
+
+    let sig_u8_idx = get_or_add_signature(m, vec![SignatureToken::U8]);
+    let recording = if record_args {
+        let resolve_args_def_idx = get_or_add_struct(
+            m,
+            STRUCT_NAME_RESOLVE_ARGS.clone(),
+            AbilitySet::EMPTY | Ability::Key | Ability::Drop,
+            vec![
+                (Identifier::new("proposal_id")?, SignatureToken::U64),
+                (Identifier::new("signer_address")?, SignatureToken::Address),
+            ],
+        );
+        let sig_signer_idx = get_or_add_signature(m, vec![SignatureToken::Signer]);
+        Some((resolve_args_def_idx, sig_signer_idx))
+    } else {
+        None
+    };
+
+    let module_id = m.self_id();
+
+    // The resolve function has been renamed before, so help telling framework drift apart from a
+    // misconfigured patch.
+    let Some(func_def) = find_function_def_by_name(m, func_name) else {
+        bail!(
+            "{}, or use `--resolve-function` to patch a renamed function",
+            describe_missing_function(m, func_name, "resolve")
+        );
+    };
+    let func_handle_idx = func_def.function;
+
+    // Make sure the parameters are laid out as expected by the patch.
+    let func_handle = m.function_handle_at(func_handle_idx);
+    let params = &m.signature_at(func_handle.parameters).0;
+    let mut expected_params = vec![SignatureToken::U64, SignatureToken::Address];
+    if forbid_next_execution_hash {
+        expected_params.push(SignatureToken::Vector(Box::new(SignatureToken::U8)));
+    }
+    if !params.starts_with(&expected_params) {
+        bail!(
+            "`fun {}` in module {} has unexpected parameters {:?}, expected them to start with {:?}",
+            func_name,
+            module_id,
+            params,
+            expected_params
+        );
+    }
+
+    // The patched body leaves exactly one signer on the stack when returning, so the function
+    // must be declared to return exactly that. Otherwise the patched module would not match
+    // what callers expect.
+    let returns = &m.signature_at(func_handle.return_).0;
+    if returns.as_slice() != [SignatureToken::Signer] {
+        bail!(
+            "`fun {}` in module {} has unexpected return types {:?}, expected [Signer]",
+            func_name,
+            module_id,
+            returns
+        );
+    }
+
+    let num_params = params.len() as u8;
+
+    let func_def = find_function_def_by_name(m, func_name)
+        .expect("function definition must exist, as it has been located before");
+    // Note: functions of the same module calling the resolve function would need to acquire
+    // the recording resource as well, but the deployed governance modules have none.
+    func_def.acquires_global_resources = recording
+        .map(|(resolve_args_def_idx, _)| resolve_args_def_idx)
+        .into_iter()
+        .collect();
+    let code = func_def
+        .code
+        .as_mut()
+        .ok_or_else(|| anyhow!("`fun {}` must have a Move-defined body", func_name))?;
+
+    code.code.clear();
+    if forbid_next_execution_hash {
+        // If it is needed to forbid a next execution hash, inject additional Move
+        // code at the beginning that aborts with a magic number if the vector
+        // representing the hash is not empty.
+        //
+        //     if (!vector::is_empty(&next_execution_hash)) {
+        //         abort MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK;
+        //     }
+        //
+        // The magic number can later be checked in Rust to determine if such violation
+        // has happened.
+        code.code.extend([
+            ImmBorrowLoc(2),
+            VecLen(sig_u8_idx),
+            LdU64(0),
+            Eq,
+            BrTrue(7),
+            LdU64(MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK),
+            Abort,
+        ]);
+    }
+    if let Some((resolve_args_def_idx, sig_signer_idx)) = recording {
+        // Record the received arguments before returning the signer, which is held in the only
+        // non-parameter local.
+        //
+        //     let signer = create_signer(signer_address);
+        //     if (exists<SimulatedResolveArgs>(signer_address)) {
+        //         move_from<SimulatedResolveArgs>(signer_address);
+        //     };
+        //     move_to(&signer, SimulatedResolveArgs { proposal_id, signer_address });
+        //     signer
+        //
+        let signer_local = num_params;
+        let offset = code.code.len() as u16;
+        code.locals = sig_signer_idx;
+        code.code.extend([
+            CopyLoc(1),
+            Call(create_signer_handle_idx),
+            StLoc(signer_local),
+            CopyLoc(1),
+            Exists(resolve_args_def_idx),
+            BrFalse(offset + 9),
+            CopyLoc(1),
+            MoveFrom(resolve_args_def_idx),
+            Pop,
+            ImmBorrowLoc(signer_local),
+            CopyLoc(0),
+            CopyLoc(1),
+            Pack(resolve_args_def_idx),
+            MoveTo(resolve_args_def_idx),
+            MoveLoc(signer_local),
+            Ret,
+        ]);
+    } else {
+        // Replace the original logic with `create_signer(signer_address)`, bypassing
+        // the governance process.
+        code.code
+            .extend([MoveLoc(1), Call(create_signer_handle_idx), Ret]);
+    }
+
+    Ok(())
+}
+
+/// Returns the layout of the given type if constants can have it, i.e. if it is a primitive type
+/// other than `signer`, or a vector of such.
+fn constant_layout(token: &SignatureToken) -> Option<MoveTypeLayout> {
+    Some(match token {
+        SignatureToken::Bool => MoveTypeLayout::Bool,
+        SignatureToken::U8 => MoveTypeLayout::U8,
+        SignatureToken::U16 => MoveTypeLayout::U16,
+        SignatureToken::U32 => MoveTypeLayout::U32,
+        SignatureToken::U64 => MoveTypeLayout::U64,
+        SignatureToken::U128 => MoveTypeLayout::U128,
+        SignatureToken::U256 => MoveTypeLayout::U256,
+        SignatureToken::Address => MoveTypeLayout::Address,
+        SignatureToken::Vector(elem) => MoveTypeLayout::Vector(Box::new(constant_layout(elem)?)),
+        _ => return None,
+    })
+}
+
+/// Replaces the body of a function so that it returns the given constant, e.g. to make a
+/// permission check always pass.
+///
+/// The function must return a single value of the constant's type. The parameters are simply
+/// dropped, so functions taking values without the `drop` ability cannot be patched this way.
+fn patch_function_to_return(
+    m: &mut IndexedModule,
+    func_name: &IdentStr,
+    value: MoveValue,
+) -> Result<()> {
+    let module_id = m.self_id();
+    let func_handle_idx = find_function_def_by_name(m, func_name)
+        .ok_or_else(|| {
+            anyhow!(
+                "failed to locate `fun {}` in module {}",
+                func_name,
+                module_id
+            )
+        })?
+        .function;
+
+    let func_handle = m.function_handle_at(func_handle_idx);
+    let returns = m.signature_at(func_handle.return_).0.clone();
+    let [return_type] = returns.as_slice() else {
+        bail!(
+            "`fun {}` in module {} has return types {:?}, expected a single one",
+            func_name,
+            module_id,
+            returns
+        );
+    };
+    let layout = constant_layout(return_type).ok_or_else(|| {
+        anyhow!(
+            "`fun {}` in module {} returns {:?}, which cannot be a constant",
+            func_name,
+            module_id,
+            return_type
+        )
+    })?;
+
+    // The value matches the return type if it survives a round trip through its layout.
+    let data = value
+        .simple_serialize()
+        .ok_or_else(|| anyhow!("failed to serialize {:?}", value))?;
+    if MoveValue::simple_deserialize(&data, &layout).ok().as_ref() != Some(&value) {
+        bail!(
+            "cannot return {:?} from `fun {}` in module {}, which returns {:?}",
+            value,
+            func_name,
+            module_id,
+            return_type
+        );
+    }
+
+    let const_idx = get_or_add_constant(m, Constant {
+        type_: return_type.clone(),
+        data,
+    });
+    let locals = get_or_add_signature(m, vec![]);
+
+    let func_def = find_function_def_by_name(m, func_name)
+        .expect("function definition must exist, as it has been located before");
+    func_def.acquires_global_resources.clear();
+    let code = func_def
+        .code
+        .as_mut()
+        .ok_or_else(|| anyhow!("`fun {}` must have a Move-defined body", func_name))?;
+    code.locals = locals;
+    code.code = vec![Bytecode::LdConst(const_idx), Bytecode::Ret];
+
+    Ok(())
+}
+
+/// A function whose body gets replaced so that it returns a constant.
+#[derive(Clone, Debug)]
+pub struct FunctionStub {
+    pub module_id: ModuleId,
+    pub function: Identifier,
+    pub return_value: MoveValue,
+}
+
+/// Stubs out the given functions, patching each affected module once.
+fn patch_function_stubs(
+    state_view: &SimulationStateView<impl StateView>,
+    deserializer_config: &DeserializerConfig,
+    stubs: &[FunctionStub],
+) -> Result<()> {
+    if stubs.is_empty() {
+        return Ok(());
+    }
+    let patches = stubs
+        .iter()
+        .map(|stub| {
+            let patch = Box::new(|m: &mut IndexedModule| {
+                patch_function_to_return(m, &stub.function, stub.return_value.clone())
+            }) as ModulePatch;
+            (stub.module_id.clone(), patch)
+        })
+        .collect();
+    patch_modules(state_view, deserializer_config, patches, None)
+}
+
+/// Patches the resolve function described by the patch plan (by default,
+/// `aptos_framework::aptos_governance::resolve_multi_step_proposal`) so that it returns the
+/// requested signer directly, skipping the governance process altogether.
+///
+/// For single-step proposals, `resolve` of the governance module is patched instead. Since
+/// single-step proposals do not chain, there is no next execution hash to forbid.
+fn patch_aptos_governance(
+    state_view: &SimulationStateView<impl StateView>,
+    deserializer_config: &DeserializerConfig,
+    patch_plan: &PatchPlan,
+    single_step: bool,
+    forbid_next_execution_hash: bool,
+    dump_dir: Option<&Path>,
+) -> Result<()> {
+    let (resolve_function, forbid_next_execution_hash) = if single_step {
+        (&*FUNC_NAME_RESOLVE, false)
+    } else {
+        (&patch_plan.resolve_function, forbid_next_execution_hash)
+    };
+
+    let create_signer_params = vec![SignatureToken::Address];
+    let create_signer_returns = vec![SignatureToken::Signer];
+
+    if patch_plan.governance_module == *MODULE_ID_APTOS_GOVERNANCE {
+        return patch_module(
+            state_view,
+            deserializer_config,
+            &MODULE_ID_APTOS_GOVERNANCE,
+            |m| {
+                // Inject `native fun create_signer`.
+                let create_signer_handle_idx = add_simple_native_function(
+                    m,
+                    FUNC_NAME_CREATE_SIGNER.clone(),
+                    create_signer_params,
+                    create_signer_returns,
+                )?;
+
+                patch_resolve_function(
+                    m,
+                    resolve_function,
+                    create_signer_handle_idx,
+                    forbid_next_execution_hash,
+                    patch_plan.record_resolve_args,
+                )
+            },
+            dump_dir,
+        );
+    }
+
+    // The VM only provides the `create_signer` native for `0x1::aptos_governance`, so inject it
+    // there and call it from the experimental governance module. It is only exposed to that
+    // module, as a friend, so that the proposal cannot obtain signers through other code paths.
+    // Friends must be published at the same address.
+    if patch_plan.governance_module.address() != MODULE_ID_APTOS_GOVERNANCE.address() {
+        bail!(
+            "governance module {} must be published at {} to be patched",
+            patch_plan.governance_module,
+            MODULE_ID_APTOS_GOVERNANCE.address().short_str_lossless()
+        );
+    }
+    let patches = vec![
+        (
+            MODULE_ID_APTOS_GOVERNANCE.clone(),
+            Box::new(|m: &mut IndexedModule| {
+                add_friend_create_signer(m, &patch_plan.governance_module)
+            }) as ModulePatch,
+        ),
+        (
+            patch_plan.governance_module.clone(),
+            Box::new(|m: &mut IndexedModule| {
+                let create_signer_handle_idx = get_or_add_imported_function_handle(
+                    m,
+                    &MODULE_ID_APTOS_GOVERNANCE,
+                    FUNC_NAME_CREATE_SIGNER.clone(),
+                    create_signer_params,
+                    create_signer_returns,
+                );
+
+                patch_resolve_function(
+                    m,
+                    resolve_function,
+                    create_signer_handle_idx,
+                    forbid_next_execution_hash,
+                    patch_plan.record_resolve_args,
+                )
+            }) as ModulePatch,
+        ),
+    ];
+    patch_modules(state_view, deserializer_config, patches, dump_dir)
+}
+
+/// Injects `native fun create_signer(address): signer` into `0x1::aptos_governance`, the only
+/// module the VM provides the native for, and makes it public so that other code can call it.
+fn add_public_create_signer(m: &mut IndexedModule) -> Result<FunctionHandleIndex> {
+    let func_handle_idx = add_simple_native_function(
+        m,
+        FUNC_NAME_CREATE_SIGNER.clone(),
+        vec![SignatureToken::Address],
+        vec![SignatureToken::Signer],
+    )?;
+    let func_def = find_function_def_by_name(m, &FUNC_NAME_CREATE_SIGNER)
+        .ok_or_else(|| anyhow!("failed to locate `fun {}`", &*FUNC_NAME_CREATE_SIGNER))?;
+    func_def.visibility = Visibility::Public;
+    Ok(func_handle_idx)
+}
+
+/// Injects `native fun create_signer(address): signer` into `0x1::aptos_governance` as a friend
+/// function, and declares the given module a friend so that only it can call the function.
+fn add_friend_create_signer(m: &mut IndexedModule, friend: &ModuleId) -> Result<()> {
+    add_simple_native_function(
+        m,
+        FUNC_NAME_CREATE_SIGNER.clone(),
+        vec![SignatureToken::Address],
+        vec![SignatureToken::Signer],
+    )?;
+    let func_def = find_function_def_by_name(m, &FUNC_NAME_CREATE_SIGNER)
+        .ok_or_else(|| anyhow!("failed to locate `fun {}`", &*FUNC_NAME_CREATE_SIGNER))?;
+    func_def.visibility = Visibility::Friend;
+
+    // Friend declarations are not dependencies, so they do not go through the module handles.
+    let friend_decl = ModuleHandle {
+        address: get_or_add_addr(m, *friend.address()),
+        name: get_or_add_ident(m, friend.name().to_owned()),
+    };
+    if !m.friend_decls.contains(&friend_decl) {
+        m.friend_decls.push(friend_decl);
+    }
+    Ok(())
+}
+
+/// Makes `0x1::aptos_governance::create_signer` available to any code executed on top of the
+/// state view, e.g. setup calls or modules published by them, so that it can obtain signers for
+/// arbitrary addresses like governance proposals do for the framework accounts.
+///
+/// The patch lifts the access control of the framework, so it should only be applied for the
+/// duration of the calls that need it and reverted afterwards.
+///
+/// The execution environment must be created with
+/// [`AptosEnvironment::new_with_injected_create_signer_for_gov_sim`] for the native to be
+/// available.
+pub fn inject_create_signer(
+    state_view: &SimulationStateView<impl StateView>,
+    deserializer_config: &DeserializerConfig,
+) -> Result<()> {
+    patch_module(
+        state_view,
+        deserializer_config,
+        &MODULE_ID_APTOS_GOVERNANCE,
+        |m| add_public_create_signer(m).map(|_| ()),
+        None,
+    )
+}
+
+/// Returns true if the script calls the given function.
+fn script_calls_function(
+    script: &CompiledScript,
+    module_id: &ModuleId,
+    func_name: &IdentStr,
+) -> bool {
+    script.function_handles().iter().any(|handle| {
+        let module_handle = script.module_handle_at(handle.module);
+        script.address_identifier_at(module_handle.address) == module_id.address()
+            && script.identifier_at(module_handle.name) == module_id.name()
+            && script.identifier_at(handle.name) == func_name
+    })
+}
+
+/// Returns the next execution hash the script passes to the multi-step resolve function, or
+/// `None` if it does not call the function or the hash cannot be determined statically, i.e. it
+/// is neither a constant nor an empty vector literal.
+fn find_next_execution_hash(script: &CompiledScript, patch_plan: &PatchPlan) -> Option<Vec<u8>> {
+    let resolve_handle_idx = script.function_handles().iter().position(|handle| {
+        let module_handle = script.module_handle_at(handle.module);
+        script.address_identifier_at(module_handle.address)
+            == patch_plan.governance_module.address()
+            && script.identifier_at(module_handle.name) == patch_plan.governance_module.name()
+            && script.identifier_at(handle.name) == patch_plan.resolve_function.as_ident_str()
+    })? as u16;
+
+    // The hash is the last argument, so it is pushed right before the call.
+    let code = &script.code.code;
+    let call_offset = code
+        .iter()
+        .position(|instr| *instr == Bytecode::Call(FunctionHandleIndex(resolve_handle_idx)))?;
+    match code.get(call_offset.checked_sub(1)?)? {
+        Bytecode::LdConst(idx) => {
+            let constant = script.constant_at(*idx);
+            if constant.type_ != SignatureToken::Vector(Box::new(SignatureToken::U8)) {
+                return None;
+            }
+            bcs::from_bytes(&constant.data).ok()
+        },
+        Bytecode::VecPack(_, 0) => Some(vec![]),
+        _ => None,
+    }
+}
+
+/// Checks that every step but the last passes the hash of the following step as its next
+/// execution hash, as a mis-chained proposal would pass the simulation but fail on chain. Steps
+/// whose next execution hash cannot be determined are skipped with a warning.
+fn verify_execution_hash_chain(
+    proposal_scripts: &[PathBuf],
+    compiled_scripts: &[(Vec<u8>, HashValue)],
+    patch_plan: &PatchPlan,
+) -> Result<()> {
+    for (idx, (script_path, (script_blob, _))) in
+        proposal_scripts.iter().zip(compiled_scripts).enumerate()
+    {
+        let (Some(next_script_path), Some((_, next_script_hash))) =
+            (proposal_scripts.get(idx + 1), compiled_scripts.get(idx + 1))
+        else {
+            break;
+        };
+        let script_name = script_path.file_name().unwrap().to_string_lossy();
+
+        let script = CompiledScript::deserialize(script_blob)
+            .map_err(|err| anyhow!("failed to deserialize script {}: {:?}", script_name, err))?;
+        match find_next_execution_hash(&script, patch_plan) {
+            Some(hash) if hash == next_script_hash.to_vec() => (),
+            Some(hash) => bail!(
+                "{} passes next execution hash 0x{}, but the next step {} has hash 0x{}",
+                script_name,
+                hex::encode(hash),
+                next_script_path.file_name().unwrap().to_string_lossy(),
+                next_script_hash.to_hex()
+            ),
+            None => warn!(
+                "Warning: could not determine the next execution hash passed by {}, skipping \
+                 the check against the next step",
+                script_name
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if the script is a single-step proposal, i.e. it is resolved through
+/// `resolve` of the governance module rather than the multi-step resolve function.
+fn is_single_step_script(script: &CompiledScript, patch_plan: &PatchPlan) -> bool {
+    script_calls_function(script, &patch_plan.governance_module, &FUNC_NAME_RESOLVE)
+        && !script_calls_function(
+            script,
+            &patch_plan.governance_module,
+            &patch_plan.resolve_function,
+        )
+}
+
+// Add the hash of the script to the list of approved hashes, so to enable the
+// alternative (higher) execution limits.
+//
+// The approved hashes are a `SimpleMap` keyed by proposal id, so the keys must stay unique: the
+// hash of an earlier step of the simulated proposal is replaced, and an id already approved in the
+// remote state, i.e. for a real proposal, is rejected.
 fn add_script_execution_hash(
     state_view: &SimulationStateView<impl StateView>,
+    proposal_id: u64,
     hash: HashValue,
 ) -> Result<()> {
-    let entry = (DUMMY_PROPOSAL_ID, hash.to_vec());
+    if fetch_approved_execution_hashes(&state_view.remote)?
+        .iter()
+        .any(|(approved_id, _)| *approved_id == proposal_id)
+    {
+        bail!(
+            "proposal id {} already has an approved execution hash on chain, choose another dummy \
+             proposal id",
+            proposal_id
+        );
+    }
+
+    state_view.modify_on_chain_config(|approved_hashes: &mut ApprovedExecutionHashes| {
+        match approved_hashes
+            .entries
+            .iter_mut()
+            .find(|(approved_id, _)| *approved_id == proposal_id)
+        {
+            Some((_, approved_hash)) => *approved_hash = hash.to_vec(),
+            None => approved_hashes.entries.push((proposal_id, hash.to_vec())),
+        }
+        Ok(())
+    })
+}
+
+/// Returns the approved execution hashes in the given state, keyed by proposal id. Failing to
+/// fetch or decode them is an error rather than treated as no hashes being approved.
+fn fetch_approved_execution_hashes(state_view: &impl StateView) -> Result<Vec<(u64, Vec<u8>)>> {
+    let state_key = StateKey::on_chain_config::<ApprovedExecutionHashes>()?;
+    let Some(bytes) = state_view
+        .get_state_value_bytes(&state_key)
+        .context("failed to fetch the approved execution hashes")?
+    else {
+        return Ok(vec![]);
+    };
+    let approved_hashes = ApprovedExecutionHashes::deserialize_into_config(&bytes)
+        .context("failed to deserialize the approved execution hashes")?;
+    Ok(approved_hashes.entries)
+}
+
+/// Checks the hash of the first script of a proposal against the approved execution hashes in the
+/// given state, e.g. the remote state before the simulation added its own, and warns if none
+/// matches, which indicates that the local source differs from the approved proposal.
+///
+/// Only the first script needs checking: the hashes of the later steps are chained through the
+/// next execution hashes, see [`verify_execution_hash_chain`].
+fn verify_approved_execution_hash(
+    state_view: &impl StateView,
+    script_hash: HashValue,
+) -> Result<()> {
+    let entries = fetch_approved_execution_hashes(state_view)?;
+    if let Some((proposal_id, _)) = entries
+        .iter()
+        .find(|(_, hash)| hash.as_slice() == script_hash.as_ref())
+    {
+        info!(
+            "The script hash matches the approved execution hash of proposal {}",
+            proposal_id
+        );
+    } else if entries.is_empty() {
+        warn!(
+            "Warning: no execution hashes are approved on chain, so the script hash {} cannot be \
+             verified",
+            script_hash
+        );
+    } else {
+        warn!(
+            "Warning: the script hash {} matches none of the execution hashes approved on chain, \
+             the local source may differ from the approved proposal:",
+            script_hash
+        );
+        for (proposal_id, hash) in &entries {
+            warn!("    proposal {}: {}", proposal_id, hex::encode(hash));
+        }
+    }
+
+    Ok(())
+}
+
+/***************************************************************************************************
+ * Multisig Transactions
+ *
+ **************************************************************************************************/
+static MODULE_ID_MULTISIG_ACCOUNT: Lazy<ModuleId> = Lazy::new(|| {
+    ModuleId::new(
+        AccountAddress::ONE,
+        Identifier::new("multisig_account").unwrap(),
+    )
+});
+
+/// Abort code of the patched `failed_transaction_execution_cleanup`, which signals that the
+/// payload of a multisig transaction failed. Chosen like
+/// [`MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK`] to avoid collisions with framework abort codes.
+const MAGIC_FAILED_MULTISIG_PAYLOAD: u64 = 0x5349_4D55_DEAD_FA11;
+
+/// Replaces the body of a function with the given code, which must not use any locals besides
+/// the parameters.
+fn replace_function_body(
+    m: &mut IndexedModule,
+    func_name: &IdentStr,
+    body: Vec<Bytecode>,
+) -> Result<()> {
+    let module_id = m.self_id();
+    let locals = get_or_add_signature(m, vec![]);
+    let func_def = find_function_def_by_name(m, func_name).ok_or_else(|| {
+        anyhow!(
+            "failed to locate `fun {}` in module {}",
+            func_name,
+            module_id
+        )
+    })?;
+    func_def.acquires_global_resources.clear();
+    let code = func_def
+        .code
+        .as_mut()
+        .ok_or_else(|| anyhow!("`fun {}` must have a Move-defined body", func_name))?;
+    code.locals = locals;
+    code.code = body;
+
+    Ok(())
+}
+
+/// Patches `0x1::multisig_account` so that any account can execute any payload on behalf of a
+/// multisig account, without the payload being stored on chain or approved by the owners.
+///
+/// As there is no pending transaction to clean up after, the cleanup functions do nothing, except
+/// that a failed payload makes the transaction abort with [`MAGIC_FAILED_MULTISIG_PAYLOAD`].
+/// Otherwise, the failure would go unnoticed, as the VM keeps multisig transactions whose payload
+/// failed.
+///
+/// The patch disables the checks for every multisig account, so it must only be in place while a
+/// payload is executed, see [`SimulationSession::execute_multisig_payload`].
+fn patch_multisig_account(
+    state_view: &SimulationStateView<impl StateView>,
+    deserializer_config: &DeserializerConfig,
+) -> Result<()> {
+    use Bytecode::*;
+
+    patch_module(
+        state_view,
+        deserializer_config,
+        &MODULE_ID_MULTISIG_ACCOUNT,
+        |m| {
+            replace_function_body(m, ident_str!("validate_multisig_transaction"), vec![Ret])?;
+            // Return the provided payload, i.e. the one sent along with the transaction.
+            replace_function_body(m, ident_str!("get_next_transaction_payload"), vec![
+                MoveLoc(1),
+                Ret,
+            ])?;
+            replace_function_body(
+                m,
+                ident_str!("successful_transaction_execution_cleanup"),
+                vec![Ret],
+            )?;
+            replace_function_body(m, ident_str!("failed_transaction_execution_cleanup"), vec![
+                LdU64(MAGIC_FAILED_MULTISIG_PAYLOAD),
+                Abort,
+            ])
+        },
+        None,
+    )
+}
+
+/// Returns whether an entry function takes a signer, which the VM passes as the first argument.
+fn takes_signer(
+    state_view: &impl StateView,
+    deserializer_config: &DeserializerConfig,
+    module_id: &ModuleId,
+    func_name: &IdentStr,
+) -> Result<bool> {
+    let blob = state_view
+        .get_state_value_bytes(&StateKey::module_id(module_id))?
+        .ok_or_else(|| anyhow!("module {} does not exist on chain", module_id))?;
+    let mut m = CompiledModule::deserialize_with_config(&blob, deserializer_config)?;
+    let func_handle_idx = find_function_def_by_name(&mut m, func_name)
+        .ok_or_else(|| {
+            anyhow!(
+                "failed to locate `fun {}` in module {}",
+                func_name,
+                module_id
+            )
+        })?
+        .function;
+    let params = m.signature_at(m.function_handle_at(func_handle_idx).parameters);
+    Ok(match params.0.first() {
+        Some(SignatureToken::Signer) => true,
+        Some(SignatureToken::Reference(inner)) => **inner == SignatureToken::Signer,
+        _ => false,
+    })
+}
+
+/***************************************************************************************************
+ * Governance Signer Capabilities
+ *
+ **************************************************************************************************/
+/// Rust mirror of `0x1::aptos_governance::GovernanceResponsbility`, which holds the signer
+/// capabilities governance proposals are resolved with.
+///
+/// The `SimpleMap<address, SignerCapability>` is encoded as a vector of key-value pairs, and
+/// `SignerCapability` only wraps the address of the account it grants access to.
+#[derive(Debug, Deserialize)]
+struct GovernanceResponsibility {
+    signer_caps: Vec<(AccountAddress, AccountAddress)>,
+}
+
+impl MoveStructType for GovernanceResponsibility {
+    // Note: the typo is part of the on-chain struct name.
+    const MODULE_NAME: &'static IdentStr = ident_str!("aptos_governance");
+    const STRUCT_NAME: &'static IdentStr = ident_str!("GovernanceResponsbility");
+}
+
+impl MoveResource for GovernanceResponsibility {}
+
+/// Prints the addresses governance currently holds signer capabilities for.
+///
+/// The patched governance module bypasses these capabilities entirely, so this is purely
+/// informational, and must be called before any patches are applied to the state view.
+fn report_governance_signer_capabilities(state_view: &impl StateView) -> Result<()> {
+    let state_key = StateKey::resource_typed::<GovernanceResponsibility>(&AccountAddress::ONE)?;
+    let bytes = match state_view.get_state_value_bytes(&state_key)? {
+        Some(bytes) => bytes,
+        None => {
+            info!("    No governance signer capabilities found on chain");
+            return Ok(());
+        },
+    };
+    let resource: GovernanceResponsibility = bcs::from_bytes(&bytes)
+        .context("failed to deserialize the governance signer capabilities")?;
+
+    info!(
+        "    Governance holds signer capabilities for {} account{}:",
+        resource.signer_caps.len(),
+        if resource.signer_caps.len() == 1 {
+            ""
+        } else {
+            "s"
+        }
+    );
+    for (addr, cap_account) in &resource.signer_caps {
+        if addr == cap_account {
+            info!("        {}", addr);
+        } else {
+            info!("        {} (capability for {})", addr, cap_account);
+        }
+    }
+
+    Ok(())
+}
+
+/***************************************************************************************************
+ * Sender Funding
+ *
+ **************************************************************************************************/
+/// Rust mirror of `0x1::fungible_asset::Supply`, which tracks the supply of a fungible asset
+/// when concurrent supply is not in use.
+#[derive(Debug, Deserialize, Serialize)]
+struct FungibleAssetSupply {
+    current: u128,
+    maximum: Option<u128>,
+}
+
+impl MoveStructType for FungibleAssetSupply {
+    const MODULE_NAME: &'static IdentStr = ident_str!("fungible_asset");
+    const STRUCT_NAME: &'static IdentStr = ident_str!("Supply");
+}
+
+/// Adds `amount` to the supply tracked by `0x1::coin::CoinInfo<AptosCoin>`.
+///
+/// The APT supply is parallelizable, meaning the actual value lives in an aggregator (v1) table
+/// item rather than in the `CoinInfo` resource itself.
+fn increase_coin_apt_supply(
+    state_view: &SimulationStateView<impl StateView>,
+    amount: u64,
+) -> Result<()> {
+    let coin_info_key =
+        StateKey::resource_typed::<CoinInfoResource<AptosCoinType>>(&AccountAddress::ONE)?;
+    let bytes = state_view
+        .get_state_value_bytes(&coin_info_key)?
+        .ok_or_else(|| anyhow!("APT coin info does not exist"))?;
+    let coin_info: CoinInfoResource<AptosCoinType> =
+        bcs::from_bytes(&bytes).context("failed to deserialize the APT coin info")?;
+
+    let aggregator = match coin_info.supply() {
+        // The supply is not being monitored, so there is nothing to update.
+        None => return Ok(()),
+        Some(supply) => match &supply.aggregator {
+            Some(aggregator) => aggregator,
+            None => bail!("the APT supply is expected to be tracked by an aggregator"),
+        },
+    };
+
+    let supply_key = aggregator.state_key();
+    let supply_val = state_view
+        .get_state_value(&supply_key)?
+        .ok_or_else(|| anyhow!("the APT supply aggregator does not exist"))?;
+    let supply_val = supply_val.map_bytes(|bytes| {
+        let supply: u128 = bcs::from_bytes(&bytes)?;
+        Ok(bcs::to_bytes(&(supply + amount as u128))?.into())
+    })?;
+    state_view.set_state_value(supply_key, supply_val);
+
+    Ok(())
+}
+
+/// Adds `amount` to the supply of the APT fungible asset, whose metadata object lives at `0xA`.
+fn increase_fa_apt_supply(
+    state_view: &SimulationStateView<impl StateView>,
+    amount: u64,
+) -> Result<()> {
+    let group_key =
+        StateKey::resource_group(&AccountAddress::TEN, &ObjectGroupResource::struct_tag());
+    let group_val = state_view
+        .get_state_value(&group_key)?
+        .ok_or_else(|| anyhow!("the APT fungible asset metadata object does not exist"))?;
+    let mut group = ObjectGroupResource {
+        group: bcs::from_bytes(group_val.bytes())
+            .context("failed to deserialize the APT metadata object group")?,
+    };
+
+    let concurrent_supply_tag = ConcurrentSupplyResource::struct_tag();
+    let supply_tag = FungibleAssetSupply::struct_tag();
+    if let Some(bytes) = group.group.get(&concurrent_supply_tag) {
+        let mut supply: ConcurrentSupplyResource = bcs::from_bytes(bytes)?;
+        supply.current.set(supply.current.get() + amount as u128);
+        group.insert(concurrent_supply_tag, bcs::to_bytes(&supply)?);
+    } else if let Some(bytes) = group.group.get(&supply_tag) {
+        let mut supply: FungibleAssetSupply = bcs::from_bytes(bytes)?;
+        supply.current += amount as u128;
+        group.insert(supply_tag, bcs::to_bytes(&supply)?);
+    } else {
+        bail!("the APT fungible asset metadata object does not track its supply");
+    }
+
+    let group_val = group_val.map_bytes(|_| Ok(group.to_bytes()?.into()))?;
+    state_view.set_state_value(group_key, group_val);
+
+    Ok(())
+}
+
+/// Accounts for the APT minted out of thin air when funding the sender account, so that the
+/// total supply stays consistent with the sum of all balances.
+fn increase_apt_supply(
+    state_view: &SimulationStateView<impl StateView>,
+    amount: u64,
+    use_fa_apt: bool,
+) -> Result<()> {
+    if use_fa_apt {
+        increase_fa_apt_supply(state_view, amount)
+    } else {
+        increase_coin_apt_supply(state_view, amount)
+    }
+}
+
+/// Rust mirror of `0x1::account::Account`, whose authentication key [`AccountResource`] does not
+/// allow replacing.
+#[derive(Deserialize, Serialize)]
+struct AccountMirror {
+    authentication_key: Vec<u8>,
+    sequence_number: u64,
+    guid_creation_num: u64,
+    coin_register_events: EventHandle,
+    key_rotation_events: EventHandle,
+    rotation_capability_offer: Option<AccountAddress>,
+    signer_capability_offer: Option<AccountAddress>,
+}
+
+/// Returns true if the address holds an APT coin store or primary fungible store, whose balance
+/// is already part of the supply.
+fn holds_apt_store(state_view: &impl StateView, address: &AccountAddress) -> Result<bool> {
+    let coin_store_key = StateKey::resource_typed::<CoinStoreResource<AptosCoinType>>(address)?;
+    let fa_store_key = StateKey::resource_group(
+        &primary_apt_store(*address),
+        &ObjectGroupResource::struct_tag(),
+    );
+    Ok(state_view.get_state_value_bytes(&coin_store_key)?.is_some()
+        || state_view.get_state_value_bytes(&fa_store_key)?.is_some())
+}
+
+/// Sets up the account the scripts are sent from.
+///
+/// A new account is funded with the given balance, which is added to the APT supply. Unless
+/// `allow_existing` is unset, in which case it is an error, an existing account is taken over by
+/// replacing its authentication key, keeping its sequence number and balance. Likewise, the APT
+/// an address holds without an account is kept rather than minted again.
+fn install_sender_account(
+    state_view: &SimulationStateView<impl StateView>,
+    account: &AccountData,
+    balance: u64,
+    use_fa_apt: bool,
+    allow_existing: bool,
+) -> Result<()> {
+    let address = account.address();
+    let account_key = StateKey::resource_typed::<AccountResource>(address)?;
+    let existing_account = state_view.get_state_value(&account_key)?;
+    let holds_apt = holds_apt_store(state_view, address)?;
+    if !allow_existing && (existing_account.is_some() || holds_apt) {
+        bail!("sender account {} already exists", address);
+    }
+
+    match existing_account {
+        Some(account_val) => {
+            let mut account_resource: AccountMirror = bcs::from_bytes(account_val.bytes())
+                .with_context(|| format!("failed to deserialize account {}", address))?;
+            account_resource.authentication_key = account.account().auth_key();
+            let account_val =
+                account_val.map_bytes(|_| Ok(bcs::to_bytes(&account_resource)?.into()))?;
+            state_view.set_state_value(account_key, account_val);
+        },
+        None if holds_apt => {
+            state_view.set_state_value(
+                account_key,
+                StateValue::new_legacy(account.to_bytes().into()),
+            );
+        },
+        None => {
+            state_view.apply_write_set(account.to_writeset());
+            increase_apt_supply(state_view, balance, use_fa_apt)
+                .context("failed to account for the sender balance in the APT supply")?;
+        },
+    }
+
+    Ok(())
+}
+
+/***************************************************************************************************
+ * Resource Changes
+ *
+ **************************************************************************************************/
+/// A resource whose value in the local overlay differs from the one before the proposal executed.
+struct ResourceChange {
+    address: AccountAddress,
+    struct_tag: StructTag,
+    before: Option<Vec<u8>>,
+    after: Option<Vec<u8>>,
+}
+
+/// Collects the resources changed since the given snapshot of the local overlay was taken, sorted
+/// by address and type. Resource groups are broken up into their members.
+///
+/// The snapshot is taken right before the first script, so that the changes made by the
+/// simulation itself, e.g. funding the sender or applying overrides, are not attributed to the
+/// proposal.
+fn collect_resource_changes(
+    state_view: &SimulationStateView<impl StateView>,
+    baseline: &HashMap<StateKey, Option<StateValue>>,
+) -> Result<Vec<ResourceChange>> {
+    // Fetch the remote values without holding the lock on the local changes.
+    let states = state_view.states.lock().clone();
+    let read_value =
+        |states: &HashMap<StateKey, Option<StateValue>>, state_key: &StateKey| -> Result<_> {
+            let state_value = match states.get(state_key) {
+                Some(state_value) => state_value.clone(),
+                None => state_view.remote.get_state_value(state_key)?,
+            };
+            Ok(state_value.map(|state_value| state_value.bytes().to_vec()))
+        };
+
+    let state_keys = states
+        .keys()
+        .chain(baseline.keys())
+        .collect::<BTreeSet<_>>();
+
+    let mut changes = vec![];
+    for state_key in state_keys {
+        let StateKeyInner::AccessPath(path) = state_key.inner() else {
+            continue;
+        };
+        let before = read_value(baseline, state_key)?;
+        let after = read_value(&states, state_key)?;
+        if before == after {
+            continue;
+        }
+
+        match path.get_path() {
+            access_path::Path::Resource(struct_tag) => changes.push(ResourceChange {
+                address: path.address,
+                struct_tag,
+                before,
+                after,
+            }),
+            access_path::Path::ResourceGroup(group_tag) => {
+                let decode_group = |blob: Option<Vec<u8>>| -> Result<BTreeMap<StructTag, Vec<u8>>> {
+                    blob.map(|blob| bcs::from_bytes(&blob))
+                        .transpose()
+                        .map(Option::unwrap_or_default)
+                        .with_context(|| format!("failed to decode resource group {}", group_tag))
+                };
+                let mut before = decode_group(before)?;
+                let mut after = decode_group(after)?;
+                let member_tags = before
+                    .keys()
+                    .chain(after.keys())
+                    .cloned()
+                    .collect::<BTreeSet<_>>();
+                for struct_tag in member_tags {
+                    let before = before.remove(&struct_tag);
+                    let after = after.remove(&struct_tag);
+                    if before != after {
+                        changes.push(ResourceChange {
+                            address: path.address,
+                            struct_tag,
+                            before,
+                            after,
+                        });
+                    }
+                }
+            },
+            access_path::Path::Code(_) => (),
+        }
+    }
+    changes.sort_by(|a, b| (a.address, &a.struct_tag).cmp(&(b.address, &b.struct_tag)));
+    Ok(changes)
+}
+
+/// Name of the file in a proposal directory the resource diff is written to.
+const RESOURCE_DIFF_FILE_NAME: &str = "resource_diff.txt";
+
+/// Number of unchanged lines shown around the changed lines of a modified resource.
+const RESOURCE_DIFF_CONTEXT_LINES: usize = 3;
+
+/// Computes a line diff of two texts, returning each line prefixed with `-`, `+` or a space.
+///
+/// Texts too large for the quadratic longest common subsequence are shown as entirely removed
+/// and added instead.
+fn diff_lines(before: &str, after: &str) -> Vec<String> {
+    const MAX_LCS_CELLS: usize = 4_000_000;
+
+    let before = before.lines().collect::<Vec<_>>();
+    let after = after.lines().collect::<Vec<_>>();
+    let removed = |line: &&str| format!("-{}", line);
+    let added = |line: &&str| format!("+{}", line);
+    if before.len().saturating_mul(after.len()) > MAX_LCS_CELLS {
+        return before
+            .iter()
+            .map(removed)
+            .chain(after.iter().map(added))
+            .collect();
+    }
+
+    // lcs[i][j] is the length of the longest common subsequence of before[i..] and after[j..].
+    let mut lcs = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            lcs[i][j] = if before[i] == after[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() && j < after.len() {
+        if before[i] == after[j] {
+            lines.push(format!(" {}", before[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(removed(&before[i]));
+            i += 1;
+        } else {
+            lines.push(added(&after[j]));
+            j += 1;
+        }
+    }
+    lines.extend(before[i..].iter().map(removed));
+    lines.extend(after[j..].iter().map(added));
+    lines
+}
+
+/// Renders a resource with its fields decoded, or as hex if its layout cannot be resolved.
+fn render_resource<S: StateView>(
+    annotator: &AptosValueAnnotator<S>,
+    struct_tag: &StructTag,
+    blob: &[u8],
+) -> String {
+    match annotator.view_resource(struct_tag, blob) {
+        Ok(resource) => resource.to_string(),
+        Err(_) => format!("0x{}", hex::encode(blob)),
+    }
+}
+
+/// Renders the resource changes as a diff, grouped by account. The old values are decoded with
+/// the layouts of the remote state, the new ones with the layouts after the simulation, as the
+/// proposal may have upgraded the modules defining them.
+fn render_resource_diff(
+    state_view: &SimulationStateView<impl StateView>,
+    changes: &[ResourceChange],
+) -> String {
+    let before_annotator = AptosValueAnnotator::new(&state_view.remote);
+    let after_annotator = AptosValueAnnotator::new(state_view);
+
+    let mut diff = String::new();
+    let mut prev_address = None;
+    for change in changes {
+        if prev_address != Some(change.address) {
+            diff.push_str(&format!("{}\n", change.address.to_hex_literal()));
+            prev_address = Some(change.address);
+        }
+
+        let struct_tag = &change.struct_tag;
+        let (marker, lines) = match (&change.before, &change.after) {
+            (None, Some(after)) => (
+                "+",
+                diff_lines("", &render_resource(&after_annotator, struct_tag, after)),
+            ),
+            (Some(before), None) => (
+                "-",
+                diff_lines(&render_resource(&before_annotator, struct_tag, before), ""),
+            ),
+            (Some(before), Some(after)) => {
+                let lines = diff_lines(
+                    &render_resource(&before_annotator, struct_tag, before),
+                    &render_resource(&after_annotator, struct_tag, after),
+                );
+                // Only show the unchanged lines close to a change, eliding the others.
+                let is_changed = |idx: usize| !lines[idx].starts_with(' ');
+                let mut shown_lines = vec![];
+                let mut elided = false;
+                for (idx, line) in lines.iter().enumerate() {
+                    let start = idx.saturating_sub(RESOURCE_DIFF_CONTEXT_LINES);
+                    let end = (idx + RESOURCE_DIFF_CONTEXT_LINES + 1).min(lines.len());
+                    if (start..end).any(is_changed) {
+                        if elided {
+                            shown_lines.push(" ...".to_string());
+                            elided = false;
+                        }
+                        shown_lines.push(line.clone());
+                    } else {
+                        elided = true;
+                    }
+                }
+                if elided {
+                    shown_lines.push(" ...".to_string());
+                }
+                ("~", shown_lines)
+            },
+            (None, None) => continue,
+        };
+
+        diff.push_str(&format!(
+            "    {} {}\n",
+            marker,
+            struct_tag.to_canonical_string()
+        ));
+        for line in lines {
+            diff.push_str(&format!("        {}\n", line));
+        }
+    }
+    diff
+}
+
+/***************************************************************************************************
+ * Staking Summary
+ *
+ **************************************************************************************************/
+/// Rust mirror of `0x1::staking_config::StakingConfig`.
+#[derive(Deserialize, Serialize)]
+struct StakingConfig {
+    minimum_stake: u64,
+    maximum_stake: u64,
+    recurring_lockup_duration_secs: u64,
+    allow_validator_set_change: bool,
+    rewards_rate: u64,
+    rewards_rate_denominator: u64,
+    voting_power_increase_limit: u64,
+}
+
+/// Rust mirror of `0x1::fixed_point64::FixedPoint64`.
+#[derive(Deserialize)]
+struct FixedPoint64 {
+    value: u128,
+}
+
+impl FixedPoint64 {
+    fn to_f64(&self) -> f64 {
+        self.value as f64 / 2f64.powi(64)
+    }
+}
+
+/// Rust mirror of `0x1::staking_config::StakingRewardsConfig`.
+#[derive(Deserialize)]
+struct StakingRewardsConfig {
+    rewards_rate: FixedPoint64,
+    min_rewards_rate: FixedPoint64,
+    rewards_rate_period_in_secs: u64,
+    last_rewards_rate_period_start_in_secs: u64,
+    rewards_rate_decrease_rate: FixedPoint64,
+}
+
+fn format_percent(rate: f64) -> String {
+    format!("{:.4}%", rate * 100.0)
+}
+
+/// Formats a rate given as a fraction, e.g. of a misconfigured staking config, which may have a
+/// zero denominator.
+fn format_fraction_percent(numerator: u64, denominator: u64) -> String {
+    if denominator == 0 {
+        "n/a".to_string()
+    } else {
+        format_percent(numerator as f64 / denominator as f64)
+    }
+}
+
+/// Appends a line describing the change of a field to the summary, if it changed at all.
+fn push_field_change<T: PartialEq + std::fmt::Display>(
+    summary: &mut Vec<String>,
+    name: &str,
+    before: T,
+    after: T,
+) {
+    if before != after {
+        summary.push(format!("{} {} → {}", name, before, after));
+    }
+}
+
+fn summarize_staking_config(before: &StakingConfig, after: &StakingConfig) -> Vec<String> {
+    let mut summary = vec![];
+    push_field_change(
+        &mut summary,
+        "minimum stake",
+        before.minimum_stake,
+        after.minimum_stake,
+    );
+    push_field_change(
+        &mut summary,
+        "maximum stake",
+        before.maximum_stake,
+        after.maximum_stake,
+    );
+    push_field_change(
+        &mut summary,
+        "recurring lockup duration (secs)",
+        before.recurring_lockup_duration_secs,
+        after.recurring_lockup_duration_secs,
+    );
+    push_field_change(
+        &mut summary,
+        "allow validator set change",
+        before.allow_validator_set_change,
+        after.allow_validator_set_change,
+    );
+    push_field_change(
+        &mut summary,
+        "reward rate per epoch",
+        format_fraction_percent(before.rewards_rate, before.rewards_rate_denominator),
+        format_fraction_percent(after.rewards_rate, after.rewards_rate_denominator),
+    );
+    push_field_change(
+        &mut summary,
+        "voting power increase limit (%)",
+        before.voting_power_increase_limit,
+        after.voting_power_increase_limit,
+    );
+    summary
+}
+
+fn summarize_staking_rewards_config(
+    before: &StakingRewardsConfig,
+    after: &StakingRewardsConfig,
+) -> Vec<String> {
+    let mut summary = vec![];
+    push_field_change(
+        &mut summary,
+        "reward rate per epoch",
+        format_percent(before.rewards_rate.to_f64()),
+        format_percent(after.rewards_rate.to_f64()),
+    );
+    push_field_change(
+        &mut summary,
+        "minimum reward rate per epoch",
+        format_percent(before.min_rewards_rate.to_f64()),
+        format_percent(after.min_rewards_rate.to_f64()),
+    );
+    push_field_change(
+        &mut summary,
+        "reward rate period (secs)",
+        before.rewards_rate_period_in_secs,
+        after.rewards_rate_period_in_secs,
+    );
+    push_field_change(
+        &mut summary,
+        "last reward rate period start (secs)",
+        before.last_rewards_rate_period_start_in_secs,
+        after.last_rewards_rate_period_start_in_secs,
+    );
+    push_field_change(
+        &mut summary,
+        "reward rate decrease rate",
+        format_percent(before.rewards_rate_decrease_rate.to_f64()),
+        format_percent(after.rewards_rate_decrease_rate.to_f64()),
+    );
+    summary
+}
+
+fn summarize_validator_set(before: &ValidatorSet, after: &ValidatorSet) -> Vec<String> {
+    let mut summary = vec![];
+
+    let lists = |validator_set: &ValidatorSet| {
+        [
+            ("active", &validator_set.active_validators),
+            ("pending active", &validator_set.pending_active),
+            ("pending inactive", &validator_set.pending_inactive),
+        ]
+        .into_iter()
+        .map(|(name, validators)| {
+            let validators = validators
+                .iter()
+                .map(|info| (*info.account_address(), info.consensus_voting_power()))
+                .collect::<BTreeMap<_, _>>();
+            (name, validators)
+        })
+        .collect::<Vec<_>>()
+    };
+    for ((name, validators_before), (_, validators_after)) in
+        lists(before).into_iter().zip(lists(after))
+    {
+        for (addr, voting_power) in &validators_after {
+            match validators_before.get(addr) {
+                None => summary.push(format!("{} validator {} added", name, addr)),
+                Some(prev_voting_power) if prev_voting_power != voting_power => {
+                    summary.push(format!(
+                        "{} validator {} voting power {} → {}",
+                        name, addr, prev_voting_power, voting_power
+                    ))
+                },
+                Some(_) => (),
+            }
+        }
+        for addr in validators_before.keys() {
+            if !validators_after.contains_key(addr) {
+                summary.push(format!("{} validator {} removed", name, addr));
+            }
+        }
+    }
+
+    push_field_change(
+        &mut summary,
+        "total voting power",
+        before.total_voting_power,
+        after.total_voting_power,
+    );
+    push_field_change(
+        &mut summary,
+        "total joining power",
+        before.total_joining_power,
+        after.total_joining_power,
+    );
+    summary
+}
+
+/// Decodes the before and after values of a changed resource and summarizes the change.
+fn summarize_decoded_change<T: serde::de::DeserializeOwned>(
+    change: &ResourceChange,
+    summarize: impl FnOnce(&T, &T) -> Vec<String>,
+) -> Result<Vec<String>> {
+    let decode = |bytes: &[u8]| -> Result<T> {
+        bcs::from_bytes(bytes).with_context(|| format!("failed to decode {}", change.struct_tag))
+    };
+    Ok(match (&change.before, &change.after) {
+        (Some(before), Some(after)) => summarize(&decode(before)?, &decode(after)?),
+        (None, Some(_)) => vec!["created".to_string()],
+        (Some(_), None) => vec!["deleted".to_string()],
+        (None, None) => vec![],
+    })
+}
+
+/// Summarizes the changes to well-known staking resources in domain terms, e.g. reward rate
+/// changes or validators joining the validator set.
+fn summarize_staking_changes(changes: &[ResourceChange]) -> Result<Vec<String>> {
+    let mut summary = vec![];
+    for change in changes {
+        let tag = &change.struct_tag;
+        if tag.address != AccountAddress::ONE {
+            continue;
+        }
+        let (title, lines) = match (tag.module.as_str(), tag.name.as_str()) {
+            ("staking_config", "StakingConfig") => (
+                "Staking config".to_string(),
+                summarize_decoded_change(change, summarize_staking_config)?,
+            ),
+            ("staking_config", "StakingRewardsConfig") => (
+                "Staking rewards config".to_string(),
+                summarize_decoded_change(change, summarize_staking_rewards_config)?,
+            ),
+            ("stake", "ValidatorSet") => (
+                "Validator set".to_string(),
+                summarize_decoded_change(change, summarize_validator_set)?,
+            ),
+            ("delegation_pool", "DelegationPool") => (
+                format!("Delegation pool {}", change.address),
+                vec![match (&change.before, &change.after) {
+                    (None, _) => "created".to_string(),
+                    (_, None) => "deleted".to_string(),
+                    _ => "modified".to_string(),
+                }],
+            ),
+            _ => continue,
+        };
+        for line in lines {
+            summary.push(format!("{}: {}", title, line));
+        }
+    }
+    Ok(summary)
+}
+
+/***************************************************************************************************
+ * Consensus Config
+ *
+ **************************************************************************************************/
+/// Rust mirror of `0x1::copyable_any::Any`.
+#[derive(Deserialize)]
+struct AnyValue {
+    #[allow(dead_code)]
+    type_name: String,
+    data: Vec<u8>,
+}
+
+/// Rust mirror of `0x1::config_buffer::PendingConfigs`, which holds the configs to be applied
+/// at the next reconfiguration.
+///
+/// The `SimpleMap<String, Any>` is encoded as a vector of key-value pairs.
+#[derive(Deserialize)]
+struct PendingConfigs {
+    configs: Vec<(String, AnyValue)>,
+}
+
+impl MoveStructType for PendingConfigs {
+    const MODULE_NAME: &'static IdentStr = ident_str!("config_buffer");
+    const STRUCT_NAME: &'static IdentStr = ident_str!("PendingConfigs");
+}
+
+impl MoveResource for PendingConfigs {}
+
+fn fetch_consensus_config(state_view: &impl StateView) -> Result<OnChainConsensusConfig> {
+    OnChainConsensusConfig::fetch_config(state_view)
+        .ok_or_else(|| anyhow!("failed to fetch the consensus config"))
+}
+
+/// Returns the consensus config that takes effect at the next reconfiguration, which may still
+/// be buffered if the proposal did not trigger one.
+fn fetch_next_consensus_config(state_view: &impl StateView) -> Result<OnChainConsensusConfig> {
+    let state_key = StateKey::resource_typed::<PendingConfigs>(&AccountAddress::ONE)?;
+    if let Some(bytes) = state_view.get_state_value_bytes(&state_key)? {
+        let pending: PendingConfigs =
+            bcs::from_bytes(&bytes).context("failed to deserialize the pending configs")?;
+        let type_name = format!(
+            "0x1::{}::{}",
+            OnChainConsensusConfig::MODULE_IDENTIFIER,
+            OnChainConsensusConfig::TYPE_IDENTIFIER
+        );
+        if let Some((_, config)) = pending.configs.iter().find(|(key, _)| *key == type_name) {
+            return OnChainConsensusConfig::deserialize_into_config(&config.data)
+                .context("failed to deserialize the pending consensus config");
+        }
+    }
+    fetch_consensus_config(state_view)
+}
+
+/// Appends a line for every leaf field whose value differs between the two JSON values.
+fn push_json_field_changes(
+    summary: &mut Vec<String>,
+    path: &str,
+    before: &serde_json::Value,
+    after: &serde_json::Value,
+) {
+    match (before, after) {
+        (serde_json::Value::Object(before), serde_json::Value::Object(after)) => {
+            let keys = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                let field_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                push_json_field_changes(
+                    summary,
+                    &field_path,
+                    before.get(key).unwrap_or(&serde_json::Value::Null),
+                    after.get(key).unwrap_or(&serde_json::Value::Null),
+                );
+            }
+        },
+        _ => push_field_change(summary, path, before, after),
+    }
+}
+
+/// Summarizes the changes between two consensus configs, one line per changed field.
+fn summarize_consensus_config(
+    before: &OnChainConsensusConfig,
+    after: &OnChainConsensusConfig,
+) -> Result<Vec<String>> {
+    let mut summary = vec![];
+    push_json_field_changes(
+        &mut summary,
+        "",
+        &serde_json::to_value(before)?,
+        &serde_json::to_value(after)?,
+    );
+    Ok(summary)
+}
+
+/***************************************************************************************************
+ * Coverage
+ *
+ **************************************************************************************************/
+/// Records the functions (including native ones) executed in the given call graph, which is
+/// reconstructed by the gas profiler from the VM's execution hooks.
+fn collect_executed_functions(
+    frame: &CallFrame,
+    executed_functions: &mut BTreeSet<(ModuleId, Identifier)>,
+) {
+    if let FrameName::Function {
+        module_id, name, ..
+    } = &frame.name
+    {
+        executed_functions.insert((module_id.clone(), name.clone()));
+    }
+
+    for event in &frame.events {
+        match event {
+            ExecutionGasEvent::Call(callee) => {
+                collect_executed_functions(callee, executed_functions)
+            },
+            ExecutionGasEvent::CallNative {
+                module_id, fn_name, ..
+            } => {
+                executed_functions.insert((module_id.clone(), fn_name.clone()));
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Returns the total execution cost of a call frame, including the calls it makes, in internal
+/// gas units.
+fn frame_execution_cost(frame: &CallFrame) -> u64 {
+    frame
+        .events
+        .iter()
+        .map(|event| match event {
+            ExecutionGasEvent::Loc(_) => 0,
+            ExecutionGasEvent::Bytecode { cost, .. }
+            | ExecutionGasEvent::CallNative { cost, .. }
+            | ExecutionGasEvent::LoadResource { cost, .. }
+            | ExecutionGasEvent::CreateTy { cost } => u64::from(*cost),
+            ExecutionGasEvent::Call(callee) => frame_execution_cost(callee),
+        })
+        .sum()
+}
+
+/// Collects the functions called directly by the script, which for a single-script proposal
+/// correspond to the individual governance actions it performs.
+fn collect_script_actions(script_frame: &CallFrame) -> Vec<ScriptAction> {
+    script_frame
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            ExecutionGasEvent::Call(callee) => match &callee.name {
+                FrameName::Function {
+                    module_id, name, ..
+                } => Some(ScriptAction {
+                    function: format!("{}::{}", module_id.short_str_lossless(), name),
+                    execution_cost: frame_execution_cost(callee),
+                }),
+                FrameName::Script => None,
+            },
+            ExecutionGasEvent::CallNative {
+                module_id,
+                fn_name,
+                cost,
+                ..
+            } => Some(ScriptAction {
+                function: format!("{}::{}", module_id.short_str_lossless(), fn_name),
+                execution_cost: u64::from(*cost),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Name of the file in the proposal directory the executed functions are written to.
+const COVERAGE_FILE_NAME: &str = "coverage.txt";
+
+fn write_coverage_report(
+    path: &Path,
+    executed_functions: &BTreeSet<(ModuleId, Identifier)>,
+) -> Result<()> {
+    let mut content = String::new();
+    for (module_id, func_name) in executed_functions {
+        content.push_str(&format!(
+            "{}::{}\n",
+            module_id.short_str_lossless(),
+            func_name
+        ));
+    }
+    std::fs::write(path, content)
+        .with_context(|| format!("failed to write coverage report to {}", path.display()))
+}
+
+/***************************************************************************************************
+ * Idempotency Check
+ *
+ **************************************************************************************************/
+/// Resources under `0x1`, as module and struct names, that may legitimately differ when the
+/// proposal is re-executed from the same state, e.g. because the block metadata or the storage
+/// usage are tracked across transactions.
+const VOLATILE_RERUN_RESOURCES: &[(&str, &str)] = &[
+    ("block", "BlockResource"),
+    ("reconfiguration", "Configuration"),
+    ("state_storage", "StateStorageUsage"),
+    ("timestamp", "CurrentTimeMicroseconds"),
+    ("transaction_fee", "CollectedFeesPerBlock"),
+];
+
+/// Returns true if the state item is expected to differ when the proposal is re-executed from the
+/// same state, i.e. the sender's account and balance, the APT supply, one of
+/// [`VOLATILE_RERUN_RESOURCES`], or the patched governance modules and the resolve arguments
+/// they record, which depend on the options of each run.
+fn is_expected_rerun_difference(
+    state_key: &StateKey,
+    sender: &AccountAddress,
+    patch_plan: &PatchPlan,
+) -> bool {
+    let StateKeyInner::AccessPath(path) = state_key.inner() else {
+        return false;
+    };
+    if path.address == *sender
+        || path.address == primary_apt_store(*sender)
+        // The APT metadata object, which tracks the total supply.
+        || path.address == AccountAddress::TEN
+    {
+        return true;
+    }
+    match path.get_path() {
+        access_path::Path::Resource(struct_tag) | access_path::Path::ResourceGroup(struct_tag) => {
+            let is_resolve_args = struct_tag.address == *patch_plan.governance_module.address()
+                && struct_tag.module.as_ident_str() == patch_plan.governance_module.name()
+                && struct_tag.name == *STRUCT_NAME_RESOLVE_ARGS;
+            path.address == AccountAddress::ONE
+                && struct_tag.address == AccountAddress::ONE
+                && (is_resolve_args
+                    || VOLATILE_RERUN_RESOURCES.iter().any(|(module, name)| {
+                        struct_tag.module.as_str() == *module && struct_tag.name.as_str() == *name
+                    }))
+        },
+        access_path::Path::Code(module_id) => {
+            module_id == *MODULE_ID_APTOS_GOVERNANCE || module_id == patch_plan.governance_module
+        },
+    }
+}
+
+/// Returns the state keys whose values differ between two snapshots of the local overlay,
+/// excluding the ones that are expected to differ between runs.
+fn find_rerun_differences(
+    state_view: &SimulationStateView<impl StateView>,
+    first_run: &HashMap<StateKey, Option<StateValue>>,
+    second_run: &HashMap<StateKey, Option<StateValue>>,
+    sender: &AccountAddress,
+    patch_plan: &PatchPlan,
+) -> Result<Vec<StateKey>> {
+    let read_value = |states: &HashMap<StateKey, Option<StateValue>>,
+                      state_key: &StateKey|
+     -> Result<Option<StateValue>> {
+        Ok(match states.get(state_key) {
+            Some(state_val) => state_val.clone(),
+            None => state_view.remote.get_state_value(state_key)?,
+        })
+    };
+
+    let state_keys = first_run
+        .keys()
+        .chain(second_run.keys())
+        .collect::<BTreeSet<_>>();
+
+    let mut differences = vec![];
+    for state_key in state_keys {
+        if is_expected_rerun_difference(state_key, sender, patch_plan) {
+            continue;
+        }
+
+        let before = read_value(first_run, state_key)?;
+        let after = read_value(second_run, state_key)?;
+        if before.as_ref().map(|val| val.bytes()) != after.as_ref().map(|val| val.bytes()) {
+            differences.push(state_key.clone());
+        }
+    }
+
+    Ok(differences)
+}
+
+/***************************************************************************************************
+ * Voting Records
+ *
+ **************************************************************************************************/
+/// Framework functions that read voting records or vote tallies. Since the simulation bypasses
+/// the voting process, the voting state these functions observe is synthetic.
+const VOTING_RECORD_FUNCTIONS: &[(&str, &str)] = &[
+    ("aptos_governance", "has_entirely_voted"),
+    ("aptos_governance", "get_remaining_voting_power"),
+    ("voting", "get_votes"),
+    ("voting", "get_proposal_state"),
+    ("voting", "is_voting_closed"),
+    ("voting", "can_be_resolved_early"),
+    ("voting", "is_resolved"),
+    ("voting", "get_resolution_time_secs"),
+];
+
+/// Returns the voting record functions referenced by the given compiled script.
+fn find_voting_record_references(script: &CompiledScript) -> Vec<String> {
+    let mut references = vec![];
+    for handle in script.function_handles() {
+        let module_handle = script.module_handle_at(handle.module);
+        let address = script.address_identifier_at(module_handle.address);
+        let module_name = script.identifier_at(module_handle.name);
+        let func_name = script.identifier_at(handle.name);
+
+        if *address == AccountAddress::ONE
+            && VOTING_RECORD_FUNCTIONS
+                .iter()
+                .any(|(m, f)| module_name.as_str() == *m && func_name.as_str() == *f)
+        {
+            references.push(format!(
+                "0x{}::{}::{}",
+                address.short_str_lossless(),
+                module_name,
+                func_name
+            ));
+        }
+    }
+
+    references
+}
+
+/***************************************************************************************************
+ * Proposal Manifest
+ *
+ **************************************************************************************************/
+/// Name of the optional manifest file in a proposal directory.
+const PROPOSAL_MANIFEST_FILE_NAME: &str = "proposal.json";
+
+/// Optional per-proposal settings, read from `proposal.json` in the proposal directory.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProposalManifest {
+    /// Version of the remote state to simulate the proposal on, e.g. for a proposal that builds
+    /// on one applied at a later version. Overrides the version given in the simulation options.
+    pub at_version: Option<u64>,
+    /// File names of the scripts in the order to execute them, overriding the default natural
+    /// order, e.g. for scripts whose names do not reflect their step number. Must list every
+    /// script of the proposal exactly once.
+    pub scripts: Option<Vec<String>>,
+    /// Per-step settings, keyed by the file name of the script.
+    pub steps: BTreeMap<String, StepManifest>,
+    /// Payloads of multisig transactions to execute after the scripts, e.g. operational changes
+    /// that go through a multisig account rather than governance.
+    pub multisig_payloads: Vec<MultisigPayload>,
+}
+
+/// Settings of a single step of a proposal.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StepManifest {
+    /// Set this flag for steps that intentionally change no on-chain config and publish no
+    /// modules, to suppress the possible no-op warning.
+    pub allow_no_op: bool,
+    /// Set this flag for steps that intentionally do not reconfigure, to exempt them from the
+    /// reconfiguration check.
+    pub allow_no_reconfiguration: bool,
+}
+
+impl ProposalManifest {
+    /// Loads the manifest of the proposal, falling back to the default settings if the proposal
+    /// has none.
+    pub fn load(proposal_dir: &Path) -> Result<Self> {
+        let path = proposal_dir.join(PROPOSAL_MANIFEST_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let bytes = std::fs::read(&path)
+            .with_context(|| format!("failed to read proposal manifest {}", path.display()))?;
+        serde_json::from_slice(&bytes)
+            .with_context(|| format!("failed to parse proposal manifest {}", path.display()))
+    }
+
+    pub fn step(&self, script_name: &str) -> StepManifest {
+        self.steps.get(script_name).cloned().unwrap_or_default()
+    }
+}
+
+/// An entry function executed on behalf of a multisig account, see
+/// [`SimulationSession::execute_multisig_payload`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MultisigPayload {
+    pub multisig_address: AccountAddress,
+    /// The entry function, e.g. `0x1::aptos_account::transfer`.
+    pub function: String,
+    /// Type arguments, e.g. `0x1::aptos_coin::AptosCoin`.
+    #[serde(default)]
+    pub type_args: Vec<String>,
+    /// Arguments following the signer, e.g. `{"U64": 1}` or `{"Address": "0x1"}`.
+    #[serde(default)]
+    pub args: Vec<TransactionArgument>,
+}
+
+impl MultisigPayload {
+    pub fn entry_function(&self) -> Result<EntryFunction> {
+        let (module, function) = self
+            .function
+            .rsplit_once("::")
+            .ok_or_else(|| anyhow!("invalid entry function {}", self.function))?;
+        let module_id = module
+            .parse::<ModuleId>()
+            .with_context(|| format!("invalid module of entry function {}", self.function))?;
+        let ty_args = self
+            .type_args
+            .iter()
+            .map(|type_arg| {
+                type_arg
+                    .parse::<TypeTag>()
+                    .with_context(|| format!("invalid type argument {}", type_arg))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let args = self
+            .args
+            .iter()
+            .map(|arg| MoveValue::from(arg.clone()))
+            .collect();
+        Ok(EntryFunction::new(
+            module_id,
+            Identifier::new(function)?,
+            ty_args,
+            serialize_args(args),
+        ))
+    }
+}
+
+/// Extension of the optional file next to a script that specifies the arguments to execute it
+/// with, e.g. `0-features.args.json` for `0-features.move`.
+const SCRIPT_ARGS_FILE_EXTENSION: &str = "args.json";
+
+/// Arguments to execute a script with, read from the file next to the script.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScriptArgs {
+    /// Type arguments, e.g. `0x1::aptos_coin::AptosCoin`.
+    pub type_args: Vec<String>,
+    /// Transaction arguments, e.g. `{"U64": 1}` or `{"Address": "0x1"}`.
+    pub args: Vec<TransactionArgument>,
+}
+
+impl ScriptArgs {
+    /// Loads the arguments of the script, falling back to the proposal id as the only argument
+    /// if the script has no arguments file.
+    pub fn load(
+        script_path: &Path,
+        proposal_id: u64,
+    ) -> Result<(Vec<TypeTag>, Vec<TransactionArgument>)> {
+        let path = script_path.with_extension(SCRIPT_ARGS_FILE_EXTENSION);
+        if !path.exists() {
+            return Ok((vec![], vec![TransactionArgument::U64(proposal_id)]));
+        }
+        let bytes = std::fs::read(&path)
+            .with_context(|| format!("failed to read script arguments {}", path.display()))?;
+        let script_args: Self = serde_json::from_slice(&bytes)
+            .with_context(|| format!("failed to parse script arguments {}", path.display()))?;
+        let type_args = script_args
+            .type_args
+            .iter()
+            .map(|type_arg| {
+                type_arg.parse::<TypeTag>().with_context(|| {
+                    format!("invalid type argument {} in {}", type_arg, path.display())
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok((type_args, script_args.args))
+    }
+}
+
+/***************************************************************************************************
+ * Simulation Results
+ *
+ **************************************************************************************************/
+/// Gas and storage fees charged for executing a governance script.
+#[derive(Clone, Debug, Serialize)]
+pub struct FeeStatementRecord {
+    /// Total gas units charged, including the storage fees converted to gas units.
+    pub gas_used: u64,
+    pub execution_gas_units: u64,
+    pub io_gas_units: u64,
+    pub storage_fee_octas: u64,
+    pub storage_fee_refund_octas: u64,
+}
+
+impl FeeStatementRecord {
+    fn print(&self, group_digits: bool) {
+        info!("        Fee statement:");
+        for (name, value) in [
+            ("Gas used", self.gas_used),
+            ("Execution gas units", self.execution_gas_units),
+            ("IO gas units", self.io_gas_units),
+            ("Storage fee (octas)", self.storage_fee_octas),
+            ("Storage fee refund (octas)", self.storage_fee_refund_octas),
+        ] {
+            info!(
+                "            {}: {}",
+                name,
+                format_number(value, group_digits)
+            );
+        }
+    }
+}
+
+impl From<&FeeStatement> for FeeStatementRecord {
+    fn from(fee_statement: &FeeStatement) -> Self {
+        Self {
+            gas_used: fee_statement.gas_used(),
+            execution_gas_units: fee_statement.execution_gas_used(),
+            io_gas_units: fee_statement.io_gas_used(),
+            storage_fee_octas: fee_statement.storage_fee_used(),
+            storage_fee_refund_octas: fee_statement.storage_fee_refund(),
+        }
+    }
+}
+
+/// An event emitted by a governance script.
+#[derive(Clone, Debug, Serialize)]
+pub struct CapturedEvent {
+    pub type_tag: String,
+    /// BCS-encoded event payload.
+    pub data: Vec<u8>,
+}
+
+/// Prints the given events along with their payloads, decoded using the module layouts in the
+/// state view. Payloads that cannot be decoded are printed as hex instead.
+fn print_events<'a>(
+    state_view: &impl StateView,
+    events: impl IntoIterator<Item = &'a ContractEvent>,
+) {
+    let annotator = AptosValueAnnotator::new(state_view);
+    for (idx, event) in events.into_iter().enumerate() {
+        info!(
+            "        Event {}: {}",
+            idx + 1,
+            event.type_tag().to_canonical_string()
+        );
+        let decoded = match annotator.view_value(event.type_tag(), event.event_data()) {
+            Ok(value) => value.to_string(),
+            Err(err) => format!(
+                "0x{} (failed to decode: {})",
+                hex::encode(event.event_data()),
+                err
+            ),
+        };
+        for line in decoded.lines() {
+            info!("            {}", line);
+        }
+    }
+}
+
+/// A function called directly by a governance script.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScriptAction {
+    pub function: String,
+    /// Execution cost of the call, including nested calls, in internal gas units.
+    pub execution_cost: u64,
+}
+
+/// Number of state items a script created, modified and deleted.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct WriteSetSummary {
+    pub num_creations: usize,
+    pub num_modifications: usize,
+    pub num_deletions: usize,
+    /// Total size of the written values, not counting metadata.
+    pub bytes_written: usize,
+}
+
+impl WriteSetSummary {
+    fn new(write_set: &WriteSet) -> Self {
+        let mut summary = Self::default();
+        for (_, write_op) in write_set.iter() {
+            match write_op.write_op_kind() {
+                WriteOpKind::Creation => summary.num_creations += 1,
+                WriteOpKind::Modification => summary.num_modifications += 1,
+                WriteOpKind::Deletion => summary.num_deletions += 1,
+            }
+            summary.bytes_written += write_op.bytes_size();
+        }
+        summary
+    }
+
+    pub fn num_write_ops(&self) -> usize {
+        self.num_creations + self.num_modifications + self.num_deletions
+    }
+}
+
+impl std::fmt::Display for WriteSetSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} write ops ({} created, {} modified, {} deleted), {} bytes written",
+            self.num_write_ops(),
+            self.num_creations,
+            self.num_modifications,
+            self.num_deletions,
+            self.bytes_written
+        )
+    }
+}
+
+/// The outcome of simulating a single governance script.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScriptResult {
+    pub script_name: String,
+    pub status: TransactionStatus,
+    /// The gas feature version the script was executed with.
+    pub gas_feature_version: u64,
+    pub fee_statement: FeeStatementRecord,
+    pub write_set_summary: WriteSetSummary,
+    /// Whether the script started a new epoch, or DKG for one.
+    pub triggered_reconfiguration: bool,
+    /// Total number of events emitted by the script.
+    pub num_events: usize,
+    /// The emitted events, truncated to the first `max_events_per_script`.
+    pub events: Vec<CapturedEvent>,
+    /// The arguments the patched resolve function received, if they were recorded.
+    pub resolve_args: Option<ResolveArgs>,
+    /// The functions called directly by the script, if they were recorded.
+    pub actions: Vec<ScriptAction>,
+    /// The modules the script published or upgraded.
+    pub published_modules: Vec<String>,
+    /// The directory the HTML gas report of the script was written to, if the gas profiler was
+    /// on.
+    pub gas_report_path: Option<PathBuf>,
+    /// The path the text gas report of the script was written to, if requested.
+    pub text_gas_report_path: Option<PathBuf>,
+    /// Wall-clock time spent compiling the script, if it was compiled as part of the run.
+    pub compile_time: Option<Duration>,
+    /// Wall-clock time spent executing the script, including the setup of its transaction.
+    pub execution_time: Duration,
+    /// The directory the HTML gas report of the forced epoch change before the script was
+    /// written to, if it was profiled.
+    pub epoch_change_gas_report_path: Option<PathBuf>,
+    /// The path the text gas report of the forced epoch change before the script was written to,
+    /// if it was profiled and requested.
+    pub epoch_change_text_gas_report_path: Option<PathBuf>,
+}
+
+impl ScriptResult {
+    /// Returns true if some of the emitted events were not retained.
+    pub fn events_truncated(&self) -> bool {
+        self.events.len() < self.num_events
+    }
+}
+
+/// Records the time each script took to compile in the results of the scripts, in order.
+fn record_compile_times(script_results: &mut [ScriptResult], compile_times: &[Duration]) {
+    for (script_result, compile_time) in script_results.iter_mut().zip(compile_times) {
+        script_result.compile_time = Some(*compile_time);
+    }
+}
+
+/// Formats a number, optionally grouping its digits with thousands separators, e.g. `1,234,567`.
+fn format_number(n: impl Into<u128>, group_digits: bool) -> String {
+    let digits = n.into().to_string();
+    if !group_digits {
+        return digits;
+    }
+
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (idx, digit) in digits.chars().enumerate() {
+        if idx > 0 && (digits.len() - idx) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Name of the file in the proposal directory the simulation report is written to in JSON
+/// output mode.
+const SIMULATION_REPORT_FILE_NAME: &str = "simulation.json";
+
+/// Name of the file in a proposal directory the state changes of the simulation are exported to,
+/// with the extension of the overlay format.
+const OVERLAY_FILE_STEM: &str = "overlay";
+
+/// Name of the file in a proposal directory the state keys read from the remote state are written
+/// to.
+const REMOTE_READS_FILE_NAME: &str = "remote_reads.txt";
+
+/// Directory in the proposal directory the patched framework modules are dumped to, one
+/// subdirectory per script, named after its index and file stem, e.g. `0-features`.
+const PATCHED_MODULES_DIR_NAME: &str = "patched-modules";
+
+/// Directory in the proposal directory the gas reports are written to, one subdirectory per
+/// script.
+const GAS_PROFILING_DIR_NAME: &str = "gas-profiling";
+
+/// Name of the page in the gas profiling directory that links the reports of all scripts.
+const GAS_REPORT_INDEX_FILE_NAME: &str = "index.html";
+
+/// Name of the file in a proposal directory the errors of the scripts that failed to compile are
+/// written to.
+const COMPILE_ERRORS_FILE_NAME: &str = "compile_errors.txt";
+
+/// Where a proposal was simulated, so that archived results remain self-describing.
+#[derive(Clone, Debug, Serialize)]
+pub struct SimulationContext {
+    /// Version of the remote state the simulation started from, or `None` for a local genesis.
+    pub version: Option<u64>,
+    pub chain_id: u8,
+    /// Epoch before the first script was executed.
+    pub epoch_before: u64,
+    /// Epoch reached after all scripts were executed, including the reconfigurations forced by
+    /// the simulation, or `None` if the simulation failed.
+    pub epoch_after: Option<u64>,
+}
+
+impl SimulationContext {
+    /// Records the context of a simulation before any script is executed.
+    fn new(session: &SimulationSession<RemoteStateView>) -> Result<Self> {
+        Ok(Self {
+            version: session.state_view.remote().version(),
+            chain_id: ChainIdResource::fetch_config(session.state_view())
+                .context("failed to fetch chain id")?
+                .chain_id()
+                .id(),
+            epoch_before: fetch_epoch(&session.state_view)?,
+            epoch_after: None,
+        })
+    }
+}
+
+impl std::fmt::Display for SimulationContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "chain id {}, ", self.chain_id)?;
+        match self.version {
+            Some(version) => write!(f, "version {}", version)?,
+            None => write!(f, "local genesis")?,
+        }
+        write!(f, ", epoch {}", self.epoch_before)?;
+        if let Some(epoch_after) = self.epoch_after {
+            write!(f, " -> {}", epoch_after)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of simulating a proposal.
+#[derive(Clone, Debug, Serialize)]
+pub struct SimulationReport {
+    pub proposal_dir: PathBuf,
+    pub context: SimulationContext,
+    pub scripts: Vec<ScriptResult>,
+}
+
+impl SimulationReport {
+    /// Returns the total gas units used by the scripts of the proposal.
+    pub fn total_gas_used(&self) -> u64 {
+        total_gas_used(&self.scripts)
+    }
+
+    /// Writes the report as JSON to the given path.
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("failed to write simulation report to {}", path.display()))?;
+        info!("Simulation report saved to {}", path.display());
+        Ok(())
+    }
+
+    /// Returns the total time spent compiling and executing the scripts of the proposal.
+    pub fn total_time(&self) -> Duration {
+        self.scripts
+            .iter()
+            .map(|script| script.compile_time.unwrap_or_default() + script.execution_time)
+            .sum()
+    }
+
+    /// Prints a summary of the outcome and timings of each script, followed by the total gas
+    /// used and time spent.
+    pub fn print_human_readable(&self, group_digits: bool) {
+        info!("Summary of {}:", self.proposal_dir.display());
+        info!("    Simulated on {}", self.context);
+        for script in &self.scripts {
+            let compile_time = match script.compile_time {
+                Some(compile_time) => format!("compiled in {:.2}s, ", compile_time.as_secs_f64()),
+                None => String::new(),
+            };
+            info!(
+                "    {}: {:?}, {} gas units, {}, {}executed in {:.2}s",
+                script.script_name,
+                script.status,
+                format_number(script.fee_statement.gas_used, group_digits),
+                script.write_set_summary,
+                compile_time,
+                script.execution_time.as_secs_f64()
+            );
+        }
+        info!(
+            "Total gas used: {}",
+            format_number(self.total_gas_used(), group_digits)
+        );
+        info!("Total time: {:.2}s", self.total_time().as_secs_f64());
+    }
+
+    /// Renders an HTML page listing the fee statement of each script, with links to their
+    /// detailed gas reports.
+    pub fn to_gas_report_index_html(&self, group_digits: bool) -> String {
+        let gas_profiling_dir = self.proposal_dir.join(GAS_PROFILING_DIR_NAME);
+
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n");
+        html.push_str("  <meta charset=\"utf-8\">\n");
+        html.push_str(&format!(
+            "  <title>Gas Reports - {}</title>\n",
+            escape_xml(&self.proposal_dir.display().to_string())
+        ));
+        html.push_str("</head>\n<body>\n");
+        html.push_str(&format!(
+            "  <h1>Gas Reports - {}</h1>\n",
+            escape_xml(&self.proposal_dir.display().to_string())
+        ));
+        html.push_str("  <table border=\"1\" cellpadding=\"4\">\n");
+        html.push_str(
+            "    <tr><th>Script</th><th>Status</th><th>Gas used</th>\
+             <th>Execution gas units</th><th>IO gas units</th><th>Storage fee (octas)</th>\
+             <th>Storage fee refund (octas)</th><th>Report</th></tr>\n",
+        );
+        for script in &self.scripts {
+            let fees = &script.fee_statement;
+            // Link the reports relative to the index, so that the directory can be moved around.
+            let report_link = match (&script.gas_report_path, &script.text_gas_report_path) {
+                (Some(path), _) => {
+                    let index_path = path
+                        .strip_prefix(&gas_profiling_dir)
+                        .unwrap_or(path)
+                        .join("index.html");
+                    format!(
+                        "<a href=\"{}\">report</a>",
+                        escape_xml(&index_path.display().to_string())
+                    )
+                },
+                (None, Some(path)) => {
+                    let text_path = path.strip_prefix(&gas_profiling_dir).unwrap_or(path);
+                    format!(
+                        "<a href=\"{}\">report</a>",
+                        escape_xml(&text_path.display().to_string())
+                    )
+                },
+                (None, None) => "-".to_string(),
+            };
+            html.push_str(&format!(
+                "    <tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+                 <td>{}</td><td>{}</td></tr>\n",
+                escape_xml(&script.script_name),
+                escape_xml(&format!("{:?}", script.status)),
+                format_number(fees.gas_used, group_digits),
+                format_number(fees.execution_gas_units, group_digits),
+                format_number(fees.io_gas_units, group_digits),
+                format_number(fees.storage_fee_octas, group_digits),
+                format_number(fees.storage_fee_refund_octas, group_digits),
+                report_link
+            ));
+        }
+        html.push_str(&format!(
+            "    <tr><th>Total</th><td></td><th>{}</th><td></td><td></td><td></td><td></td>\
+             <td></td></tr>\n",
+            format_number(self.total_gas_used(), group_digits)
+        ));
+        html.push_str("  </table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Writes the gas report index to the gas profiling directory of the proposal and returns
+    /// its path.
+    pub fn write_gas_report_index(&self, group_digits: bool) -> Result<PathBuf> {
+        let gas_profiling_dir = self.proposal_dir.join(GAS_PROFILING_DIR_NAME);
+        std::fs::create_dir_all(&gas_profiling_dir).with_context(|| {
+            format!(
+                "failed to create gas profiling directory {}",
+                gas_profiling_dir.display()
+            )
+        })?;
+
+        let index_path = gas_profiling_dir.join(GAS_REPORT_INDEX_FILE_NAME);
+        std::fs::write(&index_path, self.to_gas_report_index_html(group_digits)).with_context(
+            || {
+                format!(
+                    "failed to write gas report index to {}",
+                    index_path.display()
+                )
+            },
+        )?;
+        info!("Gas report index saved to {}", index_path.display());
+        Ok(index_path)
+    }
+}
+
+/// Returns the total gas units used by the given scripts.
+fn total_gas_used(script_results: &[ScriptResult]) -> u64 {
+    script_results
+        .iter()
+        .map(|result| result.fee_statement.gas_used)
+        .sum()
+}
+
+/***************************************************************************************************
+ * Framework Version
+ *
+ **************************************************************************************************/
+/// Provenance of the local framework the scripts are compiled against.
+#[derive(Clone, Debug, Serialize)]
+pub struct FrameworkVersion {
+    pub package_name: String,
+    pub package_version: String,
+    /// The commit the framework is checked out at, if it is part of a git repository.
+    pub commit: Option<String>,
+    /// Whether the framework has uncommitted changes relative to the commit.
+    pub dirty: bool,
+}
+
+impl std::fmt::Display for FrameworkVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} v{}", self.package_name, self.package_version)?;
+        match &self.commit {
+            Some(commit) if self.dirty => write!(f, " (commit {}, with local changes)", commit),
+            Some(commit) => write!(f, " (commit {})", commit),
+            None => write!(f, " (unknown commit)"),
+        }
+    }
+}
+
+/// Returns the commit the given directory is checked out at, and whether the directory has
+/// uncommitted changes, or `None` if it is not part of a git repository.
+fn read_git_commit(dir: &Path) -> Result<Option<(String, bool)>> {
+    let Ok(repository) = Repository::discover(dir) else {
+        return Ok(None);
+    };
+    let commit = repository
+        .head()?
+        .peel_to_commit()
+        .context("failed to resolve HEAD to a commit")?;
+
+    let mut dirty = false;
+    if let Some(workdir) = repository.workdir() {
+        let canonical_dir = dir.canonicalize()?;
+        let relative_dir = canonical_dir
+            .strip_prefix(workdir.canonicalize()?)
+            .unwrap_or(&canonical_dir);
+        let mut status_options = StatusOptions::new();
+        status_options
+            .include_untracked(true)
+            .pathspec(relative_dir);
+        dirty = !repository.statuses(Some(&mut status_options))?.is_empty();
+    }
+
+    Ok(Some((commit.id().to_string(), dirty)))
+}
+
+/// Reads the package version and the commit of the framework at the given path.
+pub fn read_framework_version(framework_path: &Path) -> Result<FrameworkVersion> {
+    let manifest = parse_move_manifest_from_file(framework_path).with_context(|| {
+        format!(
+            "failed to read framework manifest at {}",
+            framework_path.display()
+        )
+    })?;
+    let (major, minor, patch) = manifest.package.version;
+    let git_commit = read_git_commit(framework_path)
+        .with_context(|| format!("failed to read git commit of {}", framework_path.display()))?;
+
+    Ok(FrameworkVersion {
+        package_name: manifest.package.name.to_string(),
+        package_version: format!("{}.{}.{}", major, minor, patch),
+        commit: git_commit.as_ref().map(|(commit, _)| commit.clone()),
+        dirty: git_commit.is_some_and(|(_, dirty)| dirty),
+    })
+}
+
+/***************************************************************************************************
+ * Release Report
+ *
+ **************************************************************************************************/
+/// Outcome of simulating a single proposal of a release.
+#[derive(Debug)]
+pub struct ProposalReport {
+    pub proposal_dir: PathBuf,
+    /// Time spent compiling and simulating the proposal.
+    pub duration: Duration,
+    pub script_results: Vec<ScriptResult>,
+    /// The error the simulation failed with, if any.
+    pub failure: Option<String>,
+    /// Whether the proposal was not simulated, because another proposal failed to compile or an
+    /// earlier one failed to execute.
+    pub skipped: bool,
+    /// Where the proposal was simulated, if it got as far as connecting to the remote state.
+    pub context: Option<SimulationContext>,
+}
+
+impl ProposalReport {
+    /// Returns a report for a proposal that was not simulated.
+    fn skipped(proposal_dir: &Path) -> Self {
+        Self {
+            proposal_dir: proposal_dir.to_path_buf(),
+            duration: Duration::ZERO,
+            script_results: vec![],
+            failure: None,
+            skipped: true,
+            context: None,
+        }
+    }
+
+    /// Returns true if the proposal was simulated successfully.
+    pub fn succeeded(&self) -> bool {
+        self.failure.is_none() && !self.skipped
+    }
+}
+
+/// Outcome of simulating all proposals of a release.
+#[derive(Debug, Default)]
+pub struct ReleaseSimulationReport {
+    /// The framework the scripts were compiled against.
+    pub framework_version: Option<FrameworkVersion>,
+    pub proposals: Vec<ProposalReport>,
+    /// The reason the release exceeded its gas budget, if it did.
+    pub gas_budget_failure: Option<String>,
+}
+
+/// Name of the manifest of the files produced by a simulation run, written to the release
+/// directory.
+const ARTIFACTS_MANIFEST_FILE_NAME: &str = "artifacts.json";
+
+/// Kind of file produced by a simulation run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    /// A directory containing the HTML gas report of a script.
+    GasReport,
+    /// The plain-text gas report of a script.
+    TextGasReport,
+    /// A directory containing the HTML gas report of the forced epoch change before a script.
+    EpochChangeGasReport,
+    /// The plain-text gas report of the forced epoch change before a script.
+    EpochChangeTextGasReport,
+    /// The HTML page linking the gas reports of the scripts of a proposal.
+    GasReportIndex,
+    /// The functions executed by the scripts of a proposal.
+    Coverage,
+    /// The JUnit XML report of the whole release.
+    JunitReport,
+    /// The JSON simulation report of a proposal.
+    SimulationReport,
+    /// The state items changed by simulating a proposal, BCS-encoded.
+    Overlay,
+    /// The diff of the resources changed by a proposal.
+    ResourceDiff,
+    /// The state keys read from the remote state by simulating a proposal.
+    RemoteReads,
+    /// The remote state values read by simulating a proposal, BCS-encoded.
+    StateSnapshot,
+    /// The errors of the scripts of a proposal that failed to compile.
+    CompileErrors,
+    /// A directory containing the governance modules as patched for a script.
+    PatchedModules,
+}
+
+/// A file produced by a simulation run.
+#[derive(Clone, Debug, Serialize)]
+pub struct Artifact {
+    pub path: PathBuf,
+    pub kind: ArtifactKind,
+    /// The proposal the artifact belongs to, if any.
+    pub proposal: Option<PathBuf>,
+    /// The script the artifact belongs to, if any.
+    pub script: Option<String>,
+}
+
+/// Closes an opened JUnit test case with the given failure.
+fn push_junit_failure(xml: &mut String, failure: &str) {
+    let message = failure.lines().next().unwrap_or_default();
+    xml.push_str(&format!(
+        ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+        escape_xml(message),
+        escape_xml(failure)
+    ));
+}
+
+/// Escapes a string for use in XML text and attribute values.
+///
+/// Characters that XML 1.0 does not allow at all, such as the escape codes of colored compiler
+/// output, cannot be escaped and are replaced with U+FFFD instead.
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {
+                escaped.push(char::REPLACEMENT_CHARACTER)
+            },
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl ReleaseSimulationReport {
+    pub fn num_failures(&self) -> usize {
+        self.proposals
+            .iter()
+            .filter(|proposal| proposal.failure.is_some())
+            .count()
+    }
+
+    pub fn num_skipped(&self) -> usize {
+        self.proposals
+            .iter()
+            .filter(|proposal| proposal.skipped)
+            .count()
+    }
+
+    /// Renders the report as JUnit XML, with one test case per proposal, plus one for the gas
+    /// budget of the release if it was exceeded.
+    pub fn to_junit_xml(&self) -> String {
+        let total_duration = self
+            .proposals
+            .iter()
+            .map(|proposal| proposal.duration)
+            .sum::<Duration>();
+        let num_budget_failures = usize::from(self.gas_budget_failure.is_some());
+        let num_tests = self.proposals.len() + num_budget_failures;
+        let num_failures = self.num_failures() + num_budget_failures;
+
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+            num_tests,
+            num_failures,
+            self.num_skipped(),
+            total_duration.as_secs_f64()
+        ));
+        xml.push_str(&format!(
+            "  <testsuite name=\"release-simulation\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" \
+             time=\"{:.3}\">\n",
+            num_tests,
+            num_failures,
+            self.num_skipped(),
+            total_duration.as_secs_f64()
+        ));
+        if let Some(framework_version) = &self.framework_version {
+            xml.push_str("    <properties>\n");
+            xml.push_str(&format!(
+                "      <property name=\"framework_version\" value=\"{}\"/>\n",
+                escape_xml(&framework_version.to_string())
+            ));
+            xml.push_str("    </properties>\n");
+        }
+
+        for proposal in &self.proposals {
+            xml.push_str(&format!(
+                "    <testcase classname=\"release-simulation\" name=\"{}\" time=\"{:.3}\"",
+                escape_xml(&proposal.proposal_dir.display().to_string()),
+                proposal.duration.as_secs_f64()
+            ));
+            match &proposal.failure {
+                Some(failure) => push_junit_failure(&mut xml, failure),
+                None if proposal.skipped => xml.push_str(
+                    ">\n      <skipped message=\"another proposal failed\"/>\n    </testcase>\n",
+                ),
+                None => xml.push_str("/>\n"),
+            }
+        }
+        if let Some(failure) = &self.gas_budget_failure {
+            xml.push_str("    <testcase classname=\"release-simulation\" name=\"gas budget\"");
+            push_junit_failure(&mut xml, failure);
+        }
+
+        xml.push_str("  </testsuite>\n");
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+
+    /// Lists the files produced by the simulation run, given the options it was run with.
+    pub fn artifacts(&self, options: &SimulationOptions) -> Vec<Artifact> {
+        let mut artifacts = vec![];
+        for proposal in &self.proposals {
+            for script_result in &proposal.script_results {
+                if let Some(gas_report_path) = &script_result.gas_report_path {
+                    artifacts.push(Artifact {
+                        path: gas_report_path.clone(),
+                        kind: ArtifactKind::GasReport,
+                        proposal: Some(proposal.proposal_dir.clone()),
+                        script: Some(script_result.script_name.clone()),
+                    });
+                }
+                if let Some(gas_report_path) = &script_result.epoch_change_gas_report_path {
+                    artifacts.push(Artifact {
+                        path: gas_report_path.clone(),
+                        kind: ArtifactKind::EpochChangeGasReport,
+                        proposal: Some(proposal.proposal_dir.clone()),
+                        script: Some(script_result.script_name.clone()),
+                    });
+                }
+                if let Some(text_gas_report_path) = &script_result.epoch_change_text_gas_report_path
+                {
+                    artifacts.push(Artifact {
+                        path: text_gas_report_path.clone(),
+                        kind: ArtifactKind::EpochChangeTextGasReport,
+                        proposal: Some(proposal.proposal_dir.clone()),
+                        script: Some(script_result.script_name.clone()),
+                    });
+                }
+                if let Some(text_gas_report_path) = &script_result.text_gas_report_path {
+                    artifacts.push(Artifact {
+                        path: text_gas_report_path.clone(),
+                        kind: ArtifactKind::TextGasReport,
+                        proposal: Some(proposal.proposal_dir.clone()),
+                        script: Some(script_result.script_name.clone()),
+                    });
+                }
+            }
+            if options.profile_gas && !proposal.script_results.is_empty() {
+                artifacts.push(Artifact {
+                    path: proposal
+                        .proposal_dir
+                        .join(GAS_PROFILING_DIR_NAME)
+                        .join(GAS_REPORT_INDEX_FILE_NAME),
+                    kind: ArtifactKind::GasReportIndex,
+                    proposal: Some(proposal.proposal_dir.clone()),
+                    script: None,
+                });
+            }
+            if options.output_format == OutputFormat::Json && proposal.succeeded() {
+                artifacts.push(Artifact {
+                    path: proposal.proposal_dir.join(SIMULATION_REPORT_FILE_NAME),
+                    kind: ArtifactKind::SimulationReport,
+                    proposal: Some(proposal.proposal_dir.clone()),
+                    script: None,
+                });
+            }
+            if options.resource_diff && proposal.succeeded() {
+                artifacts.push(Artifact {
+                    path: proposal.proposal_dir.join(RESOURCE_DIFF_FILE_NAME),
+                    kind: ArtifactKind::ResourceDiff,
+                    proposal: Some(proposal.proposal_dir.clone()),
+                    script: None,
+                });
+            }
+            if options.export_overlay && proposal.succeeded() {
+                artifacts.push(Artifact {
+                    path: proposal
+                        .proposal_dir
+                        .join(OVERLAY_FILE_STEM)
+                        .with_extension(options.overlay_format.extension()),
+                    kind: ArtifactKind::Overlay,
+                    proposal: Some(proposal.proposal_dir.clone()),
+                    script: None,
+                });
+            }
+            // The state snapshot and the remote reads are also written if the proposal failed.
+            let state_snapshot_path = proposal.proposal_dir.join(STATE_SNAPSHOT_FILE_NAME);
+            if options.record_state_snapshot && state_snapshot_path.exists() {
+                artifacts.push(Artifact {
+                    path: state_snapshot_path,
+                    kind: ArtifactKind::StateSnapshot,
+                    proposal: Some(proposal.proposal_dir.clone()),
+                    script: None,
+                });
+            }
+            let remote_reads_path = proposal.proposal_dir.join(REMOTE_READS_FILE_NAME);
+            if options.record_remote_reads && remote_reads_path.exists() {
+                artifacts.push(Artifact {
+                    path: remote_reads_path,
+                    kind: ArtifactKind::RemoteReads,
+                    proposal: Some(proposal.proposal_dir.clone()),
+                    script: None,
+                });
+            }
+            // The patched modules are dumped before they are verified, so also for failed proposals.
+            if options.dump_patched_modules {
+                let patched_modules_dir = proposal.proposal_dir.join(PATCHED_MODULES_DIR_NAME);
+                let mut script_dirs = std::fs::read_dir(&patched_modules_dir)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| Some(entry.ok()?.path()))
+                    .filter(|path| path.is_dir())
+                    .collect::<Vec<_>>();
+                script_dirs.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+                for script_dir in script_dirs {
+                    artifacts.push(Artifact {
+                        path: script_dir,
+                        kind: ArtifactKind::PatchedModules,
+                        proposal: Some(proposal.proposal_dir.clone()),
+                        script: None,
+                    });
+                }
+            }
+            let compile_errors_path = proposal.proposal_dir.join(COMPILE_ERRORS_FILE_NAME);
+            if proposal.failure.is_some() && compile_errors_path.exists() {
+                artifacts.push(Artifact {
+                    path: compile_errors_path,
+                    kind: ArtifactKind::CompileErrors,
+                    proposal: Some(proposal.proposal_dir.clone()),
+                    script: None,
+                });
+            }
+            // The coverage is only written once the whole proposal succeeded.
+            if options.capture_coverage && proposal.succeeded() {
+                artifacts.push(Artifact {
+                    path: proposal.proposal_dir.join(COVERAGE_FILE_NAME),
+                    kind: ArtifactKind::Coverage,
+                    proposal: Some(proposal.proposal_dir.clone()),
+                    script: None,
+                });
+            }
+        }
+        if let Some(junit_report_path) = &options.junit_report_path {
+            artifacts.push(Artifact {
+                path: junit_report_path.clone(),
+                kind: ArtifactKind::JunitReport,
+                proposal: None,
+                script: None,
+            });
+        }
+        artifacts
+    }
+
+    /// Writes the JUnit report, if configured, and the manifest of all produced artifacts.
+    fn write_outputs(&self, release_dir: &Path, options: &SimulationOptions) -> Result<()> {
+        if let Some(junit_report_path) = &options.junit_report_path {
+            self.write_junit_xml(junit_report_path)?;
+        }
+
+        let manifest_path = release_dir.join(ARTIFACTS_MANIFEST_FILE_NAME);
+        let manifest = serde_json::to_string_pretty(&self.artifacts(options))?;
+        std::fs::write(&manifest_path, manifest).with_context(|| {
+            format!(
+                "failed to write artifacts manifest to {}",
+                manifest_path.display()
+            )
+        })?;
+        info!("Artifacts manifest saved to {}", manifest_path.display());
+        Ok(())
+    }
+
+    /// Writes the report as JUnit XML to the given path.
+    pub fn write_junit_xml(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_junit_xml())
+            .with_context(|| format!("failed to write JUnit report to {}", path.display()))?;
+        info!("JUnit report saved to {}", path.display());
+        Ok(())
+    }
+}
+
+/***************************************************************************************************
+ * Simulation Workflow
+ *
+ **************************************************************************************************/
+/// Reads the raw values of the on-chain configs an `AptosEnvironment` is derived from, which
+/// identifies the environment without having to construct it.
+///
+/// This mirrors the configs read by `Environment::new` in `aptos-vm-environment`, and
+/// additionally includes the storage gas config, which is read when pricing storage. The
+/// reconfiguration time is only used to derive the timed features, and changes with every epoch,
+/// so the timed features are included instead, to be able to reuse the environment across epochs.
+fn read_environment_configs(state_view: &impl StateView) -> Result<Vec<Option<Vec<u8>>>> {
+    let mut configs = [
+        StateKey::on_chain_config::<Features>()?,
+        StateKey::on_chain_config::<ChainIdResource>()?,
+        StateKey::on_chain_config::<GasScheduleV2>()?,
+        StateKey::on_chain_config::<GasSchedule>()?,
+        StateKey::on_chain_config::<StorageGasSchedule>()?,
+    ]
+    .iter()
+    .map(|state_key| {
+        Ok(state_view
+            .get_state_value_bytes(state_key)
+            .with_context(|| format!("failed to read {:?}", state_key))?
+            .map(|bytes| bytes.to_vec()))
+    })
+    .collect::<Result<Vec<_>>>()?;
+
+    let chain_id = ChainId::fetch_config(state_view).unwrap_or_else(ChainId::test);
+    let timestamp_micros = ConfigurationResource::fetch_config(state_view)
+        .map(|config| config.last_reconfiguration_time_micros())
+        .unwrap_or(0);
+    let timed_features = TimedFeaturesBuilder::new(chain_id, timestamp_micros).build();
+    configs.push(Some(bcs::to_bytes(&timed_features)?));
+    Ok(configs)
+}
+
+/// Executes a function in a new session, bypassing its visibility, and returns the resulting
+/// write set without applying it.
+fn execute_function_unmetered(
+    state_view: &SimulationStateView<impl StateView>,
+    module_id: &ModuleId,
+    func_name: &IdentStr,
+    ty_args: Vec<TypeTag>,
+    args: Vec<MoveValue>,
+) -> Result<WriteSet> {
+    let env = AptosEnvironment::new_with_injected_create_signer_for_gov_sim(&state_view);
+    execute_function_with_gas_meter(
+        state_view,
+        &env,
+        module_id,
+        func_name,
+        ty_args,
+        serialize_args(args),
+        &mut UnmeteredGasMeter,
+    )
+}
+
+/// Like [`execute_function_unmetered`], but charges gas like a governance script would and
+/// records a gas profile of the execution.
+///
+/// Only execution and IO gas is profiled, as there is no transaction to charge storage fees
+/// for.
+fn execute_function_profiled(
+    state_view: &SimulationStateView<impl StateView>,
+    module_id: &ModuleId,
+    func_name: &IdentStr,
+    ty_args: Vec<TypeTag>,
+    args: Vec<MoveValue>,
+    max_gas_amount: u64,
+) -> Result<(WriteSet, TransactionGasLog)> {
+    let env = AptosEnvironment::new_with_injected_create_signer_for_gov_sim(&state_view);
+    let gas_params = env
+        .gas_params()
+        .as_ref()
+        .map_err(|err| anyhow!("failed to load gas params: {}", err))?;
+    let storage_gas_params = env
+        .storage_gas_params()
+        .as_ref()
+        .map_err(|err| anyhow!("failed to load storage gas params: {}", err))?;
+
+    let gas_meter = make_prod_gas_meter(
+        env.gas_feature_version(),
+        gas_params.vm.clone(),
+        storage_gas_params.clone(),
+        /* is_approved_gov_script */ true,
+        max_gas_amount.into(),
+        &NoopBlockSynchronizationKillSwitch {},
+    );
+    let mut gas_profiler = GasProfiler::new_function(
+        gas_meter,
+        module_id.clone(),
+        func_name.to_owned(),
+        ty_args.clone(),
+    );
+
+    let write_set = execute_function_with_gas_meter(
+        state_view,
+        &env,
+        module_id,
+        func_name,
+        ty_args,
+        serialize_args(args),
+        &mut gas_profiler,
+    )?;
+
+    Ok((write_set, gas_profiler.finish()))
+}
+
+fn serialize_args(args: Vec<MoveValue>) -> Vec<Vec<u8>> {
+    args.into_iter()
+        .map(|arg| arg.simple_serialize().unwrap())
+        .collect()
+}
+
+/// Executes a function with already serialized arguments, charging the given gas meter, see
+/// [`execute_function_unmetered`].
+fn execute_function_with_gas_meter(
+    state_view: &SimulationStateView<impl StateView>,
+    env: &AptosEnvironment,
+    module_id: &ModuleId,
+    func_name: &IdentStr,
+    ty_args: Vec<TypeTag>,
+    args: Vec<Vec<u8>>,
+    gas_meter: &mut impl GasMeter,
+) -> Result<WriteSet> {
+    let vm = AptosVM::new(env, &state_view);
+    let resolver = state_view.as_move_resolver();
+    let module_storage = state_view.as_aptos_code_storage(env);
+
+    let gas_schedule =
+        GasScheduleV2::fetch_config(&state_view).context("failed to fetch gas schedule v2")?;
+    let gas_feature_version = gas_schedule.feature_version;
+
+    let change_set_configs =
+        ChangeSetConfigs::unlimited_at_gas_feature_version(gas_feature_version);
+
+    let traversal_storage = TraversalStorage::new();
+    let mut sess = vm.new_session(&resolver, SessionId::void(), None);
+    sess.execute_function_bypass_visibility(
+        module_id,
+        func_name,
+        ty_args,
+        args,
+        gas_meter,
+        &mut TraversalContext::new(&traversal_storage),
+        &module_storage,
+    )?;
+    let mut change_set = sess.finish(&change_set_configs, &module_storage)?;
+
+    change_set.try_materialize_aggregator_v1_delta_set(&resolver)?;
+    let (write_set, _events) = change_set
+        .try_combine_into_storage_change_set(ModuleWriteSet::empty())
+        .expect("Failed to convert to storage ChangeSet")
+        .into_inner();
+
+    Ok(write_set)
+}
+
+fn force_end_epoch(state_view: &SimulationStateView<impl StateView>) -> Result<()> {
+    let write_set = execute_function_unmetered(
+        state_view,
+        &MODULE_ID_APTOS_GOVERNANCE,
+        IdentStr::new("force_end_epoch").unwrap(),
+        vec![],
+        vec![MoveValue::Signer(AccountAddress::ONE)],
+    )?;
+    state_view.apply_write_set(write_set);
+
+    Ok(())
+}
+
+/// Like [`force_end_epoch`], but returns a gas profile of the reconfiguration.
+///
+/// Like the unprofiled path, the reconfiguration is not limited by the gas of the scripts, so
+/// that profiling cannot make it fail.
+fn force_end_epoch_profiled(
+    state_view: &SimulationStateView<impl StateView>,
+) -> Result<TransactionGasLog> {
+    let (write_set, gas_log) = execute_function_profiled(
+        state_view,
+        &MODULE_ID_APTOS_GOVERNANCE,
+        IdentStr::new("force_end_epoch").unwrap(),
+        vec![],
+        vec![MoveValue::Signer(AccountAddress::ONE)],
+        u64::MAX,
+    )?;
+    state_view.apply_write_set(write_set);
+
+    Ok(gas_log)
+}
+
+/// Modules under `0x1` whose resources are expected to change as a side effect of executing any
+/// transaction, mostly because of the forced epoch changes and the gas being charged.
+const VOLATILE_FRAMEWORK_MODULES: &[&str] = &[
+    "block",
+    "coin",
+    "reconfiguration",
+    "reconfiguration_state",
+    "stake",
+    "state_storage",
+    "storage_gas",
+    "transaction_fee",
+];
+
+/// A function executed before the first script of a proposal, e.g. to set up state the
+/// proposal depends on. Signer arguments can be passed as [`MoveValue::Signer`]. Code that needs
+/// to obtain signers itself can call `0x1::aptos_governance::create_signer` while the setup calls
+/// are executed, see [`inject_create_signer`].
+#[derive(Clone, Debug)]
+pub struct SetupCall {
+    pub module_id: ModuleId,
+    pub function: Identifier,
+    pub ty_args: Vec<TypeTag>,
+    pub args: Vec<MoveValue>,
+}
+
+/// Returns the deserializer config to load framework modules with, derived from the feature flags
+/// of the simulation options if overridden, or else from the ones in the state.
+fn load_deserializer_config(
+    state_view: &impl StateView,
+    options: &SimulationOptions,
+) -> Result<DeserializerConfig> {
+    Ok(match &options.deserializer_features_override {
+        Some(features) => aptos_prod_deserializer_config(features),
+        None => {
+            let features =
+                Features::fetch_config(state_view).context("failed to fetch feature flags")?;
+            aptos_prod_deserializer_config(&features)
+        },
+    })
+}
+
+/// Executes the setup calls in order, bypassing their visibility, and applies their changes.
+fn execute_setup_calls(
+    state_view: &SimulationStateView<impl StateView>,
+    setup_calls: &[SetupCall],
+) -> Result<()> {
+    for call in setup_calls {
+        let write_set = execute_function_unmetered(
+            state_view,
+            &call.module_id,
+            &call.function,
+            call.ty_args.clone(),
+            call.args.clone(),
+        )
+        .with_context(|| format!("failed to execute {}::{}", call.module_id, call.function))?;
+        state_view.apply_write_set(write_set);
+    }
+
+    Ok(())
+}
+
+/// Returns true if the write set publishes a module or changes a resource under `0x1` that is
+/// not expected to change as a side effect of executing any transaction, e.g. on-chain configs
+/// or buffered config changes.
+///
+/// Scripts that do neither are likely no-ops, e.g. because of a forgotten function call.
+fn touches_on_chain_config(write_set: &WriteSet, patch_plan: &PatchPlan) -> bool {
+    write_set.iter().any(|(state_key, _)| {
+        let StateKeyInner::AccessPath(path) = state_key.inner() else {
+            return false;
+        };
+        match path.get_path() {
+            access_path::Path::Code(_) => true,
+            access_path::Path::Resource(struct_tag)
+            | access_path::Path::ResourceGroup(struct_tag) => {
+                path.address == AccountAddress::ONE
+                    && !VOLATILE_FRAMEWORK_MODULES.contains(&struct_tag.module.as_str())
+                    && !(struct_tag.address == *patch_plan.governance_module.address()
+                        && struct_tag.module.as_ident_str() == patch_plan.governance_module.name()
+                        && struct_tag.name == *STRUCT_NAME_RESOLVE_ARGS)
+            },
+        }
+    })
+}
+
+/// Returns the modules published or upgraded by a write set, after checking that each of them
+/// passes the bytecode verifier, the same way patched modules are checked.
+///
+/// Compatibility with the previous version of an upgraded module is already enforced by the VM
+/// when publishing, so a module that got this far but fails to verify indicates a problem with
+/// the simulation rather than with the proposal. Catching it here prevents subsequent scripts
+/// from running against a broken framework.
+fn verify_published_modules(
+    write_set: &WriteSet,
+    deserializer_config: &DeserializerConfig,
+) -> Result<Vec<ModuleId>> {
+    let mut published_modules = vec![];
+    for (state_key, write_op) in write_set.iter() {
+        let StateKeyInner::AccessPath(path) = state_key.inner() else {
+            continue;
+        };
+        let access_path::Path::Code(module_id) = path.get_path() else {
+            continue;
+        };
+        let Some(blob) = write_op.bytes() else {
+            bail!("module {} was deleted", module_id);
+        };
+
+        let module = CompiledModule::deserialize_with_config(blob, deserializer_config)
+            .map_err(|err| anyhow!("failed to deserialize module {}: {:?}", module_id, err))?;
+        move_bytecode_verifier::verify_module(&module)
+            .map_err(|err| anyhow!("published module {} failed to verify: {:?}", module_id, err))?;
+        published_modules.push(module_id);
+    }
+    Ok(published_modules)
+}
+
+fn fetch_epoch(state_view: &SimulationStateView<impl StateView>) -> Result<u64> {
+    let config = ConfigurationResource::fetch_config(state_view)
+        .ok_or_else(|| anyhow!("failed to fetch the reconfiguration config"))?;
+    Ok(config.epoch())
+}
+
+/// Determines how the simulation results are output, besides the progress printed to stdout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Only print the results.
+    #[default]
+    Human,
+    /// Additionally write the results of each proposal as JSON to `simulation.json` in the
+    /// proposal directory.
+    Json,
+}
+
+/// Determines how the APT balance of the simulated sender account is stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SenderFunding {
+    /// Follow the on-chain coin-to-fungible-asset migration state.
+    #[default]
+    Auto,
+    /// Fund the sender through a `0x1::coin::CoinStore<AptosCoin>`.
+    CoinStore,
+    /// Fund the sender through its primary APT `0x1::fungible_asset::FungibleStore`.
+    FungibleStore,
+}
+
+/// The account the proposal scripts are sent from.
+#[derive(Clone, Debug, Default)]
+pub enum SenderAccount {
+    /// An account derived from a fixed seed.
+    #[default]
+    Seeded,
+    /// An account at the given address, with a key pair derived from a fixed seed. An existing
+    /// account is taken over with its sequence number and balance, by replacing its key.
+    Address(AccountAddress),
+    /// The given account, including its balance and sequence number, which must not exist yet.
+    Account(AccountData),
+}
+
+/// Determines how failed assertions, e.g. exceeded gas budgets, are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AssertionMode {
+    /// Stop the simulation at the first failed assertion.
+    FailFast,
+    /// Keep simulating and report all failed assertions at the end.
+    #[default]
+    CollectAll,
+}
+
+/// Keeps track of the failed assertions of a simulation according to the assertion mode.
+struct AssertionFailures {
+    mode: AssertionMode,
+    failures: Vec<String>,
+}
+
+impl AssertionFailures {
+    fn new(mode: AssertionMode) -> Self {
+        Self {
+            mode,
+            failures: vec![],
+        }
+    }
+
+    /// Records a failed assertion. In fail-fast mode, this returns the failure as an error.
+    fn fail(&mut self, message: String) -> Result<()> {
+        error!("Assertion failed: {}", message);
+        match self.mode {
+            AssertionMode::FailFast => bail!(message),
+            AssertionMode::CollectAll => {
+                self.failures.push(message);
+                Ok(())
+            },
+        }
+    }
+
+    /// Returns an error listing all failed assertions, if there are any.
+    fn finish(self) -> Result<()> {
+        match self.failures.len() {
+            0 => Ok(()),
+            1 => bail!(self.failures.into_iter().next().unwrap()),
+            num_failures => bail!(
+                "{} assertions failed:\n{}",
+                num_failures,
+                self.failures
+                    .iter()
+                    .map(|failure| format!("    {}", failure))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+        }
+    }
+}
+
+/// Determines whether exceeding a limit fails the simulation or only prints a warning.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LimitAction {
+    /// Print a warning and continue.
+    Warn,
+    /// Treat the violation as a failed assertion.
+    #[default]
+    Fail,
+}
+
+/// Checks that the write set of a script does not exceed the configured number of write ops.
+fn check_write_op_limit(
+    script_name: &str,
+    num_write_ops: usize,
+    max_write_ops: Option<usize>,
+    action: LimitAction,
+    assertions: &mut AssertionFailures,
+) -> Result<()> {
+    if let Some(max_write_ops) = max_write_ops {
+        if num_write_ops > max_write_ops {
+            let message = format!(
+                "{} produced {} write ops, exceeding the limit of {}",
+                script_name, num_write_ops, max_write_ops
+            );
+            match action {
+                LimitAction::Warn => warn!("        Warning: {}", message),
+                LimitAction::Fail => assertions.fail(message)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Asserts that the total gas used does not exceed the configured budget, reporting the overage.
+fn check_gas_budget(
+    total_gas_used: u64,
+    max_total_gas: Option<u64>,
+    what: &str,
+    group_digits: bool,
+    assertions: &mut AssertionFailures,
+) -> Result<()> {
+    if let Some(max_total_gas) = max_total_gas {
+        if total_gas_used > max_total_gas {
+            assertions.fail(format!(
+                "{} used {} gas units, exceeding the budget of {} by {}",
+                what,
+                format_number(total_gas_used, group_digits),
+                format_number(max_total_gas, group_digits),
+                format_number(total_gas_used - max_total_gas, group_digits)
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the gas used by all proposals of a release against `options.max_total_gas`.
+fn check_release_gas_budget(release_gas_used: u64, options: &SimulationOptions) -> Result<()> {
+    let mut assertions = AssertionFailures::new(options.assertion_mode);
+    check_gas_budget(
+        release_gas_used,
+        options.max_total_gas,
+        "the release",
+        options.group_digits,
+        &mut assertions,
+    )?;
+    assertions.finish()
+}
+
+/// Loads a feature flag snapshot in the YAML format printed by `print-configs`.
+///
+/// Flags that are not listed as enabled are considered disabled.
+pub fn load_features_snapshot(path: &Path) -> Result<Features> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("failed to read feature flags from {}", path.display()))?;
+    let snapshot: feature_flags::Features = serde_yaml::from_slice(&bytes)
+        .with_context(|| format!("failed to parse feature flags from {}", path.display()))?;
+
+    let mut features = Features { features: vec![] };
+    for flag in snapshot.enabled {
+        features.enable(flag.into());
+    }
+    Ok(features)
+}
+
+/// Parses the name of a feature flag as used in release configs, e.g. `vm_binary_format_v7`.
+pub fn parse_feature_flag(name: &str) -> Result<FeatureFlag> {
+    let flag: feature_flags::FeatureFlag =
+        serde_json::from_value(serde_json::Value::String(name.to_string()))
+            .map_err(|_| anyhow!("unknown feature flag: {}", name))?;
+    Ok(flag.into())
+}
+
+/// A modification of an on-chain config, applied before any script is executed, e.g. to simulate
+/// a proposal as if a config change it depends on had already landed.
+///
+/// Like [`SimulationStateView::modify_on_chain_config`], this only supports configs whose Rust
+/// representation is BCS-serialized as is.
+#[derive(Clone)]
+pub struct ConfigOverride {
+    config_name: &'static str,
+    state_key: StateKey,
+    /// Maps the current value of the config to the new one. `None` discards the local changes
+    /// to the config instead.
+    modify: Option<Arc<dyn Fn(&[u8]) -> Result<Vec<u8>> + Send + Sync>>,
+}
+
+impl ConfigOverride {
+    pub fn new<C, F>(modify: F) -> Result<Self>
+    where
+        C: OnChainConfig + Serialize,
+        F: Fn(&mut C) -> Result<()> + Send + Sync + 'static,
+    {
+        Ok(Self {
+            config_name: std::any::type_name::<C>(),
+            state_key: StateKey::on_chain_config::<C>()?,
+            modify: Some(Arc::new(move |bytes| {
+                let mut config = C::deserialize_into_config(bytes)?;
+                modify(&mut config)?;
+                Ok(bcs::to_bytes(&config)?)
+            })),
+        })
+    }
+
+    /// Discards the local changes to a config, so that its remote value is used, e.g. to undo
+    /// the injected feature flags snapshot for some configs. Overrides are applied in order, so
+    /// this can be followed by other overrides of the same config.
+    pub fn reset<C: OnChainConfig>() -> Result<Self> {
+        Ok(Self {
+            config_name: std::any::type_name::<C>(),
+            state_key: StateKey::on_chain_config::<C>()?,
+            modify: None,
+        })
+    }
+
+    fn apply(&self, state_view: &SimulationStateView<impl StateView>) -> Result<()> {
+        let Some(modify) = &self.modify else {
+            state_view.remove_state_value(&self.state_key);
+            return Ok(());
+        };
+
+        let state_val = state_view
+            .get_state_value(&self.state_key)?
+            .ok_or_else(|| anyhow!("on-chain config {} does not exist", self.config_name))?;
+        let state_val = state_val
+            .map_bytes(|bytes| Ok(modify(&bytes)?.into()))
+            .with_context(|| format!("failed to override on-chain config {}", self.config_name))?;
+        state_view.set_state_value(self.state_key.clone(), state_val);
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ConfigOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.modify {
+            Some(_) => write!(f, "ConfigOverride({})", self.config_name),
+            None => write!(f, "ConfigOverride(reset {})", self.config_name),
+        }
+    }
+}
+
+/// Default number of events retained per script.
+pub const DEFAULT_MAX_EVENTS_PER_SCRIPT: usize = 1000;
+
+/// Default maximum gas amount of the transactions the scripts are executed in.
+pub const DEFAULT_MAX_GAS_AMOUNT: u64 = 100_000;
+
+/// Default APT balance of the sender account, in octas.
+pub const DEFAULT_SENDER_BALANCE: u64 = 100 * 1_0000_0000; // 100 APT
+
+/// Options that control how governance proposals are simulated.
+#[derive(Clone, Debug)]
+pub struct SimulationOptions {
+    /// Set this flag to enable the gas profiler.
+    pub profile_gas: bool,
+    /// Set this flag to open the generated gas reports in the default browser. This is a no-op
+    /// in CI and headless environments.
+    pub open_report: bool,
+    /// Set this flag to also profile the forced epoch change before each script. The reports
+    /// are saved next to the ones of the scripts, under `<script>-force-end-epoch`.
+    pub profile_force_end_epoch: bool,
+    /// The format of the gas reports written when profiling gas.
+    pub gas_report_format: GasReportFormat,
+    /// Maximum number of scripts of a single proposal that are compiled in parallel.
+    pub compile_concurrency: usize,
+    /// Maximum number of proposals of a release that are simulated in parallel. Their output is
+    /// interleaved, but the final report keeps the order of the proposals.
+    pub proposal_concurrency: usize,
+    /// Set this flag to compile all scripts even if some of them fail, and report each distinct
+    /// compilation error once along with the scripts it affects.
+    pub aggregate_compile_errors: bool,
+    /// Version of the bytecode the scripts are compiled to. Inferred from the language version
+    /// if not set.
+    pub bytecode_version: Option<u32>,
+    /// Version of the Move language the scripts are compiled with. Defaults to the compiler's
+    /// default if not set.
+    pub language_version: Option<LanguageVersion>,
+    /// Version of the compiler the scripts are compiled with, e.g. to reproduce the exact
+    /// bytecode of a release build. Defaults to the compiler's default if not set.
+    pub compiler_version: Option<CompilerVersion>,
+    /// Local framework package the scripts are compiled against, e.g. a modified framework the
+    /// scripts depend on. Defaults to the framework in this repository.
+    pub framework_dir: Option<PathBuf>,
+    /// Set this flag to force the VM's paranoid type checks on for the scripts, regardless of the
+    /// process-wide setting, to catch type-safety issues introduced by upgraded or patched
+    /// modules.
+    pub force_paranoid: bool,
+    /// Whether to reuse the VM environment across the scripts of a proposal while the on-chain
+    /// configs it is derived from are unchanged and no modules are published. Disabling this
+    /// constructs a fresh environment for every script.
+    pub reuse_environment: bool,
+    /// Whether to keep the outputs of the executed transactions, see
+    /// [`simulate_multistep_proposal_with_outputs`]. Not exposed on the command line.
+    pub collect_transaction_outputs: bool,
+    /// Set this flag to summarize the changes the proposal made to well-known staking resources,
+    /// such as the staking config, the validator set and delegation pools.
+    pub summarize_staking: bool,
+    /// Set this flag to write a diff of the resources the proposal created, modified and
+    /// deleted to `resource_diff.txt` in the proposal directory, with their fields decoded.
+    pub resource_diff: bool,
+    /// Set this flag to report the fields of the consensus config the proposal changes, including
+    /// changes buffered for the next epoch. The config is only decoded for the report, the VM
+    /// reads it from the state like any other on-chain config.
+    pub summarize_consensus_config: bool,
+    /// Set this flag to format gas and fee figures with thousands separators, e.g. `1,234,567`.
+    pub group_digits: bool,
+    /// Set this flag to warn about scripts that change no on-chain config and publish no
+    /// modules. Can be suppressed for individual steps in the proposal manifest.
+    pub warn_no_op: bool,
+    /// Set this flag to fail scripts that neither start a new epoch nor start DKG, which
+    /// usually means the proposal forgot to reconfigure. Can be waived for individual steps in
+    /// the proposal manifest.
+    pub require_reconfiguration: bool,
+    /// Set this flag to report each function called directly by the scripts, which gives
+    /// per-action visibility into proposals that perform multiple actions in a single script.
+    pub report_actions: bool,
+    /// Whether to stop at the first failed assertion, e.g. an exceeded gas budget, or to keep
+    /// simulating and report all failed assertions at the end.
+    pub assertion_mode: AssertionMode,
+    /// Set this flag to keep simulating the remaining proposals of a release after one fails,
+    /// instead of stopping at the first failure. The failures are reported at the end.
+    pub keep_going: bool,
+    /// Set this flag to record the functions executed by the governance scripts.
+    /// The list is written to `coverage.txt` in the proposal directory.
+    pub capture_coverage: bool,
+    /// Set this flag to skip the forced epoch change before the first script. The epoch is still
+    /// force-ended between subsequent scripts.
+    ///
+    /// This is useful when the remote state has no buffered configuration changes, in which case
+    /// the initial reconfiguration has no effect besides bumping the epoch, which can perturb
+    /// scripts that read the epoch or the last reconfiguration time.
+    pub skip_initial_epoch_force: bool,
+    /// Set this flag to never force the epoch to end, including between scripts. Scripts are
+    /// still executed in order, but configuration changes they buffer for the next epoch stay
+    /// pending, which allows inspecting the intermediate state. This also avoids calling
+    /// `force_end_epoch`, which may fail at some historical versions.
+    pub no_epoch_force: bool,
+    /// Set this flag to execute the proposal a second time from the same state and report the
+    /// state items that end up different, which indicates logic that depends on more than the
+    /// state it is executed on.
+    pub check_idempotency: bool,
+    /// Set this flag to check the hash of the first script of each proposal against the execution
+    /// hashes approved in the remote state, e.g. for a proposal that already passed the vote, and
+    /// warn if none matches.
+    pub verify_approved_hashes: bool,
+    /// The governance function to patch in order to skip the governance process.
+    pub patch_plan: PatchPlan,
+    /// Proposal id passed to the scripts, e.g. to avoid colliding with state a script keys by
+    /// the id. The patched resolve function ignores it.
+    pub dummy_proposal_id: u64,
+    /// The account the scripts are sent from.
+    pub sender_account: SenderAccount,
+    /// How the APT balance of the sender account is set up.
+    pub sender_funding: SenderFunding,
+    /// The APT balance the sender account starts with, in octas.
+    pub sender_balance: u64,
+    /// Maximum gas amount of the transactions the scripts are executed in. Scripts running out
+    /// of gas under this cap may still pass on chain under the approved-hash execution limits.
+    pub max_gas_amount: u64,
+    /// Maximum gas units the scripts of a proposal, and of all proposals combined, may use.
+    /// The simulation fails if this budget is exceeded.
+    pub max_total_gas: Option<u64>,
+    /// Maximum gas units a single script may use. The simulation fails if a script exceeds it,
+    /// e.g. to catch an unexpectedly expensive script before it is executed on chain.
+    pub max_gas_per_script: Option<u64>,
+    /// Maximum number of write ops the write set of a single script may contain. Large write
+    /// sets can indicate an overly broad proposal or hit on-chain limits.
+    pub max_write_ops_per_script: Option<usize>,
+    /// Whether exceeding `max_write_ops_per_script` fails the simulation or only warns.
+    pub write_ops_limit_action: LimitAction,
+    /// Limits the number of remote state fetches in flight at the same time. The limit is shared
+    /// by all proposals simulated with these options. Unlimited if not set.
+    pub remote_fetch_limiter: Option<RemoteFetchLimiter>,
+    /// Cache of remote state values shared by all proposals simulated at its version. Set by
+    /// `simulate_all_proposals`.
+    pub remote_state_cache: Option<Arc<RemoteStateCache>>,
+    /// Timeout of a single request to the REST endpoint.
+    pub remote_request_timeout: Duration,
+    /// How failed requests to the REST endpoint are retried.
+    pub remote_retry_policy: RetryPolicy,
+    /// API key sent as a bearer token to the REST endpoint, e.g. for a protected fullnode.
+    pub remote_api_key: Option<Redacted<String>>,
+    /// Additional headers sent to the REST endpoint, as name and value. The values are redacted,
+    /// as they may carry credentials.
+    pub remote_headers: Vec<(String, Redacted<String>)>,
+    /// Maximum number of events retained per script. Further events are counted, but their
+    /// payloads are dropped to bound memory usage.
+    pub max_events_per_script: usize,
+    /// Set this flag to print the events emitted by each script, with their payloads decoded.
+    /// Only the retained events are printed.
+    pub print_events: bool,
+    /// Set this flag to save the remote state values read while simulating each proposal to
+    /// `state_snapshot.bcs` in the proposal directory.
+    pub record_state_snapshot: bool,
+    /// Set this flag to export all state items the simulation changed to `overlay.bcs` or
+    /// `overlay.json` in the proposal directory, for auditing what the proposal would change on
+    /// chain. Besides the changes made by the scripts, this includes the sender account and the
+    /// patched framework modules.
+    pub export_overlay: bool,
+    /// Format the overlay is exported in.
+    pub overlay_format: OverlayFormat,
+    /// Overlays exported by earlier simulations, applied in order on top of the remote state,
+    /// before the overrides and setup calls, e.g. to simulate a proposal on top of the changes of
+    /// another one. The format is detected from the file extension.
+    pub import_overlays: Vec<PathBuf>,
+    /// Set this flag to write the state keys each proposal reads from the remote state, i.e. its
+    /// read footprint, to `remote_reads.txt` in the proposal directory. The file is also written
+    /// if the proposal fails, listing the keys read up to the failure.
+    pub record_remote_reads: bool,
+    /// Set this flag to write the patched governance modules to `patched-modules` in the
+    /// proposal directory before they are verified, for inspecting a patch that misbehaves or
+    /// fails verification. The modules are patched anew for every script, so each script gets
+    /// its own subdirectory.
+    pub dump_patched_modules: bool,
+    /// Set this flag to simulate each proposal on top of the state snapshot saved in its
+    /// directory, instead of fetching the state from the REST endpoint.
+    pub offline: bool,
+    /// Simulate each proposal on top of a genesis state built locally instead of the remote
+    /// state, e.g. to test the logic of a proposal in isolation from the state accumulated on a
+    /// network. Takes precedence over the REST endpoint and offline mode.
+    pub local_genesis: Option<GenesisConfig>,
+    /// Feature flags injected before the first script is executed, replacing the ones read
+    /// from the remote state.
+    pub features_override: Option<Features>,
+    /// Feature flags to derive the bytecode deserializer config from when patching modules and
+    /// verifying published ones, instead of the feature flags in the simulated state, e.g. to
+    /// test a bytecode feature the proposal itself enables.
+    pub deserializer_features_override: Option<Features>,
+    /// Modifications of on-chain configs, applied in order before the first script is executed
+    /// and after the feature flags snapshot is injected.
+    pub config_overrides: Vec<ConfigOverride>,
+    /// Feature flags forced on before each script is executed, i.e. also after every epoch
+    /// change, which would otherwise apply buffered feature flag changes.
+    pub enabled_features: Vec<FeatureFlag>,
+    /// Feature flags forced off before each script is executed.
+    pub disabled_features: Vec<FeatureFlag>,
+    /// Functions whose bodies are replaced before each script is executed, so that they return
+    /// a constant, e.g. to make a permission check always pass.
+    pub function_stubs: Vec<FunctionStub>,
+    /// Functions executed in order before the first script, after the on-chain config
+    /// overrides are applied, e.g. to register a coin the proposal depends on.
+    pub setup_calls: Vec<SetupCall>,
+    /// Version of the remote state to simulate proposals on, unless their manifest specifies
+    /// one. Defaults to the latest version.
+    pub at_version: Option<u64>,
+    /// Path to write a JUnit XML report of the simulated proposals to, for CI integration.
+    pub junit_report_path: Option<PathBuf>,
+    /// How the simulation results are output.
+    pub output_format: OutputFormat,
+}
+
+impl Default for SimulationOptions {
+    fn default() -> Self {
+        Self {
+            profile_gas: false,
+            open_report: false,
+            profile_force_end_epoch: false,
+            gas_report_format: GasReportFormat::default(),
+            compile_concurrency: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            proposal_concurrency: 1,
+            aggregate_compile_errors: false,
+            bytecode_version: None,
+            language_version: None,
+            compiler_version: None,
+            framework_dir: None,
+            force_paranoid: false,
+            reuse_environment: true,
+            collect_transaction_outputs: false,
+            summarize_staking: false,
+            resource_diff: false,
+            summarize_consensus_config: false,
+            group_digits: false,
+            warn_no_op: false,
+            require_reconfiguration: false,
+            report_actions: false,
+            assertion_mode: AssertionMode::default(),
+            keep_going: false,
+            capture_coverage: false,
+            skip_initial_epoch_force: false,
+            no_epoch_force: false,
+            check_idempotency: false,
+            verify_approved_hashes: false,
+            patch_plan: PatchPlan::default(),
+            dummy_proposal_id: DEFAULT_DUMMY_PROPOSAL_ID,
+            sender_funding: SenderFunding::default(),
+            sender_account: SenderAccount::default(),
+            sender_balance: DEFAULT_SENDER_BALANCE,
+            max_gas_amount: DEFAULT_MAX_GAS_AMOUNT,
+            max_total_gas: None,
+            max_gas_per_script: None,
+            max_write_ops_per_script: None,
+            write_ops_limit_action: LimitAction::default(),
+            remote_fetch_limiter: None,
+            remote_state_cache: None,
+            remote_request_timeout: DEFAULT_REMOTE_REQUEST_TIMEOUT,
+            remote_retry_policy: RetryPolicy::default(),
+            remote_api_key: None,
+            remote_headers: vec![],
+            max_events_per_script: DEFAULT_MAX_EVENTS_PER_SCRIPT,
+            print_events: false,
+            record_state_snapshot: false,
+            export_overlay: false,
+            overlay_format: OverlayFormat::default(),
+            import_overlays: vec![],
+            record_remote_reads: false,
+            dump_patched_modules: false,
+            offline: false,
+            local_genesis: None,
+            features_override: None,
+            deserializer_features_override: None,
+            config_overrides: vec![],
+            enabled_features: vec![],
+            disabled_features: vec![],
+            function_stubs: vec![],
+            setup_calls: vec![],
+            at_version: None,
+            junit_report_path: None,
+            output_format: OutputFormat::default(),
+        }
+    }
+}
+
+impl SimulationOptions {
+    /// The framework the scripts are compiled against.
+    pub fn framework_path(&self) -> PathBuf {
+        self.framework_dir
+            .clone()
+            .unwrap_or_else(aptos_framework_path)
+    }
+
+    /// Creates a client of the REST endpoint at the given URL, with the timeout and the
+    /// authentication of the options.
+    pub fn remote_client(&self, remote_url: &Url) -> Result<Client> {
+        let mut builder = Client::builder(AptosBaseUrl::Custom(remote_url.clone()))
+            .timeout(self.remote_request_timeout);
+        if let Some(api_key) = &self.remote_api_key {
+            builder = builder
+                .api_key(&api_key.0)
+                .context("invalid API key of the REST endpoint")?;
+        }
+        for (name, value) in &self.remote_headers {
+            builder = builder
+                .header(name, &value.0)
+                .with_context(|| format!("invalid header {} of the REST endpoint", name))?;
+        }
+        Ok(builder.build())
+    }
+}
+
+/// A value that is left out of debug output, e.g. credentials passed in the simulation options.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Redacted<T>(pub T);
+
+impl<T> std::fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl std::str::FromStr for Redacted<String> {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
+/// Parses a header to send to the REST endpoint, given as `<name>:<value>`.
+pub fn parse_header(header: &str) -> Result<(String, Redacted<String>)> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| anyhow!("expected a header of the form <name>:<value>: {}", header))?;
+    Ok((name.trim().to_string(), Redacted(value.trim().to_string())))
+}
+
+/// Parses the path to a local framework package, resolving it to an absolute path, as the scripts
+/// are compiled in temporary directories that a relative path would be resolved against.
+pub fn parse_framework_dir(path: &str) -> Result<PathBuf> {
+    std::fs::canonicalize(path)
+        .with_context(|| format!("failed to resolve framework directory {}", path))
+}
+
+/// Loads the precompiled bytecode of a script, i.e. `<name>.mv` next to `<name>.move`, if it
+/// exists and is not older than the script itself.
+fn load_precompiled_script(script_path: &Path) -> Result<Option<(Vec<u8>, HashValue)>> {
+    let bytecode_path = script_path.with_extension("mv");
+    if !bytecode_path.is_file() {
+        return Ok(None);
+    }
+
+    let modified = |path: &Path| -> Result<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("failed to read modification time of {}", path.display()))
+    };
+    if modified(&bytecode_path)? < modified(script_path)? {
+        info!(
+            "    Ignoring {}, which is older than its source",
+            bytecode_path.display()
+        );
+        return Ok(None);
+    }
+
+    let blob = std::fs::read(&bytecode_path)
+        .with_context(|| format!("failed to read {}", bytecode_path.display()))?;
+    CompiledScript::deserialize(&blob)
+        .with_context(|| format!("{} is not a valid compiled script", bytecode_path.display()))?;
+    let hash = HashValue::sha3_256_of(&blob);
+
+    Ok(Some((blob, hash)))
+}
+
+/// Compiles a script, unless precompiled bytecode for it is available.
+///
+/// Precompiled bytecode is used as is, regardless of the compiler versions in the options.
+fn compile_script(script_path: &Path, options: &SimulationOptions) -> Result<(Vec<u8>, HashValue)> {
+    if let Some(precompiled) = load_precompiled_script(script_path)? {
+        return Ok(precompiled);
+    }
+
+    let framework_package_args = FrameworkPackageArgs::try_parse_from([
+        "dummy_executable_name",
+        "--framework-local-dir",
+        &options.framework_path().to_string_lossy(),
+        "--skip-fetch-latest-git-deps",
+    ])
+    .context(
+        "failed to parse framework package args for compiling scripts, this should not happen",
+    )?;
+
+    let (blob, hash) = compile_in_temp_dir(
+        "script",
+        script_path,
+        &framework_package_args,
+        PromptOptions::yes(),
+        options.bytecode_version,
+        options.language_version,
+        options.compiler_version,
+    )
+    .with_context(|| format!("failed to compile script {}", script_path.display()))?;
+
+    Ok((blob, hash))
+}
+
+/// Splits a compiler error message into its individual diagnostics, each starting with a line
+/// like `error[E03002]: unbound module`.
+fn split_diagnostics(message: &str) -> Vec<String> {
+    let mut diagnostics: Vec<String> = vec![];
+    for line in message.lines() {
+        if diagnostics.is_empty() || line.starts_with("error") || line.starts_with("warning") {
+            diagnostics.push(String::new());
+        }
+        let diagnostic = diagnostics.last_mut().unwrap();
+        diagnostic.push_str(line);
+        diagnostic.push('\n');
+    }
+    diagnostics
+}
+
+/// Strips the parts of a diagnostic that differ between otherwise identical errors in different
+/// scripts, namely the file locations and the line number gutter.
+fn normalize_diagnostic(diagnostic: &str) -> String {
+    diagnostic
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("┌─"))
+        .map(|line| line.trim_start_matches(|c: char| c.is_ascii_digit() || c == ' '))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prints each distinct compilation error once, along with the scripts it affects.
+fn report_compile_errors(num_scripts: usize, errors: &[(&PathBuf, anyhow::Error)]) {
+    // Distinct diagnostics in the order they were first encountered.
+    let mut diagnostics: Vec<(String, String, Vec<String>)> = vec![];
+    for (script_path, err) in errors {
+        let script_name = script_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        for diagnostic in split_diagnostics(&err.root_cause().to_string()) {
+            let key = normalize_diagnostic(&diagnostic);
+            match diagnostics
+                .iter_mut()
+                .find(|(other_key, _, _)| *other_key == key)
+            {
+                Some((_, _, script_names)) => {
+                    if !script_names.contains(&script_name) {
+                        script_names.push(script_name.clone());
+                    }
+                },
+                None => diagnostics.push((key, diagnostic, vec![script_name.clone()])),
+            }
+        }
+    }
+
+    error!(
+        "Failed to compile {} out of {} scripts, with {} distinct error{}:",
+        errors.len(),
+        num_scripts,
+        diagnostics.len(),
+        if diagnostics.len() == 1 { "" } else { "s" }
+    );
+    for (_key, diagnostic, script_names) in &diagnostics {
+        error!("");
+        for line in diagnostic.trim_end().lines() {
+            error!("    {}", line);
+        }
+        error!("    This error affects: {}", script_names.join(", "));
+    }
+}
+
+/// Compiles the given scripts using a bounded pool of worker threads.
+///
+/// The results are returned in the same order as the input scripts, along with the time each
+/// script took to compile. Every script is compiled in its own temporary package directory, so
+/// the workers never share any build outputs. They do share the framework package and the
+/// package cache under `MOVE_HOME`, which `move-package` guards by holding its package lock, a
+/// mutex shared across threads and processes, while it resolves and builds each package.
+fn compile_scripts(
+    proposal_scripts: &[PathBuf],
+    options: &SimulationOptions,
+) -> Result<(Vec<(Vec<u8>, HashValue)>, Vec<Duration>)> {
+    ScriptCompilation::run(proposal_scripts, options).finish(proposal_scripts, options)
+}
+
+/// The outcome of compiling a list of scripts in parallel, before the errors are reported.
+struct ScriptCompilation {
+    /// The result of compiling each script and the time it took, or `None` if the script was not
+    /// compiled because an earlier one failed.
+    results: Vec<Option<(Result<(Vec<u8>, HashValue)>, Duration)>>,
+    wall_time: Duration,
+    num_workers: usize,
+}
+
+impl ScriptCompilation {
+    fn run(proposal_scripts: &[PathBuf], options: &SimulationOptions) -> Self {
+        let concurrency = options.compile_concurrency;
+        let aggregate_errors = options.aggregate_compile_errors;
+        let num_scripts = proposal_scripts.len();
+        let num_workers = concurrency.clamp(1, num_scripts.max(1));
+
+        let start_time = Instant::now();
+        let next_script_idx = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new((0..num_scripts).map(|_| None).collect::<Vec<_>>());
+
+        std::thread::scope(|s| {
+            for _ in 0..num_workers {
+                s.spawn(|| {
+                    // Stop picking up new scripts once one of them failed to compile, unless all
+                    // errors are to be reported.
+                    while aggregate_errors || !failed.load(Ordering::Relaxed) {
+                        let idx = next_script_idx.fetch_add(1, Ordering::Relaxed);
+                        if idx >= num_scripts {
+                            break;
+                        }
+
+                        let script_start_time = Instant::now();
+                        let res = compile_script(&proposal_scripts[idx], options);
+                        if res.is_err() {
+                            failed.store(true, Ordering::Relaxed);
+                        }
+                        results.lock()[idx] = Some((res, script_start_time.elapsed()));
+                    }
+                });
+            }
+        });
+
+        Self {
+            results: results.into_inner(),
+            wall_time: start_time.elapsed(),
+            num_workers,
+        }
+    }
+
+    /// Returns the scripts that failed to compile, along with their errors.
+    fn failures<'a>(&self, proposal_scripts: &'a [PathBuf]) -> Vec<(&'a PathBuf, String)> {
+        proposal_scripts
+            .iter()
+            .zip(&self.results)
+            .filter_map(|(script_path, res)| match res {
+                Some((Err(err), _)) => Some((script_path, format!("{:#}", err))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the compiled scripts and their compile times, or reports the errors.
+    fn finish(
+        self,
+        proposal_scripts: &[PathBuf],
+        options: &SimulationOptions,
+    ) -> Result<(Vec<(Vec<u8>, HashValue)>, Vec<Duration>)> {
+        let num_scripts = proposal_scripts.len();
+        let mut total_compile_time = Duration::ZERO;
+        let mut compiled_scripts = vec![];
+        let mut compile_times = vec![];
+        let mut errors = vec![];
+        // Scripts are handed out in order, so every script before the failed one has been
+        // compiled.
+        for (script_path, res) in proposal_scripts.iter().zip(self.results) {
+            let Some((res, compile_time)) = res else {
+                break;
+            };
+            total_compile_time += compile_time;
+            match res {
+                Ok(compiled_script) => {
+                    info!(
+                        "    {} compiled in {:.2}s",
+                        script_path.file_name().unwrap().to_string_lossy(),
+                        compile_time.as_secs_f64()
+                    );
+                    compiled_scripts.push(compiled_script);
+                    compile_times.push(compile_time);
+                },
+                Err(err) if options.aggregate_compile_errors => errors.push((script_path, err)),
+                Err(err) => return Err(err),
+            }
+        }
+
+        if !errors.is_empty() {
+            report_compile_errors(num_scripts, &errors);
+            bail!(
+                "failed to compile {} out of {} scripts",
+                errors.len(),
+                num_scripts
+            );
+        }
+
+        // The per-script times include waiting for the package lock held by other workers, so
+        // their sum only approximates the time compiling one script after the other would take.
+        info!(
+            "Compiled {} script{} in {:.2}s using {} worker{}, with per-script compile times \
+             adding up to {:.2}s (an estimated {:.2}s saved by compiling in parallel)",
+            num_scripts,
+            if num_scripts == 1 { "" } else { "s" },
+            self.wall_time.as_secs_f64(),
+            self.num_workers,
+            if self.num_workers == 1 { "" } else { "s" },
+            total_compile_time.as_secs_f64(),
+            total_compile_time
+                .saturating_sub(self.wall_time)
+                .as_secs_f64(),
+        );
+
+        Ok((compiled_scripts, compile_times))
+    }
+}
+
+/// Creates the environment scripts are executed in, with the create signer native injected and,
+/// if requested, the VM's paranoid type checks forced on.
+fn simulation_environment(state_view: &impl StateView, force_paranoid: bool) -> AptosEnvironment {
+    if force_paranoid {
+        AptosEnvironment::new_with_injected_create_signer_and_paranoid_type_checks_for_gov_sim(
+            state_view,
+        )
+    } else {
+        AptosEnvironment::new_with_injected_create_signer_for_gov_sim(state_view)
+    }
+}
+
+fn is_paranoid_failure(vm_status: &VMStatus) -> bool {
+    use move_core_types::vm_status::sub_status::unknown_invariant_violation::{
+        EPARANOID_FAILURE, EREFERENCE_COUNTING_FAILURE,
+    };
+
+    vm_status.status_code() == StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR
+        && matches!(
+            vm_status.sub_status(),
+            Some(EPARANOID_FAILURE | EREFERENCE_COUNTING_FAILURE)
+        )
+}
+
+/// Describes a Move abort in terms of the error constants of the aborting module, e.g.
+/// `aborted in 0x1::staking_config::EINVALID_REWARD_RATE (code 0x10003)`.
+///
+/// The VM usually attaches the reason already. If it did not, the reason is looked up in the
+/// error map stored in the metadata of the aborting module. The raw code is reported if neither
+/// is available, e.g. for aborts in scripts.
+fn describe_move_abort(
+    state_view: &impl StateView,
+    deserializer_config: &DeserializerConfig,
+    location: &AbortLocation,
+    code: u64,
+    info: Option<&AbortInfo>,
+) -> String {
+    let AbortLocation::Module(module_id) = location else {
+        return format!("aborted in the script (code {:#x})", code);
+    };
+
+    let info = info.cloned().or_else(|| {
+        let blob = state_view
+            .get_state_value_bytes(&StateKey::module_id(module_id))
+            .ok()??;
+        let module = CompiledModule::deserialize_with_config(&blob, deserializer_config).ok()?;
+        get_metadata_from_compiled_module(&module)?.extract_abort_info(code)
+    });
+    match info {
+        Some(info) => format!(
+            "aborted in {}::{} (code {:#x}): {}",
+            module_id.short_str_lossless(),
+            info.reason_name,
+            code,
+            info.description
+        ),
+        None => format!(
+            "aborted in {} (code {:#x})",
+            module_id.short_str_lossless(),
+            code
+        ),
+    }
+}
+
+/// Explains a failure caused by a gas limit rather than by the logic of the script, if the status
+/// denotes one, along with how to address it in the simulation.
+///
+/// Governance scripts are executed on chain under the approved execution hash, with the gas
+/// limits of governance transactions, which can be far higher than the max gas amount of the
+/// simulated transactions. A script running out of gas in the simulation may thus pass on chain.
+fn describe_gas_failure(
+    status: &TransactionStatus,
+    max_gas_amount: u64,
+    group_digits: bool,
+) -> Option<String> {
+    let max_gas_amount = format_number(max_gas_amount, group_digits);
+    match status {
+        TransactionStatus::Keep(ExecutionStatus::OutOfGas) => Some(format!(
+            "ran out of gas under the simulation's max gas amount of {}. The approved script may \
+             be granted more gas on chain, so raise the limit with `--max-gas-amount` to tell a \
+             gas-hungry script from a failing one",
+            max_gas_amount
+        )),
+        TransactionStatus::Keep(ExecutionStatus::MiscellaneousError(Some(
+            code @ (StatusCode::EXECUTION_LIMIT_REACHED
+            | StatusCode::IO_LIMIT_REACHED
+            | StatusCode::STORAGE_LIMIT_REACHED),
+        ))) => Some(format!(
+            "hit a per-transaction limit of the gas schedule ({:?}), which does not depend on the \
+             max gas amount, so the script needs to be split into smaller steps",
+            code
+        )),
+        TransactionStatus::Discard(StatusCode::MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND) => {
+            Some(format!(
+                "was rejected because the max gas amount of {} exceeds the bound of the gas \
+                 schedule, lower it with `--max-gas-amount`",
+                max_gas_amount
+            ))
+        },
+        TransactionStatus::Discard(StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS) => {
+            Some(format!(
+                "was rejected because the max gas amount of {} is below the minimum of the gas \
+                 schedule, raise it with `--max-gas-amount`",
+                max_gas_amount
+            ))
+        },
+        TransactionStatus::Discard(StatusCode::INSUFFICIENT_BALANCE_FOR_TRANSACTION_FEE) => Some(
+            "was rejected because the sender cannot pay for the max gas amount, raise its balance \
+             with `--sender-balance`"
+                .to_string(),
+        ),
+        _ => None,
+    }
+}
+
+/// Returns true if no browser can be expected to be available, e.g. in CI or over SSH without
+/// a display.
+fn is_headless() -> bool {
+    if std::env::var_os("CI").is_some() {
+        return true;
+    }
+    cfg!(target_os = "linux")
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+}
+
+/// Opens a generated gas report in the default browser, unless running headless.
+///
+/// Failing to open the report does not fail the simulation, since the report has been saved.
+fn open_gas_report(index_path: &Path) {
+    if is_headless() {
+        return;
+    }
+    if let Err(err) = open::that(index_path) {
+        warn!(
+            "        Warning: failed to open {}: {}",
+            index_path.display(),
+            err
+        );
+    }
+}
+
+/// Number of most expensive operations listed in text gas reports.
+const TEXT_GAS_REPORT_TOP_N: usize = 20;
+
+/// Determines the format of the gas reports written when profiling gas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum GasReportFormat {
+    /// An interactive HTML report per script, in a directory named after the script.
+    #[default]
+    Html,
+    /// A plain-text summary per script, in `<script>.txt`, which is easy to diff or paste.
+    Text,
+    /// Both of the above.
+    Both,
+}
+
+impl GasReportFormat {
+    fn includes_html(self) -> bool {
+        matches!(self, Self::Html | Self::Both)
+    }
+
+    fn includes_text(self) -> bool {
+        matches!(self, Self::Text | Self::Both)
+    }
+}
+
+/// Renders a plain-text summary of a gas log, listing the totals and the most expensive
+/// operations.
+///
+/// The output only depends on the gas log, so that it can be diffed across simulations.
+fn render_text_gas_report(gas_log: &TransactionGasLog, title: &str) -> String {
+    let exec_io = &gas_log.exec_io;
+    let scaling_factor = u64::from(exec_io.gas_scaling_factor) as f64;
+    let total = u64::from(exec_io.total);
+    let dependencies_cost = exec_io
+        .dependencies
+        .iter()
+        .map(|dep| u64::from(dep.cost))
+        .sum::<u64>();
+
+    let aggregated = exec_io.aggregate_gas_events();
+    let mut operations = vec![];
+    for (prefix, entries) in [
+        ("", &aggregated.ops),
+        ("read ", &aggregated.storage_reads),
+        ("write ", &aggregated.storage_writes),
+        ("event ", &aggregated.event_writes),
+    ] {
+        for (name, count, cost) in entries {
+            operations.push((format!("{}{}", prefix, name), *count, u64::from(*cost)));
+        }
+    }
+    if !aggregated.transaction_write.is_zero() {
+        operations.push((
+            "transaction".to_string(),
+            1,
+            u64::from(aggregated.transaction_write),
+        ));
+    }
+    // Break ties by name to keep the order stable.
+    operations.sort_by(|(name1, _, cost1), (name2, _, cost2)| {
+        cost2.cmp(cost1).then_with(|| name1.cmp(name2))
+    });
+    operations.truncate(TEXT_GAS_REPORT_TOP_N);
+
+    let mut text = format!("{}\n\n", title);
+    text.push_str(&format!(
+        "Execution and IO: {:.8} gas units\n",
+        total as f64 / scaling_factor
+    ));
+    text.push_str(&format!(
+        "    Intrinsic: {:.8} gas units\n",
+        u64::from(exec_io.intrinsic_cost) as f64 / scaling_factor
+    ));
+    text.push_str(&format!(
+        "    Dependencies: {:.8} gas units\n",
+        dependencies_cost as f64 / scaling_factor
+    ));
+    text.push_str(&format!(
+        "Storage fee: {} octas\n",
+        u64::from(gas_log.storage.total)
+    ));
+    text.push_str(&format!(
+        "Storage fee refund: {} octas\n",
+        u64::from(gas_log.storage.total_refund)
+    ));
+
+    if !operations.is_empty() {
+        text.push_str(&format!(
+            "\nTop {} operations by execution and IO gas:\n",
+            operations.len()
+        ));
+        let name_width = operations
+            .iter()
+            .map(|(name, _, _)| name.len())
+            .max()
+            .unwrap_or_default();
+        for (name, count, cost) in &operations {
+            let percentage = if total == 0 {
+                0.0
+            } else {
+                *cost as f64 / total as f64 * 100.0
+            };
+            text.push_str(&format!(
+                "    {:<name_width$}  {:>8}x  {:>16.8}  {:>6.2}%\n",
+                name,
+                count,
+                *cost as f64 / scaling_factor,
+                percentage,
+                name_width = name_width
+            ));
+        }
+    }
+    text
+}
+
+/// Writes the gas reports of a gas log in the requested format. The HTML report is written to
+/// the given directory, the text report to the same path with a `.txt` suffix.
+///
+/// Returns the paths of the HTML and the text report, if written.
+fn write_gas_reports(
+    gas_log: &TransactionGasLog,
+    report_path: &Path,
+    title: String,
+    format: GasReportFormat,
+) -> Result<(Option<PathBuf>, Option<PathBuf>)> {
+    let html_path = if format.includes_html() {
+        gas_log.generate_html_report(report_path, title.clone())?;
+        Some(report_path.to_path_buf())
+    } else {
+        None
+    };
+
+    let text_path = if format.includes_text() {
+        let mut text_path = report_path.as_os_str().to_owned();
+        text_path.push(".txt");
+        let text_path = PathBuf::from(text_path);
+        if let Some(parent) = text_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&text_path, render_text_gas_report(gas_log, &title))
+            .with_context(|| format!("failed to write gas report to {}", text_path.display()))?;
+        Some(text_path)
+    } else {
+        None
+    };
+
+    Ok((html_path, text_path))
+}
+
+/// A simulation of governance scripts on top of a remote state, which can be driven step by step.
+///
+/// Besides executing the scripts of a proposal in order, the session allows executing arbitrary
+/// compiled scripts at the current state, inspecting and modifying the state in between, and
+/// taking and restoring snapshots of the local changes, e.g. to build interactive debugging tools.
+pub struct SimulationSession<S> {
+    state_view: SimulationStateView<S>,
+    proposal_dir: PathBuf,
+    manifest: ProposalManifest,
+    options: SimulationOptions,
+    /// The account the scripts are sent from.
+    account: AccountData,
+    num_executed_scripts: u64,
+    prev_gas_feature_version: Option<u64>,
+    executed_functions: BTreeSet<(ModuleId, Identifier)>,
+    assertions: AssertionFailures,
+    /// The environment the previous script was executed in, along with the configs it was
+    /// derived from, see [`read_environment_configs`].
+    cached_env: Option<(Vec<Option<Vec<u8>>>, AptosEnvironment)>,
+    /// Outputs of the transactions executed so far, if collected, see
+    /// [`Self::take_transaction_outputs`].
+    transaction_outputs: Vec<ScriptOutput>,
+}
+
+impl SimulationSession<RemoteStateView> {
+    /// Starts a session on top of the remote state at the given version. If not specified, the
+    /// version in the proposal manifest or the simulation options is used, falling back to the
+    /// latest version.
+    ///
+    /// In offline mode, the state snapshot saved in the proposal directory is used instead, and
+    /// with a local genesis, a freshly built genesis state.
+    pub async fn connect(
+        remote_url: Url,
+        proposal_dir: &Path,
+        options: &SimulationOptions,
+        version: Option<u64>,
+    ) -> Result<Self> {
+        let manifest = ProposalManifest::load(proposal_dir)?;
+        let version = version.or(manifest.at_version).or(options.at_version);
+
+        let remote = if let Some(genesis) = options.local_genesis {
+            if let Some(version) = version {
+                warn!(
+                    "Warning: ignoring version {}, which does not apply to a local genesis",
+                    version
+                );
+            }
+            info!("Using local {:?} genesis state", genesis);
+            RemoteStateView::Genesis(genesis.build_state())
+        } else if options.offline {
+            let snapshot_path = proposal_dir.join(STATE_SNAPSHOT_FILE_NAME);
+            let snapshot = StateSnapshot::load(&snapshot_path)?;
+            if let Some(version) = version {
+                if version != snapshot.version {
+                    bail!(
+                        "state snapshot {} was taken at version {}, but version {} was requested",
+                        snapshot_path.display(),
+                        snapshot.version,
+                        version
+                    );
+                }
+            }
+            info!(
+                "Using state snapshot {} taken at version {}",
+                snapshot_path.display(),
+                snapshot.version
+            );
+            RemoteStateView::Snapshot {
+                version: snapshot.version,
+                states: snapshot.states.into_iter().collect(),
+            }
+        } else {
+            let client = options.remote_client(&remote_url)?;
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    options
+                        .remote_retry_policy
+                        .retry_async("fetch ledger information", || {
+                            client.get_ledger_information()
+                        })
+                        .await?
+                        .into_inner()
+                        .version
+                },
+            };
+            info!("Using remote state at version {}", version);
+            // Share the cache of the release only if the proposal is simulated at its version.
+            let cache = options
+                .remote_state_cache
+                .clone()
+                .filter(|cache| cache.version == version)
+                .unwrap_or_else(|| Arc::new(RemoteStateCache::new(version)));
+            RemoteStateView::Live {
+                fetcher: RestStateFetcher::new(client),
+                version,
+                cache,
+                recorded: options
+                    .record_state_snapshot
+                    .then(|| Mutex::new(HashMap::new())),
+                retry_policy: options.remote_retry_policy.clone(),
+                fetch_limiter: options.remote_fetch_limiter.clone(),
+            }
+        };
+
+        let state_keys = prefetch_state_keys(&options.patch_plan)?;
+        let num_prefetched = remote.prefetch(&state_keys)?;
+        if num_prefetched > 0 {
+            info!("Prefetched {} state values", num_prefetched);
+        }
+
+        Self::with_manifest(remote, proposal_dir, manifest, options)
+    }
+
+    /// Saves the remote state values read so far to the proposal directory, if they are being
+    /// recorded.
+    pub fn save_state_snapshot(&self) -> Result<()> {
+        if let Some(snapshot) = self.state_view.remote.recorded_snapshot() {
+            let snapshot_path = self.proposal_dir.join(STATE_SNAPSHOT_FILE_NAME);
+            snapshot.save(&snapshot_path)?;
+            info!(
+                "Saved {} state values to {}",
+                snapshot.states.len(),
+                snapshot_path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<S> SimulationSession<S>
+where
+    S: StateView,
+{
+    /// Starts a session on top of the given remote state view.
+    pub fn new(remote: S, proposal_dir: &Path, options: &SimulationOptions) -> Result<Self> {
+        let manifest = ProposalManifest::load(proposal_dir)?;
+        Self::with_manifest(remote, proposal_dir, manifest, options)
+    }
+
+    fn with_manifest(
+        remote: S,
+        proposal_dir: &Path,
+        manifest: ProposalManifest,
+        options: &SimulationOptions,
+    ) -> Result<Self> {
+        // Report the capabilities found on chain, before any local change is applied.
+        info!("Reading governance signer capabilities...");
+        report_governance_signer_capabilities(&remote)?;
+
+        let state_view = SimulationStateView::new(remote);
+
+        // The imported overlays stand in for the remote state, so the overrides and setup calls
+        // apply on top of them.
+        for overlay_path in &options.import_overlays {
+            let num_state_items = state_view.import_overlay(overlay_path, None)?;
+            info!(
+                "Imported {} state item{} from {}",
+                num_state_items,
+                if num_state_items == 1 { "" } else { "s" },
+                overlay_path.display()
+            );
+        }
+        if let Some(features) = &options.features_override {
+            info!("Injecting feature flags snapshot...");
+            state_view.set_on_chain_config(features)?;
+        }
+        for config_override in &options.config_overrides {
+            info!(
+                "Overriding on-chain config {}...",
+                config_override.config_name
+            );
+            config_override.apply(&state_view)?;
+        }
+        if !options.setup_calls.is_empty() {
+            info!("Executing setup calls...");
+            // Only the setup calls may obtain arbitrary signers, the proposal must go through the
+            // access control of the framework.
+            with_temporary_patch(&state_view, &MODULE_ID_APTOS_GOVERNANCE, || {
+                inject_create_signer(
+                    &state_view,
+                    &load_deserializer_config(&state_view, options)?,
+                )
+                .context("failed to inject create_signer")?;
+                execute_setup_calls(&state_view, &options.setup_calls)
+            })?;
+        }
+
+        // Catch a mistyped governance module upfront rather than when patching the first script.
+        let governance_module = &options.patch_plan.governance_module;
+        if state_view
+            .get_state_value(&StateKey::module_id(governance_module))?
+            .is_none()
+        {
+            bail!(
+                "unknown governance module {}, it is not published at the simulated state",
+                governance_module.short_str_lossless()
+            );
+        }
+
+        // Create and fund a sender account that is used to send the governance scripts.
+        info!("Creating and funding sender account...");
+        let features =
+            Features::fetch_config(&state_view).context("failed to fetch feature flags")?;
+        let use_fa_apt = match options.sender_funding {
+            SenderFunding::Auto => {
+                features.is_enabled(FeatureFlag::NEW_ACCOUNTS_DEFAULT_TO_FA_APT_STORE)
+            },
+            SenderFunding::CoinStore => false,
+            SenderFunding::FungibleStore => true,
+        };
+        let use_concurrent_balance =
+            features.is_enabled(FeatureFlag::DEFAULT_TO_CONCURRENT_FUNGIBLE_BALANCE);
+        let mut rng = aptos_keygen::KeyGen::from_seed([0; 32]);
+        let (account, balance, use_fa_apt) = match &options.sender_account {
+            SenderAccount::Seeded => (
+                AccountData::with_account(
+                    Account::new_from_seed(&mut rng),
+                    options.sender_balance,
+                    0,
+                    use_fa_apt,
+                    use_concurrent_balance,
+                ),
+                options.sender_balance,
+                use_fa_apt,
+            ),
+            SenderAccount::Address(address) => {
+                let (privkey, pubkey) = rng.generate_ed25519_keypair();
+                (
+                    AccountData::with_account(
+                        Account::new_validator(*address, privkey, pubkey),
+                        options.sender_balance,
+                        0,
+                        use_fa_apt,
+                        use_concurrent_balance,
+                    ),
+                    options.sender_balance,
+                    use_fa_apt,
+                )
+            },
+            SenderAccount::Account(account) => match account.fungible_balance() {
+                Some(balance) => (account.clone(), balance, true),
+                None => (account.clone(), account.coin_balance().unwrap_or(0), false),
+            },
+        };
+        let allow_existing = !matches!(&options.sender_account, SenderAccount::Account(_));
+        install_sender_account(&state_view, &account, balance, use_fa_apt, allow_existing)?;
+
+        Ok(Self {
+            state_view,
+            proposal_dir: proposal_dir.to_path_buf(),
+            manifest,
+            options: options.clone(),
+            account,
+            num_executed_scripts: 0,
+            prev_gas_feature_version: None,
+            executed_functions: BTreeSet::new(),
+            assertions: AssertionFailures::new(options.assertion_mode),
+            cached_env: None,
+            transaction_outputs: vec![],
+        })
+    }
+
+    /// The current state, including the local changes made so far.
+    pub fn state_view(&self) -> &impl StateView {
+        &self.state_view
+    }
+
+    /// The options scripts are executed with, which may be changed between steps.
+    pub fn options_mut(&mut self) -> &mut SimulationOptions {
+        &mut self.options
+    }
+
+    /// The functions executed so far, if coverage is being captured.
+    pub fn executed_functions(&self) -> &BTreeSet<(ModuleId, Identifier)> {
+        &self.executed_functions
+    }
+
+    /// Returns the outputs of the transactions successfully executed since the last call, one
+    /// per script or multisig payload, in order. Empty unless
+    /// [`SimulationOptions::collect_transaction_outputs`] is set.
+    pub fn take_transaction_outputs(&mut self) -> Vec<ScriptOutput> {
+        std::mem::take(&mut self.transaction_outputs)
+    }
+
+    pub fn set_state_value(&self, state_key: StateKey, state_value: StateValue) {
+        self.state_view.set_state_value(state_key, state_value)
+    }
+
+    /// Discards the local change to the given state item, restoring its remote value.
+    pub fn remove_state_value(&self, state_key: &StateKey) {
+        self.state_view.remove_state_value(state_key)
+    }
+
+    pub fn set_on_chain_config<C>(&self, config: &C) -> Result<()>
+    where
+        C: OnChainConfig + Serialize,
+    {
+        self.state_view.set_on_chain_config(config)
+    }
+
+    pub fn apply_write_set(&self, write_set: WriteSet) {
+        self.state_view.apply_write_set(write_set)
+    }
+
+    /// Returns a snapshot of the local changes made so far, where `None` denotes a deleted
+    /// state item.
+    pub fn snapshot(&self) -> HashMap<StateKey, Option<StateValue>> {
+        self.state_view.states.lock().clone()
+    }
+
+    /// Replaces the local changes with a previously taken snapshot.
+    pub fn restore(&self, snapshot: HashMap<StateKey, Option<StateValue>>) {
+        *self.state_view.states.lock() = snapshot;
+    }
+
+    /// Returns the sequence number of the next transaction of the sender. It is read from the
+    /// account resource rather than counted, so that it stays correct after restoring a snapshot
+    /// or applying write sets that touch the sender.
+    fn next_sequence_number(&self) -> Result<u64> {
+        let account_resource = self
+            .state_view
+            .try_read_resource::<AccountResource>(self.account.address())?
+            .ok_or_else(|| anyhow!("sender account {} does not exist", self.account.address()))?;
+        Ok(account_resource.sequence_number())
+    }
+
+    /// Executes a compiled governance script at the current state and applies its changes.
+    ///
+    /// The last step of a proposal must not set a next execution hash, which is enforced if
+    /// `is_last_step` is set.
+    pub fn execute_script(
+        &mut self,
+        script_path: &Path,
+        compiled_script: &(Vec<u8>, HashValue),
+        is_last_step: bool,
+    ) -> Result<ScriptResult> {
+        let start_time = Instant::now();
+        let sequence_number = self.next_sequence_number()?;
+        let (script_blob, script_hash) = compiled_script;
+        let state_view = &self.state_view;
+        let options = &self.options;
+        let manifest = &self.manifest;
+        let account = &self.account;
+        let proposal_dir = self.proposal_dir.as_path();
+        let executed_functions = &mut self.executed_functions;
+        let assertions = &mut self.assertions;
+
+        let script_name = script_path.file_name().unwrap().to_string_lossy();
+        info!("    {}", script_name);
+
+        // Unless given otherwise, the only argument is the proposal id, which the patched resolve
+        // function ignores.
+        let (type_args, args) = ScriptArgs::load(script_path, options.dummy_proposal_id)?;
+
+        // Scripts that make decisions based on the voting state may behave differently on chain,
+        // where real votes exist.
+        let script = CompiledScript::deserialize(script_blob)
+            .map_err(|err| anyhow!("failed to deserialize script: {:?}", err))?;
+        let voting_record_references = find_voting_record_references(&script);
+        if !voting_record_references.is_empty() {
+            warn!(
+                "        Warning: the script reads voting records, which are synthetic in the \
+                 simulation, so its behavior may differ on chain: {}",
+                voting_record_references.join(", ")
+            );
+        }
+
+        // Force-end the epoch so that buffered configuration changes get applied.
+        let mut epoch_change_gas_report_path = None;
+        let mut epoch_change_text_gas_report_path = None;
+        if !options.no_epoch_force
+            && (self.num_executed_scripts > 0 || !options.skip_initial_epoch_force)
+        {
+            let epoch_before = fetch_epoch(state_view)?;
+            if options.profile_force_end_epoch {
+                let gas_log =
+                    force_end_epoch_profiled(state_view).context("failed to force end epoch")?;
+
+                let report_path = proposal_dir.join(GAS_PROFILING_DIR_NAME).join(format!(
+                    "{}-force-end-epoch",
+                    script_path.file_stem().unwrap().to_string_lossy()
+                ));
+                let (html_path, text_path) = write_gas_reports(
+                    &gas_log,
+                    &report_path,
+                    format!("Gas Report - force_end_epoch before {}", script_name),
+                    options.gas_report_format,
+                )?;
+                for path in html_path.iter().chain(text_path.iter()) {
+                    info!(
+                        "        Gas report of the forced epoch change saved to {}",
+                        path.display()
+                    );
+                }
+                epoch_change_gas_report_path = html_path;
+                epoch_change_text_gas_report_path = text_path;
+            } else {
+                force_end_epoch(state_view).context("failed to force end epoch")?;
+            }
+            let epoch_after = fetch_epoch(state_view)?;
+
+            // Each forced reconfiguration is expected to advance the epoch by exactly one.
+            // Anything else indicates that the simulation's own epoch machinery misbehaved.
+            if epoch_after != epoch_before + 1 {
+                warn!(
+                    "        Warning: forcing the epoch to end moved it from {} to {}, expected {}",
+                    epoch_before,
+                    epoch_after,
+                    epoch_before + 1
+                );
+            }
+        }
+
+        // Apply the feature flag overrides before every script, as the epoch change may have
+        // applied buffered feature flags over them.
+        if !options.enabled_features.is_empty() || !options.disabled_features.is_empty() {
+            state_view
+                .modify_on_chain_config(|features: &mut Features| {
+                    for flag in &options.enabled_features {
+                        features.enable(*flag);
+                    }
+                    for flag in &options.disabled_features {
+                        features.disable(*flag);
+                    }
+                    Ok(())
+                })
+                .context("failed to override feature flags")?;
+        }
+
+        // Fetch the on-chain configs that are needed for the simulation.
+        let chain_id =
+            ChainIdResource::fetch_config(state_view).context("failed to fetch chain id")?;
+
+        let gas_schedule =
+            GasScheduleV2::fetch_config(state_view).context("failed to fetch gas schedule v2")?;
+        let gas_feature_version = gas_schedule.feature_version;
+        let gas_params = AptosGasParameters::from_on_chain_gas_schedule(
+            &gas_schedule.into_btree_map(),
+            gas_feature_version,
+        )
+        .map_err(|err| {
+            anyhow!(
+                "failed to construct gas params at gas version {}: {}",
+                gas_feature_version,
+                err
+            )
+        })?;
+
+        // Gas behavior depends on the feature version, which a previous script may have changed
+        // by updating the gas schedule.
+        match self.prev_gas_feature_version {
+            Some(prev) if prev != gas_feature_version => info!(
+                "        Gas feature version: {} (changed from {})",
+                gas_feature_version, prev
+            ),
+            _ => info!("        Gas feature version: {}", gas_feature_version),
+        }
+        self.prev_gas_feature_version = Some(gas_feature_version);
+
+        // Patch framework functions to skip the governance process.
+        // This is redone every time we execute a script because the previous script could have
+        // overwritten the framework.
+        let deserializer_config = load_deserializer_config(state_view, options)?;
+
+        // Single-step proposals are resolved through `resolve` instead, which needs to be
+        // patched the same way.
+        let single_step = is_single_step_script(&script, &options.patch_plan);
+        if single_step {
+            info!("        Single-step proposal");
+        }
+
+        // If the script is the last step of the proposal, it MUST NOT have a next execution hash.
+        // Set the boolean flag to true to use a modified patch to catch this.
+        patch_aptos_governance(
+            state_view,
+            &deserializer_config,
+            &options.patch_plan,
+            single_step,
+            is_last_step,
+            options
+                .dump_patched_modules
+                .then(|| {
+                    proposal_dir.join(PATCHED_MODULES_DIR_NAME).join(format!(
+                        "{}-{}",
+                        self.num_executed_scripts,
+                        script_path.file_stem().unwrap().to_string_lossy()
+                    ))
+                })
+                .as_deref(),
+        )
+        .with_context(|| {
+            format!(
+                "failed to patch {}::{}",
+                options.patch_plan.governance_module,
+                if single_step {
+                    &*FUNC_NAME_RESOLVE
+                } else {
+                    &options.patch_plan.resolve_function
+                }
+            )
+        })?;
+
+        // Like the governance patch, the stubs need to be reapplied in case a previous script
+        // upgraded the stubbed modules.
+        patch_function_stubs(state_view, &deserializer_config, &options.function_stubs)
+            .context("failed to stub functions")?;
+
+        // Add the hash of the script to the list of approved hashes, so that the
+        // alternative (usually higher) execution limits can be used.
+        add_script_execution_hash(state_view, options.dummy_proposal_id, *script_hash)
+            .context("failed to add script execution hash")?;
+
+        // Constructing the environment is comparatively expensive, so reuse the one of the
+        // previous script if the configs it is derived from are unchanged. It is dropped whenever
+        // a script publishes modules, to ensure the loader is clean.
+        let env_configs = read_environment_configs(state_view)?;
+        let reuse_env = options.reuse_environment
+            && matches!(&self.cached_env, Some((configs, _)) if *configs == env_configs);
+        if !reuse_env {
+            self.cached_env = Some((
+                env_configs,
+                simulation_environment(state_view, options.force_paranoid),
+            ));
+        }
+        let env = self.cached_env.as_ref().unwrap().1.clone();
+        let vm = AptosVM::new(&env, state_view);
+        let log_context = AdapterLogSchema::new(state_view.id(), 0);
+
+        let resolver = state_view.as_move_resolver();
+        let code_storage = state_view.as_aptos_code_storage(&env);
+
+        let txn = account
+            .account()
+            .transaction()
+            .script(Script::new(script_blob.clone(), type_args, args))
+            .chain_id(chain_id.chain_id())
+            .sequence_number(sequence_number)
+            .gas_unit_price(gas_params.vm.txn.min_price_per_gas_unit.into())
+            .max_gas_amount(options.max_gas_amount)
+            .ttl(u64::MAX)
+            .sign();
+
+        // The gas profiler reconstructs the call graph from the VM's execution hooks, which is
+        // also what the coverage and the actions are derived from.
+        let (vm_status, vm_output, actions, (gas_report_path, text_gas_report_path)) =
+            if !options.profile_gas && !options.capture_coverage && !options.report_actions {
+                let (vm_status, vm_output) =
+                    vm.execute_user_transaction(&resolver, &code_storage, &txn, &log_context);
+                (vm_status, vm_output, vec![], (None, None))
+            } else {
+                let (vm_status, vm_output, gas_profiler) = vm
+                    .execute_user_transaction_with_modified_gas_meter(
+                        &resolver,
+                        &code_storage,
+                        &txn,
+                        &log_context,
+                        GasProfiler::new_script,
+                    )?;
+
+                let gas_log = gas_profiler.finish();
+
+                if options.capture_coverage {
+                    collect_executed_functions(&gas_log.exec_io.call_graph, executed_functions);
+                }
+
+                let gas_reports = if options.profile_gas {
+                    let report_path = proposal_dir
+                        .join(GAS_PROFILING_DIR_NAME)
+                        .join(script_path.file_stem().unwrap());
+                    let (html_path, text_path) = write_gas_reports(
+                        &gas_log,
+                        &report_path,
+                        format!("Gas Report - {}", script_name),
+                        options.gas_report_format,
+                    )?;
+                    for path in html_path.iter().chain(text_path.iter()) {
+                        info!("        Gas report saved to {}", path.display());
+                    }
+
+                    if options.open_report {
+                        if let Some(html_path) = &html_path {
+                            open_gas_report(&html_path.join("index.html"));
+                        }
+                    }
+                    (html_path, text_path)
+                } else {
+                    (None, None)
+                };
+
+                let actions = if options.report_actions {
+                    collect_script_actions(&gas_log.exec_io.call_graph)
+                } else {
+                    vec![]
+                };
+                for (idx, action) in actions.iter().enumerate() {
+                    info!(
+                        "        Action {}: {} (execution cost: {} internal gas units)",
+                        idx + 1,
+                        action.function,
+                        format_number(action.execution_cost, options.group_digits)
+                    );
+                }
+
+                (vm_status, vm_output, actions, gas_reports)
+            };
+
+        // Paranoid mode failures indicate type-safety issues, most likely introduced by upgraded
+        // or patched modules, rather than a problem with the script itself.
+        if is_paranoid_failure(&vm_status) {
+            bail!(
+                "script {} failed the VM's paranoid type checks: {:?}",
+                script_name,
+                vm_status
+            );
+        }
+
+        let fee_statement = FeeStatementRecord::from(vm_output.fee_statement());
+        fee_statement.print(options.group_digits);
+        info!(
+            "            Max gas amount: {}",
+            format_number(options.max_gas_amount, options.group_digits)
+        );
+        check_gas_budget(
+            fee_statement.gas_used,
+            options.max_gas_per_script,
+            &format!("script {}", script_name),
+            options.group_digits,
+            assertions,
+        )?;
+
+        let txn_output = vm_output
+            .try_materialize_into_transaction_output(&resolver)
+            .context("failed to materialize transaction output")?;
+
+        let txn_status = txn_output.status();
+        match txn_status {
+            TransactionStatus::Keep(ExecutionStatus::Success) => {
+                info!("        Success")
+            },
+            TransactionStatus::Keep(status)
+                if is_next_execution_hash_abort(status, &options.patch_plan) =>
+            {
+                // The abort does not carry the hash, so look it up in the script itself. It may
+                // also be computed at runtime, in which case it cannot be reported.
+                match find_next_execution_hash(&script, &options.patch_plan) {
+                    Some(next_hash) => bail!(
+                        "the last script has a non-zero next execution hash: 0x{}",
+                        hex::encode(next_hash)
+                    ),
+                    None => bail!("the last script has a non-zero next execution hash"),
+                }
+            },
+            TransactionStatus::Keep(ExecutionStatus::MoveAbort {
+                location,
+                code,
+                info,
+            }) => {
+                let description = describe_move_abort(
+                    state_view,
+                    &deserializer_config,
+                    location,
+                    *code,
+                    info.as_ref(),
+                );
+                error!("        Failure: {}", description);
+                bail!(
+                    "failed to execute governance script {}: {}",
+                    script_name,
+                    description
+                )
+            },
+            // The VM discards transactions that fail to read the state, e.g. because the remote
+            // state could not be fetched, which says nothing about the script itself.
+            TransactionStatus::Discard(StatusCode::STORAGE_ERROR) => {
+                let err = StateViewError::Other("failed to read the state".to_string());
+                return Err(anyhow::Error::new(err).context(format!(
+                    "failed to execute governance script {}",
+                    script_name
+                )));
+            },
+            _ => {
+                // Gas limits of the simulation are not necessarily the ones the script runs
+                // under on chain, so tell such failures apart from genuine ones.
+                if let Some(description) =
+                    describe_gas_failure(txn_status, options.max_gas_amount, options.group_digits)
+                {
+                    error!("        Failure: the script {}", description);
+                    bail!("governance script {} {}", script_name, description)
+                }
+                info!(
+                    "{}",
+                    format!("{:#?}", txn_status)
+                        .lines()
+                        .map(|line| format!("        {}", line))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                );
+                bail!("failed to execute governance script: {}", script_name)
+            },
+        }
+
+        let status = txn_status.clone();
+        if options.collect_transaction_outputs {
+            self.transaction_outputs
+                .push(ScriptOutput::new(&txn_output));
+        }
+        let (write_set, events) = txn_output.into();
+
+        // The write set includes the modules published by the script, which subsequent scripts
+        // must see. Verify them before applying them.
+        let published_modules = verify_published_modules(&write_set, &deserializer_config)
+            .with_context(|| format!("script {} published an invalid module", script_name))?;
+        if !published_modules.is_empty() {
+            // The caches of the environment may refer to the replaced modules.
+            self.cached_env = None;
+            info!(
+                "        Published {} module{}: {}",
+                published_modules.len(),
+                if published_modules.len() == 1 {
+                    ""
+                } else {
+                    "s"
+                },
+                published_modules
+                    .iter()
+                    .map(|module_id| module_id.short_str_lossless())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let write_set_summary = WriteSetSummary::new(&write_set);
+        info!("        Write set: {}", write_set_summary);
+        check_write_op_limit(
+            &script_name,
+            write_set_summary.num_write_ops(),
+            options.max_write_ops_per_script,
+            options.write_ops_limit_action,
+            assertions,
+        )?;
+
+        if options.warn_no_op
+            && !manifest.step(&script_name).allow_no_op
+            && !touches_on_chain_config(&write_set, &options.patch_plan)
+        {
+            warn!(
+                "        Warning: possible no-op, {} changed no on-chain config and published no modules",
+                script_name
+            );
+        }
+
+        let resolve_args = if options.patch_plan.record_resolve_args {
+            let resolve_args =
+                find_recorded_resolve_args(&options.patch_plan.governance_module, &write_set)?;
+            match &resolve_args {
+                Some(args) => info!(
+                    "        Resolve function received proposal id {} and signer address {}",
+                    args.proposal_id, args.signer_address
+                ),
+                None => warn!("        Warning: the resolve function was not called"),
+            }
+            resolve_args
+        } else {
+            None
+        };
+
+        state_view.apply_write_set(write_set);
+
+        // Go through the events once, retaining only the first `max_events_per_script`, so that
+        // the others are dropped right away instead of being held on to.
+        let mut num_events = 0;
+        let mut triggered_reconfiguration = false;
+        let mut retained_events = vec![];
+        for event in events {
+            num_events += 1;
+            // With randomness enabled, reconfiguring starts DKG rather than a new epoch right
+            // away.
+            let type_tag = event.type_tag();
+            triggered_reconfiguration |= *type_tag == *NEW_EPOCH_EVENT_MOVE_TYPE_TAG
+                || *type_tag == *NEW_EPOCH_EVENT_V2_MOVE_TYPE_TAG
+                || *type_tag == *DKG_START_EVENT_MOVE_TYPE_TAG;
+            if retained_events.len() < options.max_events_per_script {
+                retained_events.push(event);
+            }
+        }
+
+        if options.require_reconfiguration
+            && !triggered_reconfiguration
+            && !manifest.step(&script_name).allow_no_reconfiguration
+        {
+            assertions.fail(format!("{} did not trigger a reconfiguration", script_name))?;
+        }
+
+        if options.print_events {
+            print_events(state_view, &retained_events);
+        }
+
+        let captured_events = retained_events
+            .into_iter()
+            .map(|event| CapturedEvent {
+                type_tag: event.type_tag().to_canonical_string(),
+                data: event.event_data().to_vec(),
+            })
+            .collect::<Vec<_>>();
+        if captured_events.len() < num_events {
+            warn!(
+                "        Warning: {} events emitted, only the first {} were retained",
+                num_events,
+                captured_events.len()
+            );
+        }
+
+        self.num_executed_scripts += 1;
+
+        let execution_time = start_time.elapsed();
+        info!("        Executed in {:.2}s", execution_time.as_secs_f64());
+
+        Ok(ScriptResult {
+            script_name: script_name.to_string(),
+            status,
+            gas_feature_version,
+            fee_statement,
+            write_set_summary,
+            triggered_reconfiguration,
+            num_events,
+            events: captured_events,
+            resolve_args,
+            actions,
+            published_modules: published_modules
+                .iter()
+                .map(|module_id| module_id.short_str_lossless())
+                .collect(),
+            gas_report_path,
+            text_gas_report_path,
+            compile_time: None,
+            execution_time,
+            epoch_change_gas_report_path,
+            epoch_change_text_gas_report_path,
+        })
+    }
+
+    /// Executes the compiled scripts of a proposal in order, followed by the multisig payloads
+    /// listed in its manifest.
+    fn execute_scripts(
+        &mut self,
+        proposal_scripts: &[PathBuf],
+        compiled_scripts: &[(Vec<u8>, HashValue)],
+    ) -> Result<Vec<ScriptResult>> {
+        verify_execution_hash_chain(proposal_scripts, compiled_scripts, &self.options.patch_plan)?;
+
+        let mut script_results = vec![];
+        for (script_idx, (script_path, compiled_script)) in
+            proposal_scripts.iter().zip(compiled_scripts).enumerate()
+        {
+            let is_last_step = script_idx == proposal_scripts.len() - 1;
+            script_results.push(self.execute_script(script_path, compiled_script, is_last_step)?);
+        }
+        for payload in self.manifest.multisig_payloads.clone() {
+            let entry_function = payload.entry_function()?;
+            self.execute_multisig_payload(payload.multisig_address, entry_function)
+                .with_context(|| {
+                    format!(
+                        "failed to execute multisig payload {} of {}",
+                        payload.function, payload.multisig_address
+                    )
+                })?;
+        }
+        Ok(script_results)
+    }
+
+    /// Executes an entry function as the payload of a multisig transaction of the given multisig
+    /// account, sent from the simulation account, with the owner and approval checks skipped. See
+    /// [`patch_multisig_account`].
+    ///
+    /// The payload is executed on top of the state left by the previously executed scripts or
+    /// payloads, and its changes are applied the same way. The patch is reverted afterwards, so
+    /// that it does not affect what is executed next.
+    pub fn execute_multisig_payload(
+        &mut self,
+        multisig_address: AccountAddress,
+        entry_function: EntryFunction,
+    ) -> Result<FeeStatementRecord> {
+        let sequence_number = self.next_sequence_number()?;
+        let state_view = &self.state_view;
+        let options = &self.options;
+        info!(
+            "    Multisig payload {}::{} of {}",
+            entry_function.module(),
+            entry_function.function(),
+            multisig_address
+        );
+
+        let deserializer_config = load_deserializer_config(state_view, options)?;
+        patch_function_stubs(state_view, &deserializer_config, &options.function_stubs)
+            .context("failed to stub functions")?;
+
+        let (fee_statement, txn_output) =
+            with_temporary_patch(state_view, &MODULE_ID_MULTISIG_ACCOUNT, || {
+                patch_multisig_account(state_view, &deserializer_config)
+                    .with_context(|| format!("failed to patch {}", *MODULE_ID_MULTISIG_ACCOUNT))?;
+                execute_patched_multisig_payload(
+                    state_view,
+                    options,
+                    &self.account,
+                    sequence_number,
+                    &deserializer_config,
+                    multisig_address,
+                    entry_function,
+                )
+            })?;
+
+        if options.collect_transaction_outputs {
+            self.transaction_outputs
+                .push(ScriptOutput::new(&txn_output));
+        }
+        let (write_set, _events) = txn_output.into();
+        state_view.apply_write_set(write_set);
+        self.num_executed_scripts += 1;
+
+        Ok(fee_statement)
+    }
+
+    /// Returns an error listing the failed assertions, if there are any.
+    pub fn finish(self) -> Result<()> {
+        self.assertions.finish()
+    }
+}
+
+/// The parts of the output of an executed transaction that tools inspect, see
+/// [`ProposalSimulation`].
+#[derive(Clone, Debug)]
+pub struct ScriptOutput {
+    pub status: TransactionStatus,
+    pub gas_used: u64,
+    pub write_set: WriteSet,
+    pub events: Vec<ContractEvent>,
+}
+
+impl ScriptOutput {
+    fn new(txn_output: &TransactionOutput) -> Self {
+        Self {
+            status: txn_output.status().clone(),
+            gas_used: txn_output.gas_used(),
+            write_set: txn_output.write_set().clone(),
+            events: txn_output.events().to_vec(),
+        }
+    }
+}
+
+/// The complete outcome of simulating a proposal, for tools that inspect it further.
+#[derive(Debug)]
+pub struct ProposalSimulation {
+    pub report: SimulationReport,
+    /// The output of each script, in order, if
+    /// [`SimulationOptions::collect_transaction_outputs`] is set.
+    pub transaction_outputs: Vec<ScriptOutput>,
+    /// The state items changed by the scripts, where `None` denotes a deleted state item. This
+    /// includes the patches and overrides applied by the simulation.
+    pub overlay: Vec<(StateKey, Option<StateValue>)>,
+    /// The state keys read from the remote state, sorted.
+    pub remote_reads: Vec<StateKey>,
+}
+
+/// Simulates a multi-step proposal and returns the results of its scripts.
+pub async fn simulate_multistep_proposal(
+    remote_url: Url,
+    proposal_dir: &Path,
+    proposal_scripts: &[PathBuf],
+    options: &SimulationOptions,
+) -> Result<SimulationReport> {
+    compile_and_simulate_proposal(remote_url, proposal_dir, proposal_scripts, options)
+        .await
+        .map(|simulation| simulation.report)
+}
+
+/// Like [`simulate_multistep_proposal`], but additionally returns the transaction outputs of
+/// the scripts and the resulting state changes, e.g. to run custom checks on them.
+pub async fn simulate_multistep_proposal_with_outputs(
+    remote_url: Url,
+    proposal_dir: &Path,
+    proposal_scripts: &[PathBuf],
+    options: &SimulationOptions,
+) -> Result<ProposalSimulation> {
+    let options = SimulationOptions {
+        collect_transaction_outputs: true,
+        ..options.clone()
+    };
+    compile_and_simulate_proposal(remote_url, proposal_dir, proposal_scripts, &options).await
+}
+
+async fn compile_and_simulate_proposal(
+    remote_url: Url,
+    proposal_dir: &Path,
+    proposal_scripts: &[PathBuf],
+    options: &SimulationOptions,
+) -> Result<ProposalSimulation> {
+    info!("Simulating proposal at {}", proposal_dir.display());
+
+    // Compile all scripts.
+    info!(
+        "Compiling scripts against {}...",
+        read_framework_version(&options.framework_path())?
+    );
+    let (compiled_scripts, compile_times) = compile_scripts(proposal_scripts, options)?;
+
+    simulate_compiled_proposal(
+        remote_url,
+        proposal_dir,
+        proposal_scripts,
+        &compiled_scripts,
+        &compile_times,
+        options,
+        None,
+    )
+    .await
+}
+
+/// Simulates an already compiled multi-step proposal on top of the remote state at the given
+/// version. If not specified, the version in the proposal manifest or the simulation options is
+/// used, falling back to the latest version.
+///
+/// The time each script took to compile, if known, is recorded in the report.
+async fn simulate_compiled_proposal(
+    remote_url: Url,
+    proposal_dir: &Path,
+    proposal_scripts: &[PathBuf],
+    compiled_scripts: &[(Vec<u8>, HashValue)],
+    compile_times: &[Duration],
+    options: &SimulationOptions,
+    version: Option<u64>,
+) -> Result<ProposalSimulation> {
+    let mut session =
+        SimulationSession::connect(remote_url, proposal_dir, options, version).await?;
+    // Record where the proposal is simulated upfront, so that a failure can be traced back to it
+    // as well. The context is attached to the error, see [`ProposalReport::context`].
+    let context = SimulationContext::new(&session)?;
+    info!("Simulating on {}", context);
+    let res = execute_compiled_proposal(
+        &mut session,
+        proposal_dir,
+        proposal_scripts,
+        compiled_scripts,
+        compile_times,
+        options,
+        context.clone(),
+    )
+    .map_err(|err| err.context(context));
+
+    // The snapshot is also saved if the proposal failed, so that the failure can be reproduced
+    // offline.
+    session.save_state_snapshot()?;
+
+    if options.record_remote_reads {
+        let remote_reads = session.state_view.remote_reads();
+        let remote_reads_path = proposal_dir.join(REMOTE_READS_FILE_NAME);
+        let mut contents = String::new();
+        for state_key in &remote_reads {
+            contents.push_str(&format!("{:?}\n", state_key));
+        }
+        std::fs::write(&remote_reads_path, contents).with_context(|| {
+            format!(
+                "failed to write remote reads to {}",
+                remote_reads_path.display()
+            )
+        })?;
+        info!(
+            "Read {} state item{} from the remote state, list saved to {}",
+            remote_reads.len(),
+            if remote_reads.len() == 1 { "" } else { "s" },
+            remote_reads_path.display()
+        );
+    }
+
+    let simulation = res?;
+    session.finish()?;
+
+    info!("All scripts succeeded!");
+
+    Ok(simulation)
+}
+
+/// Executes an entry function as the payload of a multisig transaction, once
+/// `0x1::multisig_account` is patched, and returns the transaction output without applying it.
+fn execute_patched_multisig_payload(
+    state_view: &SimulationStateView<impl StateView>,
+    options: &SimulationOptions,
+    account: &AccountData,
+    sequence_number: u64,
+    deserializer_config: &DeserializerConfig,
+    multisig_address: AccountAddress,
+    entry_function: EntryFunction,
+) -> Result<(FeeStatementRecord, TransactionOutput)> {
+    let env = simulation_environment(state_view, options.force_paranoid);
+    let gas_params = env
+        .gas_params()
+        .as_ref()
+        .map_err(|err| anyhow!("failed to load gas params: {}", err))?;
+    let vm = AptosVM::new(&env, state_view);
+    let log_context = AdapterLogSchema::new(state_view.id(), 0);
+    let resolver = state_view.as_move_resolver();
+    let code_storage = state_view.as_aptos_code_storage(&env);
+
+    let txn = account
+        .account()
+        .transaction()
+        .payload(TransactionPayload::Multisig(Multisig {
+            multisig_address,
+            transaction_payload: Some(MultisigTransactionPayload::EntryFunction(
+                entry_function.clone(),
+            )),
+        }))
+        .chain_id(env.chain_id())
+        .sequence_number(sequence_number)
+        .gas_unit_price(gas_params.vm.txn.min_price_per_gas_unit.into())
+        .max_gas_amount(options.max_gas_amount)
+        .ttl(u64::MAX)
+        .sign();
+    let (_vm_status, vm_output) =
+        vm.execute_user_transaction(&resolver, &code_storage, &txn, &log_context);
+
+    let fee_statement = FeeStatementRecord::from(vm_output.fee_statement());
+    fee_statement.print(options.group_digits);
+
+    let txn_output = vm_output
+        .try_materialize_into_transaction_output(&resolver)
+        .context("failed to materialize transaction output")?;
+    match txn_output.status() {
+        TransactionStatus::Keep(ExecutionStatus::Success) => info!("        Success"),
+        TransactionStatus::Keep(ExecutionStatus::MoveAbort {
+            location: AbortLocation::Module(module_id),
+            code: MAGIC_FAILED_MULTISIG_PAYLOAD,
+            ..
+        }) if *module_id == *MODULE_ID_MULTISIG_ACCOUNT => {
+            // The VM does not pass on why the payload failed, so execute it directly, as the
+            // multisig account, to find out.
+            let mut args = vec![];
+            if takes_signer(
+                state_view,
+                deserializer_config,
+                entry_function.module(),
+                entry_function.function(),
+            )? {
+                args.extend(serialize_args(vec![MoveValue::Signer(multisig_address)]));
+            }
+            args.extend(entry_function.args().iter().cloned());
+            let res = execute_function_with_gas_meter(
+                state_view,
+                &env,
+                entry_function.module(),
+                entry_function.function(),
+                entry_function.ty_args().to_vec(),
+                args,
+                &mut UnmeteredGasMeter,
+            );
+            match res {
+                Ok(_) => {
+                    bail!("the multisig payload failed, but succeeds when executed directly")
+                },
+                Err(err) => bail!("the multisig payload failed: {:#}", err),
+            }
+        },
+        status => bail!("failed to execute multisig transaction: {:?}", status),
+    }
+
+    Ok((fee_statement, txn_output))
+}
+
+/// Executes the compiled scripts of a proposal in the given session and runs the checks enabled
+/// in the simulation options, leaving the failed assertions for the caller to report.
+fn execute_compiled_proposal(
+    session: &mut SimulationSession<RemoteStateView>,
+    proposal_dir: &Path,
+    proposal_scripts: &[PathBuf],
+    compiled_scripts: &[(Vec<u8>, HashValue)],
+    compile_times: &[Duration],
+    options: &SimulationOptions,
+    mut context: SimulationContext,
+) -> Result<ProposalSimulation> {
+    // Decode the consensus config upfront, so that an undecodable config is reported before any
+    // script is executed. Changes already buffered for the next epoch are part of it, so that
+    // they are not attributed to the proposal.
+    let consensus_config_before = options
+        .summarize_consensus_config
+        .then(|| fetch_next_consensus_config(session.state_view()))
+        .transpose()?;
+
+    if options.verify_approved_hashes {
+        if let Some((_, script_hash)) = compiled_scripts.first() {
+            verify_approved_execution_hash(session.state_view.remote(), *script_hash)?;
+        }
+    }
+
+    // Execute the governance scripts in sorted order.
+    info!("Executing governance scripts...");
+    let pre_execution = session.snapshot();
+    let mut scripts = session.execute_scripts(proposal_scripts, compiled_scripts)?;
+    record_compile_times(&mut scripts, compile_times);
+    let report = SimulationReport {
+        proposal_dir: proposal_dir.to_path_buf(),
+        context: {
+            context.epoch_after = Some(fetch_epoch(&session.state_view)?);
+            context
+        },
+        scripts,
+    };
+    // Capture the outcome before the idempotency check executes the scripts again.
+    let transaction_outputs = session.take_transaction_outputs();
+    let overlay = session.state_view.overlay();
+    report.print_human_readable(options.group_digits);
+    if options.profile_gas {
+        report.write_gas_report_index(options.group_digits)?;
+    }
+    check_gas_budget(
+        report.total_gas_used(),
+        options.max_total_gas,
+        "the proposal",
+        options.group_digits,
+        &mut session.assertions,
+    )?;
+
+    if let Some(before) = &consensus_config_before {
+        let after = fetch_next_consensus_config(session.state_view())?;
+        let consensus_changes = summarize_consensus_config(before, &after)?;
+        if consensus_changes.is_empty() {
+            info!("Consensus config unchanged");
+        } else {
+            info!("Consensus config changes:");
+            for line in &consensus_changes {
+                info!("    {}", line);
+            }
+        }
+    }
+
+    if options.summarize_staking {
+        let staking_changes = summarize_staking_changes(&collect_resource_changes(
+            &session.state_view,
+            &pre_execution,
+        )?)?;
+        if staking_changes.is_empty() {
+            info!("No staking resources changed");
+        } else {
+            info!("Staking changes:");
+            for line in &staking_changes {
+                info!("    {}", line);
+            }
+        }
+    }
+
+    if options.resource_diff {
+        let resource_changes = collect_resource_changes(&session.state_view, &pre_execution)?;
+        let diff_path = proposal_dir.join(RESOURCE_DIFF_FILE_NAME);
+        std::fs::write(
+            &diff_path,
+            render_resource_diff(&session.state_view, &resource_changes),
+        )
+        .with_context(|| format!("failed to write resource diff to {}", diff_path.display()))?;
+        info!(
+            "{} resource{} changed, diff saved to {}",
+            resource_changes.len(),
+            if resource_changes.len() == 1 { "" } else { "s" },
+            diff_path.display()
+        );
+    }
+
+    if options.check_idempotency {
+        info!("Re-executing governance scripts from the same state to check idempotency...");
+
+        // Start the rerun from exactly where the first run started, including whether the epoch
+        // is forced to end before the first script.
+        let first_run = session.snapshot();
+        let num_executed_scripts = session.num_executed_scripts;
+        session.restore(pre_execution);
+        session.num_executed_scripts = 0;
+        let rerun_options = SimulationOptions {
+            profile_gas: false,
+            capture_coverage: false,
+            report_actions: false,
+            // The first run already reported and checked these.
+            warn_no_op: false,
+            require_reconfiguration: false,
+            print_events: false,
+            dump_patched_modules: false,
+            patch_plan: PatchPlan {
+                record_resolve_args: false,
+                ..options.patch_plan.clone()
+            },
+            max_write_ops_per_script: None,
+            max_gas_per_script: None,
+            ..options.clone()
+        };
+        let original_options = std::mem::replace(session.options_mut(), rerun_options);
+        session
+            .execute_scripts(proposal_scripts, compiled_scripts)
+            .context("failed to re-execute the proposal")?;
+        *session.options_mut() = original_options;
+        let second_run = session.snapshot();
+        // Carry on from the outcome of the first run.
+        session.restore(first_run.clone());
+        session.num_executed_scripts = num_executed_scripts;
+
+        let differences = find_rerun_differences(
+            &session.state_view,
+            &first_run,
+            &second_run,
+            session.account.address(),
+            &options.patch_plan,
+        )?;
+        if differences.is_empty() {
+            info!("    The proposal is idempotent");
+        } else {
+            warn!(
+                "    Warning: {} state item{} differ when re-executing the proposal:",
+                differences.len(),
+                if differences.len() == 1 { "" } else { "s" }
+            );
+            for state_key in &differences {
+                info!("        {:?}", state_key);
+            }
+        }
+    }
+
+    if options.capture_coverage {
+        let coverage_path = proposal_dir.join(COVERAGE_FILE_NAME);
+        let executed_functions = session.executed_functions();
+        write_coverage_report(&coverage_path, executed_functions)?;
+        info!(
+            "Executed {} distinct function{}, list saved to {}",
+            executed_functions.len(),
+            if executed_functions.len() == 1 {
+                ""
+            } else {
+                "s"
+            },
+            coverage_path.display()
+        );
+    }
+
+    if options.export_overlay {
+        let overlay_path = proposal_dir
+            .join(OVERLAY_FILE_STEM)
+            .with_extension(options.overlay_format.extension());
+        let num_state_items = session
+            .state_view
+            .export_overlay(&overlay_path, options.overlay_format)?;
+        info!(
+            "Exported {} changed state item{} to {}",
+            num_state_items,
+            if num_state_items == 1 { "" } else { "s" },
+            overlay_path.display()
+        );
+    }
+
+    Ok(ProposalSimulation {
+        report,
+        transaction_outputs,
+        overlay,
+        remote_reads: session.state_view.remote_reads(),
+    })
+}
+
+/// Returns true if the error was caused by failing to talk to the REST endpoint or to read the
+/// remote state, rather than by the proposal itself.
+fn is_transport_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause.is::<RestError>() || cause.is::<reqwest::Error>() || cause.is::<StateViewError>()
+    })
+}
+
+/// Finds the earliest version within the given range at which the proposal can be executed
+/// successfully, by simulating it at versions narrowed down through binary search.
+///
+/// This assumes that once the proposal succeeds at some version, it also succeeds at all later
+/// versions. Returns `None` if the proposal fails even at the end of the range. Errors reaching
+/// the remote state are returned rather than treated as failures of the proposal.
+pub async fn find_min_compatible_version(
+    remote_url: Url,
+    proposal_dir: &Path,
+    proposal_scripts: &[PathBuf],
+    version_range: RangeInclusive<u64>,
+    options: &SimulationOptions,
+) -> Result<Option<u64>> {
+    info!("Compiling scripts...");
+    let (compiled_scripts, compile_times) = compile_scripts(proposal_scripts, options)?;
+
+    let succeeds_at = |version: u64| {
+        let remote_url = remote_url.clone();
+        let compiled_scripts = &compiled_scripts;
+        let compile_times = &compile_times;
+        async move {
+            info!("Simulating proposal at version {}", version);
+            match simulate_compiled_proposal(
+                remote_url,
+                proposal_dir,
+                proposal_scripts,
+                compiled_scripts,
+                compile_times,
+                options,
+                Some(version),
+            )
+            .await
+            {
+                Ok(_) => Ok(true),
+                // Failing to reach the endpoint says nothing about whether the proposal is
+                // compatible with the version.
+                Err(err) if is_transport_error(&err) => Err(err.context(format!(
+                    "failed to simulate proposal at version {}",
+                    version
+                ))),
+                Err(err) => {
+                    info!("Simulation failed at version {}: {:#}", version, err);
+                    Ok(false)
+                },
+            }
+        }
+    };
+
+    let (mut low, mut high) = version_range.into_inner();
+    if low > high {
+        bail!("invalid version range {}..={}", low, high);
+    }
+    if !succeeds_at(high).await? {
+        return Ok(None);
+    }
+
+    // Invariant: the proposal succeeds at `high`, and fails at all versions before `low`.
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if succeeds_at(mid).await? {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+
+    info!("Earliest compatible version: {}", high);
+    Ok(Some(high))
+}
+
+/// Compares file names in natural order, i.e. comparing runs of digits by their numeric value, so
+/// that `2_foo.move` comes before `10_bar.move`. Names that only differ in leading zeros are
+/// compared lexicographically.
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    fn chunks(s: &str) -> impl Iterator<Item = &str> {
+        let mut rest = s;
+        std::iter::from_fn(move || {
+            let first = rest.chars().next()?;
+            let len = rest
+                .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let (chunk, tail) = rest.split_at(len);
+            rest = tail;
+            Some(chunk)
+        })
+    }
+
+    let mut lhs = chunks(a);
+    let mut rhs = chunks(b);
+    loop {
+        let ord = match (lhs.next(), rhs.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(l), Some(r)) => {
+                if l.starts_with(|c: char| c.is_ascii_digit())
+                    && r.starts_with(|c: char| c.is_ascii_digit())
+                {
+                    // Compare by value without parsing, so that long numbers cannot overflow.
+                    let l = l.trim_start_matches('0');
+                    let r = r.trim_start_matches('0');
+                    l.len().cmp(&r.len()).then_with(|| l.cmp(r))
+                } else {
+                    l.cmp(r)
+                }
+            },
+        };
+        if ord.is_ne() {
+            return ord;
+        }
+    }
+}
+
+/// Orders the scripts of a proposal as listed in its manifest, checking that the list matches the
+/// scripts present.
+fn order_scripts(mut move_files: Vec<PathBuf>, order: &[String]) -> Result<Vec<PathBuf>> {
+    let mut ordered = Vec::with_capacity(order.len());
+    for script_name in order {
+        let idx = move_files
+            .iter()
+            .position(|path| path.file_name() == Some(std::ffi::OsStr::new(script_name)))
+            .ok_or_else(|| {
+                anyhow!(
+                    "script {} listed in the manifest does not exist or is listed twice",
+                    script_name
+                )
+            })?;
+        ordered.push(move_files.remove(idx));
+    }
+    if !move_files.is_empty() {
+        bail!(
+            "scripts missing from the manifest: {}",
+            move_files
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(ordered)
+}
+
+/// Collects the proposals under the given directory, i.e. the directories directly containing
+/// `.move` scripts, along with their scripts. Proposals are sorted lexicographically, and their
+/// scripts in natural order (see [`natural_cmp`]) unless the proposal manifest lists them in an
+/// explicit order.
+///
+/// Precompiled `.mv` files next to the scripts are not collected separately, but are used in
+/// place of compiling the corresponding script.
+pub fn collect_proposals(root_dir: &Path) -> Result<Vec<(PathBuf, Vec<PathBuf>)>> {
+    let mut proposals: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+
+    for entry in WalkDir::new(root_dir).min_depth(1) {
+        let entry = entry
+            .with_context(|| format!("failed to traverse directory {}", root_dir.display()))?;
+        let path = entry.path();
+        if path.is_file() && path.extension() == Some(std::ffi::OsStr::new("move")) {
+            if let Some(parent) = path.parent() {
+                proposals
+                    .entry(parent.to_path_buf())
+                    .or_default()
+                    .push(path.to_path_buf());
+            }
+        }
+    }
+
+    proposals
+        .into_iter()
+        .map(|(proposal_dir, mut move_files)| {
+            move_files.sort_by(|a, b| {
+                natural_cmp(
+                    &a.file_name().unwrap_or_default().to_string_lossy(),
+                    &b.file_name().unwrap_or_default().to_string_lossy(),
+                )
+            });
+            let manifest = ProposalManifest::load(&proposal_dir)?;
+            let move_files = match &manifest.scripts {
+                Some(order) => order_scripts(move_files, order).with_context(|| {
+                    format!("invalid script order for {}", proposal_dir.display())
+                })?,
+                None => move_files,
+            };
+            Ok((proposal_dir, move_files))
+        })
+        .collect()
+}
+
+/// Differences in the scripts of a proposal present in both release directories.
+#[derive(Debug, Default)]
+pub struct ProposalDiff {
+    pub proposal: PathBuf,
+    pub added_scripts: Vec<String>,
+    pub removed_scripts: Vec<String>,
+    pub changed_scripts: Vec<String>,
+}
+
+/// Structural and content differences between two release directories.
+#[derive(Debug, Default)]
+pub struct ReleaseDiff {
+    pub added_proposals: Vec<PathBuf>,
+    pub removed_proposals: Vec<PathBuf>,
+    pub changed_proposals: Vec<ProposalDiff>,
+}
+
+impl ReleaseDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_proposals.is_empty()
+            && self.removed_proposals.is_empty()
+            && self.changed_proposals.is_empty()
+    }
+
+    pub fn print(&self) {
+        if self.is_empty() {
+            info!("No differences found.");
+            return;
+        }
+        for proposal in &self.added_proposals {
+            info!("+ {}", proposal.display());
+        }
+        for proposal in &self.removed_proposals {
+            info!("- {}", proposal.display());
+        }
+        for diff in &self.changed_proposals {
+            info!("~ {}", diff.proposal.display());
+            for script in &diff.added_scripts {
+                info!("    + {}", script);
+            }
+            for script in &diff.removed_scripts {
+                info!("    - {}", script);
+            }
+            for script in &diff.changed_scripts {
+                info!("    ~ {}", script);
+            }
+        }
+    }
+}
+
+/// Collects the proposals of a release directory, keyed by their path relative to the root,
+/// with the content hash of each script keyed by its file name.
+fn hash_release_scripts(root_dir: &Path) -> Result<BTreeMap<PathBuf, BTreeMap<String, HashValue>>> {
+    let mut result = BTreeMap::new();
+
+    for (proposal_dir, scripts) in collect_proposals(root_dir)? {
+        let relative_dir = proposal_dir
+            .strip_prefix(root_dir)
+            .unwrap_or(&proposal_dir)
+            .to_path_buf();
+
+        let mut hashes = BTreeMap::new();
+        for script_path in scripts {
+            let content = std::fs::read(&script_path)
+                .with_context(|| format!("failed to read script {}", script_path.display()))?;
+            let script_name = script_path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            hashes.insert(script_name, HashValue::sha3_256_of(&content));
+        }
+
+        result.insert(relative_dir, hashes);
+    }
+
+    Ok(result)
+}
+
+/// Compares the proposals of two release directories without compiling or executing them.
+///
+/// Proposals are matched by their path relative to the release root, and scripts by file name
+/// and content hash.
+pub fn diff_releases(old_dir: &Path, new_dir: &Path) -> Result<ReleaseDiff> {
+    let old = hash_release_scripts(old_dir)
+        .with_context(|| format!("failed to collect proposals in {}", old_dir.display()))?;
+    let new = hash_release_scripts(new_dir)
+        .with_context(|| format!("failed to collect proposals in {}", new_dir.display()))?;
+
+    let mut diff = ReleaseDiff::default();
+
+    for (proposal, new_scripts) in &new {
+        let Some(old_scripts) = old.get(proposal) else {
+            diff.added_proposals.push(proposal.clone());
+            continue;
+        };
+
+        let mut proposal_diff = ProposalDiff {
+            proposal: proposal.clone(),
+            ..Default::default()
+        };
+        for (script, hash) in new_scripts {
+            match old_scripts.get(script) {
+                None => proposal_diff.added_scripts.push(script.clone()),
+                Some(old_hash) if old_hash != hash => {
+                    proposal_diff.changed_scripts.push(script.clone())
+                },
+                Some(_) => (),
+            }
+        }
+        for script in old_scripts.keys() {
+            if !new_scripts.contains_key(script) {
+                proposal_diff.removed_scripts.push(script.clone());
+            }
+        }
+
+        if !proposal_diff.added_scripts.is_empty()
+            || !proposal_diff.removed_scripts.is_empty()
+            || !proposal_diff.changed_scripts.is_empty()
+        {
+            diff.changed_proposals.push(proposal_diff);
+        }
+    }
+
+    for proposal in old.keys() {
+        if !new.contains_key(proposal) {
+            diff.removed_proposals.push(proposal.clone());
+        }
+    }
+
+    Ok(diff)
+}
+
+pub async fn simulate_all_proposals(
+    remote_url: Url,
+    output_dir: &Path,
+    options: &SimulationOptions,
+) -> Result<ReleaseSimulationReport> {
+    let proposals =
+        collect_proposals(output_dir).context("failed to collect proposals for simulation")?;
+
+    if proposals.is_empty() {
+        bail!("failed to simulate proposals: no proposals found")
+    }
+
+    info!(
+        "Found {} proposal{}",
+        proposals.len(),
+        if proposals.len() == 1 { "" } else { "s" }
+    );
+    for (proposal_dir, proposal_scripts) in &proposals {
+        info!("    {}", proposal_dir.display());
+
+        for script_path in proposal_scripts {
+            info!(
+                "        {}",
+                script_path.file_name().unwrap().to_string_lossy()
+            );
+        }
+    }
+
+    // Resolve the remote version once, so that all proposals see the same remote state and share
+    // a cache of the remote state values, which is dropped once the release has been simulated.
+    let pinned_options;
+    let options = if !options.offline && options.local_genesis.is_none() {
+        let version = match options.at_version {
+            Some(version) => version,
+            None => {
+                let client = options.remote_client(&remote_url)?;
+                options
+                    .remote_retry_policy
+                    .retry_async("fetch ledger information", || {
+                        client.get_ledger_information()
+                    })
+                    .await?
+                    .into_inner()
+                    .version
+            },
+        };
+        info!("Simulating proposals at version {}", version);
+        pinned_options = SimulationOptions {
+            at_version: Some(version),
+            remote_state_cache: Some(Arc::new(RemoteStateCache::new(version))),
+            ..options.clone()
+        };
+        &pinned_options
+    } else {
+        options
+    };
+
+    let framework_version = read_framework_version(&options.framework_path())?;
+    info!(
+        "Compiling scripts of all proposals against {}...",
+        framework_version
+    );
+    let mut report = ReleaseSimulationReport {
+        framework_version: Some(framework_version),
+        ..Default::default()
+    };
+
+    // Compile the scripts of all proposals upfront, so that the workers are kept busy across
+    // proposal boundaries. Execution still happens one proposal at a time, in order.
+    let all_scripts = proposals
+        .iter()
+        .flat_map(|(_, proposal_scripts)| proposal_scripts.iter().cloned())
+        .collect::<Vec<_>>();
+    let compilation = ScriptCompilation::run(&all_scripts, options);
+    let compile_failures = compilation.failures(&all_scripts);
+    let (all_compiled_scripts, all_compile_times) = match compilation.finish(&all_scripts, options)
+    {
+        Ok(compiled_scripts) => compiled_scripts,
+        Err(err) => {
+            // Report the proposals with scripts that failed to compile as failed, and the
+            // others as skipped.
+            for (proposal_dir, proposal_scripts) in &proposals {
+                let failures = compile_failures
+                    .iter()
+                    .filter(|(script_path, _)| proposal_scripts.contains(script_path))
+                    .map(|(_, failure)| failure.as_str())
+                    .collect::<Vec<_>>();
+                if failures.is_empty() {
+                    report.proposals.push(ProposalReport::skipped(proposal_dir));
+                } else {
+                    let compile_errors_path = proposal_dir.join(COMPILE_ERRORS_FILE_NAME);
+                    std::fs::write(&compile_errors_path, failures.join("\n\n")).with_context(
+                        || {
+                            format!(
+                                "failed to write compile errors to {}",
+                                compile_errors_path.display()
+                            )
+                        },
+                    )?;
+                    report.proposals.push(ProposalReport {
+                        proposal_dir: proposal_dir.clone(),
+                        duration: Duration::ZERO,
+                        script_results: vec![],
+                        failure: Some(failures.join("\n")),
+                        skipped: false,
+                        context: None,
+                    });
+                }
+            }
+            report.write_outputs(output_dir, options)?;
+            return Err(err.context("failed to compile proposals"));
+        },
+    };
+
+    let mut remaining_compiled_scripts = all_compiled_scripts.as_slice();
+    let mut remaining_compile_times = all_compile_times.as_slice();
+    let compiled_scripts = proposals
+        .iter()
+        .map(|(_, proposal_scripts)| {
+            let (compiled_scripts, rest) =
+                remaining_compiled_scripts.split_at(proposal_scripts.len());
+            remaining_compiled_scripts = rest;
+            let (compile_times, rest) = remaining_compile_times.split_at(proposal_scripts.len());
+            remaining_compile_times = rest;
+            (compiled_scripts, compile_times)
+        })
+        .collect::<Vec<_>>();
+
+    let outcomes =
+        simulate_proposals_concurrently(&remote_url, &proposals, &compiled_scripts, options).await;
+
+    // Handle the outcomes in the order of the proposals, regardless of when they completed.
+    let mut release_gas_used = 0;
+    let mut first_error = None;
+    for ((proposal_dir, _), outcome) in proposals.iter().zip(outcomes) {
+        let Some((res, duration)) = outcome else {
+            report.proposals.push(ProposalReport::skipped(proposal_dir));
+            continue;
+        };
+        match res {
+            Ok(simulation_report) => {
+                if options.output_format == OutputFormat::Json {
+                    simulation_report
+                        .write_json(&proposal_dir.join(SIMULATION_REPORT_FILE_NAME))?;
+                }
+                release_gas_used += simulation_report.total_gas_used();
+                report.proposals.push(ProposalReport {
+                    proposal_dir: proposal_dir.clone(),
+                    duration,
+                    script_results: simulation_report.scripts,
+                    failure: None,
+                    skipped: false,
+                    context: Some(simulation_report.context),
+                });
+            },
+            Err(err) => {
+                error!("Proposal at {} failed: {:#}", proposal_dir.display(), err);
+                report.proposals.push(ProposalReport {
+                    proposal_dir: proposal_dir.clone(),
+                    duration,
+                    script_results: vec![],
+                    failure: Some(format!("{:#}", err)),
+                    skipped: false,
+                    context: err.downcast_ref::<SimulationContext>().cloned(),
+                });
+                if !options.keep_going && first_error.is_none() {
+                    first_error = Some(err);
+                }
+            },
+        }
+    }
+
+    // Check the budget before writing the reports, so that they include its outcome.
+    let gas_budget_check = check_release_gas_budget(release_gas_used, options);
+    report.gas_budget_failure = gas_budget_check
+        .as_ref()
+        .err()
+        .map(|err| format!("{:#}", err));
+    report.write_outputs(output_dir, options)?;
+    if let Some(err) = first_error {
+        return Err(err);
+    }
+
+    if let Some(cache) = &options.remote_state_cache {
+        let (num_cache_hits, num_cache_misses) = cache.stats();
+        info!(
+            "Remote state cache: {} hits, {} fetches",
+            num_cache_hits, num_cache_misses
+        );
+    }
+    info!(
+        "Total gas used by all proposals: {}",
+        format_number(release_gas_used, options.group_digits)
+    );
+
+    let num_failures = report.num_failures();
+    if num_failures > 0 {
+        info!("Proposal results:");
+        for proposal in &report.proposals {
+            match &proposal.failure {
+                None if proposal.skipped => {
+                    info!("    SKIPPED {}", proposal.proposal_dir.display())
+                },
+                None => info!("    PASSED {}", proposal.proposal_dir.display()),
+                Some(failure) => info!(
+                    "    FAILED {}: {}",
+                    proposal.proposal_dir.display(),
+                    failure
+                ),
+            }
+        }
+        // With `keep_going`, the release budget is still enforced, so report its outcome too.
+        if let Some(gas_budget_failure) = &report.gas_budget_failure {
+            info!("    FAILED gas budget: {}", gas_budget_failure);
+            bail!(
+                "{} of {} proposals failed, and {}",
+                num_failures,
+                report.proposals.len(),
+                gas_budget_failure
+            );
+        }
+        bail!(
+            "{} of {} proposals failed",
+            num_failures,
+            report.proposals.len()
+        );
+    }
+
+    gas_budget_check?;
+
+    info!("All proposals succeeded!");
+
+    Ok(report)
+}
+
+/// Simulates the compiled proposals with up to `options.proposal_concurrency` proposals in
+/// parallel, and returns the outcome of each along with the time it took, in the order of the
+/// proposals.
+///
+/// Unless `options.keep_going` is set, no new proposals are picked up once one of them failed,
+/// in which case the outcomes of the proposals that were not simulated are `None`.
+async fn simulate_proposals_concurrently(
+    remote_url: &Url,
+    proposals: &[(PathBuf, Vec<PathBuf>)],
+    compiled_scripts: &[(&[(Vec<u8>, HashValue)], &[Duration])],
+    options: &SimulationOptions,
+) -> Vec<Option<(Result<SimulationReport>, Duration)>> {
+    let num_proposals = proposals.len();
+    let num_workers = options.proposal_concurrency.clamp(1, num_proposals.max(1));
+    let mut outcomes = (0..num_proposals).map(|_| None).collect::<Vec<_>>();
+
+    if num_workers == 1 {
+        for (idx, (proposal_dir, proposal_scripts)) in proposals.iter().enumerate() {
+            info!("Simulating proposal at {}", proposal_dir.display());
+            let (proposal_compiled_scripts, compile_times) = compiled_scripts[idx];
+            let start_time = Instant::now();
+            let res = simulate_compiled_proposal(
+                remote_url.clone(),
+                proposal_dir,
+                proposal_scripts,
+                proposal_compiled_scripts,
+                compile_times,
+                options,
+                None,
+            )
+            .await
+            .map(|simulation| simulation.report)
+            .with_context(|| format!("failed to simulate proposal at {}", proposal_dir.display()));
+            let failed = res.is_err();
+            outcomes[idx] = Some((res, start_time.elapsed()));
+            if failed && !options.keep_going {
+                break;
+            }
+        }
+        return outcomes;
+    }
+
+    // The simulation itself is blocking, so run each proposal on a blocking thread, which works
+    // regardless of the flavor of the runtime.
+    let runtime = tokio::runtime::Handle::current();
+    let shared_options = Arc::new(options.clone());
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut next_proposal_idx = 0;
+    let mut failed = false;
+    loop {
+        while tasks.len() < num_workers
+            && next_proposal_idx < num_proposals
+            && (options.keep_going || !failed)
+        {
+            let idx = next_proposal_idx;
+            next_proposal_idx += 1;
+
+            let (proposal_dir, proposal_scripts) = proposals[idx].clone();
+            let (proposal_compiled_scripts, compile_times) = compiled_scripts[idx];
+            let proposal_compiled_scripts = proposal_compiled_scripts.to_vec();
+            let compile_times = compile_times.to_vec();
+            let remote_url = remote_url.clone();
+            let options = shared_options.clone();
+            let runtime = runtime.clone();
+            tasks.spawn_blocking(move || {
+                info!("Simulating proposal at {}", proposal_dir.display());
+                let start_time = Instant::now();
+                let res = runtime
+                    .block_on(simulate_compiled_proposal(
+                        remote_url,
+                        &proposal_dir,
+                        &proposal_scripts,
+                        &proposal_compiled_scripts,
+                        &compile_times,
+                        &options,
+                        None,
+                    ))
+                    .map(|simulation| simulation.report)
+                    .with_context(|| {
+                        format!("failed to simulate proposal at {}", proposal_dir.display())
+                    });
+                (idx, res, start_time.elapsed())
+            });
+        }
+
+        let Some(joined) = tasks.join_next().await else {
+            break;
+        };
+        let (idx, res, duration) =
+            joined.unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()));
+        failed |= res.is_err();
+        outcomes[idx] = Some((res, duration));
+    }
+
+    outcomes
+}
+
+/// Applies a sequence of already-materialized write sets, e.g. ones captured from a historical
+/// proposal, on top of the remote state at the given version (the latest one if `None`), skipping
+/// compilation and execution entirely.
+///
+/// Returns the cumulative state changes, where `None` denotes a deleted state item.
+pub async fn replay_write_sets(
+    remote_url: Url,
+    write_sets: Vec<WriteSet>,
+    version: Option<u64>,
+) -> Result<BTreeMap<StateKey, Option<StateValue>>> {
+    let client = Client::new(remote_url);
+    let debugger =
+        AptosDebugger::rest_client(client.clone()).context("failed to create AptosDebugger")?;
+    let version = match version {
+        Some(version) => version,
+        None => {
+            RetryPolicy::default()
+                .retry_async("fetch ledger information", || {
+                    client.get_ledger_information()
+                })
+                .await?
+                .into_inner()
+                .version
+        },
+    };
+    info!("Using remote state at version {}", version);
+
+    let state_view = SimulationStateView::new(&debugger.state_view_at_version(version));
+
+    info!(
+        "Replaying {} write set{}...",
+        write_sets.len(),
+        if write_sets.len() == 1 { "" } else { "s" }
+    );
+
+    let mut changes = BTreeMap::new();
+    for write_set in write_sets {
+        for (state_key, write_op) in write_set.iter() {
+            changes.insert(state_key.clone(), write_op.as_state_value());
+        }
+        state_view.apply_write_set(write_set);
+    }
+
+    let (mut num_created, mut num_modified, mut num_deleted) = (0, 0, 0);
+    for (state_key, state_value) in &changes {
+        let existed = state_view.remote.get_state_value(state_key)?.is_some();
+        match (existed, state_value) {
+            (false, Some(_)) => num_created += 1,
+            (true, Some(_)) => num_modified += 1,
+            (_, None) => num_deleted += 1,
+        }
+    }
+    info!(
+        "    {} state items changed: {} created, {} modified, {} deleted",
+        changes.len(),
+        num_created,
+        num_modified,
+        num_deleted
+    );
+
+    Ok(changes)
+}
+
+/// Interval at which the watched directory is polled for changes in watch mode.
+///
+/// Polling, rather than subscribing to file system notifications, also works on network and
+/// container mounts that do not deliver them. Release directories hold few enough scripts that
+/// walking them twice a second is cheap.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time the scripts must stay unchanged before a change is acted upon, so that an editor saving
+/// several files at once, or writing a file in several steps, triggers a single run.
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// The scripts of each proposal in the order they are simulated, along with their last
+/// modification times. A missing modification time denotes a script that vanished while the
+/// snapshot was taken.
+type WatchSnapshot = BTreeMap<PathBuf, Vec<(PathBuf, Option<SystemTime>)>>;
+
+/// Collects the proposals under the given directory, along with the last modification times of
+/// their scripts.
+///
+/// Editors often save a file by writing a temporary file and renaming it over the original, so a
+/// script may be deleted between listing and reading its metadata. It is then recorded without
+/// a modification time, which counts as a change, and is picked up again by the next snapshot.
+fn snapshot_proposals(root_dir: &Path) -> Result<WatchSnapshot> {
+    collect_proposals(root_dir)?
+        .into_iter()
+        .map(|(proposal_dir, proposal_scripts)| {
+            let scripts = proposal_scripts
+                .into_iter()
+                .map(|path| {
+                    let modified = match std::fs::metadata(&path)
+                        .and_then(|metadata| metadata.modified())
+                    {
+                        Ok(modified) => Some(modified),
+                        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+                        Err(err) => {
+                            return Err(anyhow::Error::new(err)
+                                .context(format!("failed to read metadata of {}", path.display())))
+                        },
+                    };
+                    Ok((path, modified))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok((proposal_dir, scripts))
+        })
+        .collect()
+}
+
+/// Watches the proposals under the given directory and re-simulates a proposal every time one of
+/// its `.move` files is added, removed or modified. Only the proposals whose scripts changed are
+/// simulated again, once the changes have settled for [`WATCH_DEBOUNCE`].
+///
+/// Every run starts from the remote state again, since a script can only be simulated on top of
+/// the side effects of the scripts before it. Simulation failures are printed and do not stop
+/// the watcher. This function only returns if the directory can no longer be read.
+pub async fn simulate_watch(
+    remote_url: Url,
+    root_dir: &Path,
+    options: &SimulationOptions,
+) -> Result<()> {
+    let mut last_snapshot: Option<WatchSnapshot> = None;
+    // The latest snapshot that differs from the last simulated one, and since when it is stable.
+    let mut pending: Option<(WatchSnapshot, Instant)> = None;
+
+    loop {
+        let snapshot = snapshot_proposals(root_dir)?;
+
+        if last_snapshot.as_ref() == Some(&snapshot) {
+            pending = None;
+        } else {
+            let stable_since = match pending.take() {
+                Some((pending_snapshot, since)) if pending_snapshot == snapshot => since,
+                _ => Instant::now(),
+            };
+            // The first run does not need to wait for anything to settle, but scripts that are
+            // being replaced must reappear first.
+            let has_vanished_scripts = snapshot
+                .values()
+                .flatten()
+                .any(|(_, modified)| modified.is_none());
+            if has_vanished_scripts
+                || (last_snapshot.is_some() && stable_since.elapsed() < WATCH_DEBOUNCE)
+            {
+                pending = Some((snapshot, stable_since));
+            } else {
+                let previous_snapshot = last_snapshot.take().unwrap_or_default();
+                for proposal_dir in previous_snapshot.keys() {
+                    if !snapshot.contains_key(proposal_dir) {
+                        info!("Proposal at {} was removed", proposal_dir.display());
+                    }
+                }
+                if snapshot.is_empty() {
+                    info!("No proposals found in {}", root_dir.display());
+                }
+
+                for (proposal_dir, scripts) in &snapshot {
+                    if previous_snapshot.get(proposal_dir) == Some(scripts) {
+                        continue;
+                    }
+                    let proposal_scripts = scripts
+                        .iter()
+                        .map(|(path, _)| path.clone())
+                        .collect::<Vec<_>>();
+                    if let Err(err) = simulate_multistep_proposal(
+                        remote_url.clone(),
+                        proposal_dir,
+                        &proposal_scripts,
+                        options,
+                    )
+                    .await
+                    {
+                        error!("Simulation of {} failed: {:#}", proposal_dir.display(), err);
+                    }
+                }
+
+                last_snapshot = Some(snapshot);
+                info!(
+                    "Watching {} for changes (press Ctrl-C to stop)...",
+                    root_dir.display()
+                );
+            }
+        }
+
+        tokio::time::sleep(WATCH_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use aptos_language_e2e_tests::{data_store::FakeDataStore, executor::FakeExecutor};
+    use aptos_types::{
+        validator_config::ValidatorConfig,
+        validator_info::ValidatorInfo,
+        write_set::{WriteOp, WriteSetMut},
+    };
+    use move_binary_format::{
+        file_format::{empty_module, empty_script, CodeUnit},
+        file_format_common::VERSION_6,
+    };
+    use move_vm_runtime::WithRuntimeEnvironment;
+
+    /// Creates a module with a stub resolve function that has the expected parameters, but the
+    /// given return types.
+    fn make_resolve_function_stub(
+        returns: Vec<SignatureToken>,
+    ) -> (CompiledModule, FunctionHandleIndex) {
+        let mut module = empty_module();
+        let mut m = IndexedModule::new(&mut module);
+
+        let create_signer_handle_idx = add_simple_native_function(
+            &mut m,
+            FUNC_NAME_CREATE_SIGNER.clone(),
+            vec![SignatureToken::Address],
+            vec![SignatureToken::Signer],
+        )
+        .unwrap();
+        let resolve_handle_idx = add_simple_native_function(
+            &mut m,
+            FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL.clone(),
+            vec![SignatureToken::U64, SignatureToken::Address],
+            returns,
+        )
+        .unwrap();
+
+        let locals = get_or_add_signature(&mut m, vec![]);
+        let func_def = m
+            .function_defs
+            .iter_mut()
+            .find(|func_def| func_def.function == resolve_handle_idx)
+            .unwrap();
+        func_def.code = Some(CodeUnit {
+            locals,
+            code: vec![Bytecode::Abort],
+        });
+
+        (module, create_signer_handle_idx)
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(diff_lines("a\nb\nc", "a\nx\nc\nd"), vec![
+            " a", "-b", "+x", " c", "+d"
+        ]);
+        assert_eq!(diff_lines("", "a"), vec!["+a"]);
+        assert_eq!(diff_lines("a", ""), vec!["-a"]);
+    }
+
+    #[test]
+    fn test_redacted_options() {
+        let options = SimulationOptions {
+            remote_api_key: Some(Redacted("secret-key".to_string())),
+            remote_headers: vec![parse_header("x-api-key: secret-header").unwrap()],
+            ..SimulationOptions::default()
+        };
+        let debug = format!("{:?}", options);
+        assert!(!debug.contains("secret-key"));
+        assert!(!debug.contains("secret-header"));
+        assert!(debug.contains("x-api-key"));
+    }
+
+    #[test]
+    fn test_parse_framework_dir() {
+        let framework_dir = parse_framework_dir(".").unwrap();
+        assert!(framework_dir.is_absolute());
+        assert_eq!(
+            framework_dir,
+            std::env::current_dir().unwrap().canonicalize().unwrap()
+        );
+        assert!(parse_framework_dir("does-not-exist").is_err());
+    }
+
+    /// Returns an error of the REST API with the given status code.
+    fn make_api_error(status_code: reqwest::StatusCode) -> RestError {
+        RestError::Api(AptosErrorResponse {
+            error: AptosError::new_with_error_code("failed", AptosErrorCode::InternalError),
+            state: None,
+            status_code,
+        })
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        };
+        let transient = || make_api_error(reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        // Transient errors are retried up to the maximum number of retries.
+        let mut num_attempts = 0;
+        let res = policy.retry("fetch", || {
+            num_attempts += 1;
+            if num_attempts < 3 {
+                Err(transient())
+            } else {
+                Ok(num_attempts)
+            }
+        });
+        assert_eq!(res.unwrap(), 3);
+
+        let mut num_attempts = 0;
+        let res = policy.retry("fetch", || -> Result<(), _> {
+            num_attempts += 1;
+            Err(transient())
+        });
+        assert!(res.is_err());
+        assert_eq!(num_attempts, 3);
+
+        // Other errors are returned right away.
+        let mut num_attempts = 0;
+        let res = policy.retry("fetch", || -> Result<(), _> {
+            num_attempts += 1;
+            Err(make_api_error(reqwest::StatusCode::NOT_FOUND))
+        });
+        assert!(res.is_err());
+        assert_eq!(num_attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_async() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        };
+
+        let mut num_attempts = 0;
+        let res = policy
+            .retry_async("fetch", || {
+                num_attempts += 1;
+                let res = if num_attempts < 2 {
+                    Err(make_api_error(reqwest::StatusCode::BAD_GATEWAY))
+                } else {
+                    Ok(())
+                };
+                std::future::ready(res)
+            })
+            .await;
+        assert!(res.is_ok());
+        assert_eq!(num_attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_is_transient_rest_error() {
+        assert!(make_api_error(reqwest::StatusCode::BAD_GATEWAY).is_transient());
+        assert!(!make_api_error(reqwest::StatusCode::NOT_FOUND).is_transient());
+        assert!(!RestError::Timeout("transaction").is_transient());
+
+        // Nothing listens on the port, so the connection is refused.
+        let err = Client::new(Url::parse("http://127.0.0.1:1").unwrap())
+            .get_ledger_information()
+            .await
+            .unwrap_err();
+        assert!(err.is_transient());
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec![
+            "10_bar.move",
+            "2_foo.move",
+            "step_10.move",
+            "step_2.move",
+            "02_foo.move",
+            "1.move",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, vec![
+            "1.move",
+            "02_foo.move",
+            "2_foo.move",
+            "10_bar.move",
+            "step_2.move",
+            "step_10.move",
+        ]);
+    }
+
+    #[test]
+    fn test_order_scripts() {
+        let move_files = vec![
+            PathBuf::from("p/step_1.move"),
+            PathBuf::from("p/step_10.move"),
+            PathBuf::from("p/step_2.move"),
+        ];
+        let order = |names: &[&str]| {
+            order_scripts(
+                move_files.clone(),
+                &names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(
+            order(&["step_1.move", "step_2.move", "step_10.move"]).unwrap(),
+            vec![
+                PathBuf::from("p/step_1.move"),
+                PathBuf::from("p/step_2.move"),
+                PathBuf::from("p/step_10.move"),
+            ]
+        );
+        assert!(order(&["step_1.move", "step_2.move"]).is_err());
+        assert!(order(&["step_1.move", "step_1.move", "step_2.move", "step_10.move"]).is_err());
+        assert!(order(&["step_1.move", "step_2.move", "step_3.move", "step_10.move"]).is_err());
+    }
+
+    #[test]
+    fn test_find_next_execution_hash() {
+        let patch_plan = PatchPlan::default();
+        let mut script = make_governance_call_script(&patch_plan, &patch_plan.resolve_function);
+        script.constant_pool.push(Constant {
+            type_: SignatureToken::Vector(Box::new(SignatureToken::U8)),
+            data: bcs::to_bytes(&vec![1u8, 2, 3]).unwrap(),
+        });
+
+        script.code.code = vec![
+            Bytecode::LdConst(ConstantPoolIndex(0)),
+            Bytecode::Call(FunctionHandleIndex(0)),
+            Bytecode::Ret,
+        ];
+        assert_eq!(
+            find_next_execution_hash(&script, &patch_plan),
+            Some(vec![1, 2, 3])
+        );
+
+        script.code.code[0] = Bytecode::VecPack(SignatureIndex(0), 0);
+        assert_eq!(find_next_execution_hash(&script, &patch_plan), Some(vec![]));
+
+        script.code.code[0] = Bytecode::CopyLoc(0);
+        assert_eq!(find_next_execution_hash(&script, &patch_plan), None);
+    }
+
+    #[test]
+    fn test_pool_index() {
+        let mut pool = vec![1, 2, 2];
+        let mut index = PoolIndex::default();
+        assert_eq!(index.get_or_add(&mut pool, 2), 1);
+        assert_eq!(index.get_or_add(&mut pool, 3), 3);
+
+        // Entries appended without going through the index are picked up as well.
+        pool.push(4);
+        assert_eq!(index.get_or_add(&mut pool, 4), 4);
+        assert_eq!(index.get_or_add(&mut pool, 5), 5);
+        assert_eq!(pool, vec![1, 2, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_patch_resolve_function() {
+        let (mut m, create_signer_handle_idx) =
+            make_resolve_function_stub(vec![SignatureToken::Signer]);
+
+        patch_resolve_function(
+            &mut IndexedModule::new(&mut m),
+            &FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL,
+            create_signer_handle_idx,
+            false,
+            false,
+        )
+        .unwrap();
+
+        let func_def =
+            find_function_def_by_name(&mut m, &FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL).unwrap();
+        assert_eq!(func_def.code.as_ref().unwrap().code, vec![
+            Bytecode::MoveLoc(1),
+            Bytecode::Call(create_signer_handle_idx),
+            Bytecode::Ret
+        ]);
+    }
+
+    #[test]
+    fn test_patch_function_to_return() {
+        let (mut m, _) = make_resolve_function_stub(vec![SignatureToken::U64]);
+        let mut m = IndexedModule::new(&mut m);
+
+        let err = patch_function_to_return(
+            &mut m,
+            &FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL,
+            MoveValue::Bool(true),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("cannot return"));
+
+        patch_function_to_return(
+            &mut m,
+            &FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL,
+            MoveValue::U64(42),
+        )
+        .unwrap();
+        let func_def =
+            find_function_def_by_name(&mut m, &FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL).unwrap();
+        let const_idx = match func_def.code.as_ref().unwrap().code.as_slice() {
+            [Bytecode::LdConst(const_idx), Bytecode::Ret] => *const_idx,
+            code => panic!("unexpected code {:?}", code),
+        };
+        assert_eq!(
+            m.constant_at(const_idx).data,
+            bcs::to_bytes(&42u64).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_is_next_execution_hash_abort() {
+        let patch_plan = PatchPlan::default();
+        let abort = |location, code| ExecutionStatus::MoveAbort {
+            location,
+            code,
+            info: None,
+        };
+
+        assert!(is_next_execution_hash_abort(
+            &abort(
+                AbortLocation::Module(patch_plan.governance_module.clone()),
+                MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK
+            ),
+            &patch_plan
+        ));
+        // The same code raised by the script itself must not be mistaken for the check.
+        assert!(!is_next_execution_hash_abort(
+            &abort(
+                AbortLocation::Script,
+                MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK
+            ),
+            &patch_plan
+        ));
+    }
+
+    #[test]
+    fn test_describe_gas_failure() {
+        let describe = |status| describe_gas_failure(&status, 100_000, true);
+
+        assert!(describe(TransactionStatus::Keep(ExecutionStatus::OutOfGas))
+            .unwrap()
+            .contains("100,000"));
+        assert!(describe(TransactionStatus::Keep(
+            ExecutionStatus::MiscellaneousError(Some(StatusCode::IO_LIMIT_REACHED))
+        ))
+        .is_some());
+        assert!(describe(TransactionStatus::Discard(
+            StatusCode::MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND
+        ))
+        .is_some());
+        // Logic failures are left to the generic handling.
+        assert!(
+            describe(TransactionStatus::Keep(ExecutionStatus::MoveAbort {
+                location: AbortLocation::Script,
+                code: 1,
+                info: None,
+            }))
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_patch_resolve_function_with_mismatched_return() {
+        let (mut m, create_signer_handle_idx) =
+            make_resolve_function_stub(vec![SignatureToken::Signer, SignatureToken::U64]);
+
+        let err = patch_resolve_function(
+            &mut IndexedModule::new(&mut m),
+            &FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL,
+            create_signer_handle_idx,
+            false,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unexpected return types"));
+    }
+
+    #[test]
+    fn test_to_junit_xml() {
+        let report = ReleaseSimulationReport {
+            proposals: vec![
+                ProposalReport {
+                    proposal_dir: PathBuf::from("passed"),
+                    duration: Duration::from_millis(1500),
+                    script_results: vec![],
+                    failure: None,
+                    skipped: false,
+                    context: None,
+                },
+                ProposalReport {
+                    proposal_dir: PathBuf::from("failed"),
+                    duration: Duration::ZERO,
+                    script_results: vec![],
+                    failure: Some("\x1b[31merror\x1b[0m: a < b & \"c\"\nsecond line".to_string()),
+                    skipped: false,
+                    context: None,
+                },
+                ProposalReport::skipped(Path::new("skipped")),
+            ],
+            ..ReleaseSimulationReport::default()
+        };
+
+        let xml = report.to_junit_xml();
+        assert!(xml.contains(
+            "<testsuite name=\"release-simulation\" tests=\"3\" failures=\"1\" skipped=\"1\" \
+             time=\"1.500\">"
+        ));
+        assert!(xml.contains(
+            "<testcase classname=\"release-simulation\" name=\"passed\" time=\"1.500\"/>"
+        ));
+        assert!(xml.contains(
+            "<failure message=\"\u{fffd}[31merror\u{fffd}[0m: a &lt; b &amp; &quot;c&quot;\">\
+             \u{fffd}[31merror\u{fffd}[0m: a &lt; b &amp; &quot;c&quot;\nsecond line</failure>"
+        ));
+        assert!(xml.contains("<skipped message=\"another proposal failed\"/>"));
+        // Control characters other than whitespace are not allowed anywhere in XML 1.0.
+        assert!(!xml
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r')));
+    }
+
+    #[test]
+    fn test_summarize_staking_changes() {
+        let make_change =
+            |module: &str, name: &str, before: Vec<u8>, after: Vec<u8>| ResourceChange {
+                address: AccountAddress::ONE,
+                struct_tag: StructTag {
+                    address: AccountAddress::ONE,
+                    module: Identifier::new(module).unwrap(),
+                    name: Identifier::new(name).unwrap(),
+                    type_args: vec![],
+                },
+                before: Some(before),
+                after: Some(after),
+            };
+
+        let staking_config = StakingConfig {
+            minimum_stake: 1,
+            maximum_stake: 100,
+            recurring_lockup_duration_secs: 3600,
+            allow_validator_set_change: true,
+            rewards_rate: 1,
+            rewards_rate_denominator: 100,
+            voting_power_increase_limit: 20,
+        };
+        // A zero denominator must not render as NaN.
+        let misconfigured_staking_config = StakingConfig {
+            rewards_rate_denominator: 0,
+            ..staking_config
+        };
+
+        let mut keygen = aptos_keygen::KeyGen::from_seed([0; 32]);
+        let mut make_validator = |address: AccountAddress, voting_power: u64| {
+            let private_key = keygen.generate_bls12381_private_key();
+            ValidatorInfo::new(
+                address,
+                voting_power,
+                ValidatorConfig::new((&private_key).into(), vec![], vec![], 0),
+            )
+        };
+        let validator_a = AccountAddress::from_hex_literal("0xa").unwrap();
+        let validator_b = AccountAddress::from_hex_literal("0xb").unwrap();
+        let validator_set_before = ValidatorSet::new(vec![make_validator(validator_a, 10)]);
+        let validator_set_after = ValidatorSet::new(vec![
+            make_validator(validator_a, 20),
+            make_validator(validator_b, 5),
+        ]);
+
+        let changes = [
+            make_change(
+                "staking_config",
+                "StakingConfig",
+                bcs::to_bytes(&staking_config).unwrap(),
+                bcs::to_bytes(&misconfigured_staking_config).unwrap(),
+            ),
+            make_change(
+                "stake",
+                "ValidatorSet",
+                bcs::to_bytes(&validator_set_before).unwrap(),
+                bcs::to_bytes(&validator_set_after).unwrap(),
+            ),
+        ];
+        assert_eq!(summarize_staking_changes(&changes).unwrap(), vec![
+            "Staking config: reward rate per epoch 1.0000% → n/a".to_string(),
+            format!(
+                "Validator set: active validator {} voting power 10 → 20",
+                validator_a
+            ),
+            format!("Validator set: active validator {} added", validator_b),
+        ]);
+    }
+
+    #[test]
+    fn test_add_script_execution_hash() {
+        let executor = FakeExecutor::from_head_genesis();
+        let remote = SimulationStateView::new(executor.data_store());
+        let state_view = SimulationStateView::new(&remote);
+        let num_remote_entries = ApprovedExecutionHashes::fetch_config(&remote)
+            .unwrap()
+            .entries
+            .len();
+
+        // The hash of a later step replaces the one of the earlier step.
+        add_script_execution_hash(&state_view, 42, HashValue::sha3_256_of(b"step 1")).unwrap();
+        add_script_execution_hash(&state_view, 42, HashValue::sha3_256_of(b"step 2")).unwrap();
+        let approved_hashes = ApprovedExecutionHashes::fetch_config(&state_view).unwrap();
+        assert_eq!(approved_hashes.entries.len(), num_remote_entries + 1);
+        assert!(approved_hashes
+            .entries
+            .contains(&(42, HashValue::sha3_256_of(b"step 2").to_vec())));
 
-    state_view.modify_on_chain_config(|approved_hashes: &mut ApprovedExecutionHashes| {
-        if !approved_hashes.entries.contains(&entry) {
-            approved_hashes.entries.push(entry);
+        // An id approved in the remote state belongs to a real proposal.
+        add_script_execution_hash(&remote, 43, HashValue::sha3_256_of(b"real")).unwrap();
+        let state_view = SimulationStateView::new(&remote);
+        assert!(
+            add_script_execution_hash(&state_view, 43, HashValue::sha3_256_of(b"step 1")).is_err()
+        );
+    }
+
+    #[test]
+    fn test_verify_approved_execution_hash() {
+        let executor = FakeExecutor::from_head_genesis();
+        let state_view = SimulationStateView::new(executor.data_store());
+        let script_hash = HashValue::sha3_256_of(b"script");
+        verify_approved_execution_hash(&state_view, script_hash).unwrap();
+
+        // Undecodable hashes must not pass for no hashes being approved.
+        state_view.set_state_value(
+            StateKey::on_chain_config::<ApprovedExecutionHashes>().unwrap(),
+            StateValue::new_legacy(vec![0xFF].into()),
+        );
+        assert!(verify_approved_execution_hash(&state_view, script_hash).is_err());
+        assert!(
+            add_script_execution_hash(&SimulationStateView::new(&state_view), 42, script_hash)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_overlay_round_trip() {
+        let executor = FakeExecutor::from_head_genesis();
+        let state_view = SimulationStateView::new(executor.data_store());
+        state_view.set_state_value(
+            StateKey::raw(b"changed"),
+            StateValue::new_legacy(vec![1, 2, 3].into()),
+        );
+        state_view.apply_write_set(
+            WriteSetMut::new(vec![(
+                StateKey::on_chain_config::<ChainIdResource>().unwrap(),
+                WriteOp::legacy_deletion(),
+            )])
+            .freeze()
+            .unwrap(),
+        );
+
+        for format in [OverlayFormat::Bcs, OverlayFormat::Json] {
+            let overlay_path = aptos_temppath::TempPath::new();
+            let overlay_path = overlay_path.path().with_extension(format.extension());
+            assert_eq!(state_view.export_overlay(&overlay_path, format).unwrap(), 2);
+
+            let imported = SimulationStateView::new(executor.data_store());
+            assert_eq!(imported.import_overlay(&overlay_path, None).unwrap(), 2);
+            assert_eq!(imported.overlay(), state_view.overlay());
+            std::fs::remove_file(&overlay_path).unwrap();
         }
-        Ok(())
-    })
-}
+    }
 
-/***************************************************************************************************
- * Simulation Workflow
- *
- **************************************************************************************************/
-fn force_end_epoch(state_view: &SimulationStateView<impl StateView>) -> Result<()> {
-    let env = AptosEnvironment::new_with_injected_create_signer_for_gov_sim(&state_view);
-    let vm = AptosVM::new(&env, &state_view);
-    let resolver = state_view.as_move_resolver();
-    let module_storage = state_view.as_aptos_code_storage(&env);
+    #[test]
+    fn test_import_overlay_before_overrides() {
+        let executor = FakeExecutor::from_head_genesis();
+        let state_view = SimulationStateView::new(executor.data_store());
+        let mut features = Features::fetch_config(&state_view).unwrap();
 
-    let gas_schedule =
-        GasScheduleV2::fetch_config(&state_view).context("failed to fetch gas schedule v2")?;
-    let gas_feature_version = gas_schedule.feature_version;
+        // An overlay that enables a feature the override disables again.
+        features.enable(FeatureFlag::EMIT_FEE_STATEMENT);
+        state_view.set_on_chain_config(&features).unwrap();
+        let overlay_path = aptos_temppath::TempPath::new();
+        let overlay_path = overlay_path.path().with_extension("bcs");
+        state_view
+            .export_overlay(&overlay_path, OverlayFormat::Bcs)
+            .unwrap();
 
-    let change_set_configs =
-        ChangeSetConfigs::unlimited_at_gas_feature_version(gas_feature_version);
+        features.disable(FeatureFlag::EMIT_FEE_STATEMENT);
+        let options = SimulationOptions {
+            import_overlays: vec![overlay_path.clone()],
+            features_override: Some(features),
+            ..SimulationOptions::default()
+        };
+        let session = new_session(&executor, &options).unwrap();
+        assert!(!Features::fetch_config(session.state_view())
+            .unwrap()
+            .is_enabled(FeatureFlag::EMIT_FEE_STATEMENT));
+        std::fs::remove_file(&overlay_path).unwrap();
+    }
 
-    let traversal_storage = TraversalStorage::new();
-    let mut sess = vm.new_session(&resolver, SessionId::void(), None);
-    sess.execute_function_bypass_visibility(
-        &MODULE_ID_APTOS_GOVERNANCE,
-        IdentStr::new("force_end_epoch").unwrap(),
-        vec![],
-        vec![MoveValue::Signer(AccountAddress::ONE)
-            .simple_serialize()
-            .unwrap()],
-        &mut UnmeteredGasMeter,
-        &mut TraversalContext::new(&traversal_storage),
-        &module_storage,
-    )?;
-    let mut change_set = sess.finish(&change_set_configs, &module_storage)?;
+    #[test]
+    fn test_apply_write_set_deletion() {
+        let executor = FakeExecutor::from_head_genesis();
+        let state_view = SimulationStateView::new(executor.data_store());
+        let state_key = StateKey::on_chain_config::<ChainIdResource>().unwrap();
+        assert!(state_view.get_state_value(&state_key).unwrap().is_some());
 
-    change_set.try_materialize_aggregator_v1_delta_set(&resolver)?;
-    let (write_set, _events) = change_set
-        .try_combine_into_storage_change_set(ModuleWriteSet::empty())
-        .expect("Failed to convert to storage ChangeSet")
-        .into_inner();
+        let write_set = WriteSetMut::new(vec![(state_key.clone(), WriteOp::legacy_deletion())])
+            .freeze()
+            .unwrap();
+        state_view.apply_write_set(write_set);
 
-    state_view.apply_write_set(write_set);
+        // The deletion shadows the value in the remote state.
+        assert!(state_view.get_state_value(&state_key).unwrap().is_none());
+        assert_eq!(state_view.overlay(), vec![(state_key, None)]);
+    }
 
-    Ok(())
-}
+    #[test]
+    fn test_resource_diff_deletion() {
+        let executor = FakeExecutor::from_head_genesis();
+        let state_view = SimulationStateView::new(executor.data_store());
+        let state_key = StateKey::on_chain_config::<ChainIdResource>().unwrap();
 
-pub async fn simulate_multistep_proposal(
-    remote_url: Url,
-    proposal_dir: &Path,
-    proposal_scripts: &[PathBuf],
-    profile_gas: bool,
-) -> Result<()> {
-    println!("Simulating proposal at {}", proposal_dir.display());
+        // Changes made before the baseline was taken are not part of the diff.
+        state_view
+            .modify_on_chain_config(|features: &mut Features| {
+                features.disable(FeatureFlag::EMIT_FEE_STATEMENT);
+                Ok(())
+            })
+            .unwrap();
+        let baseline = state_view.states.lock().clone();
 
-    // Compile all scripts.
-    println!("Compiling scripts...");
-    let mut compiled_scripts = vec![];
-    for path in proposal_scripts {
-        let framework_package_args = FrameworkPackageArgs::try_parse_from([
-            "dummy_executable_name",
-            "--framework-local-dir",
-            &aptos_framework_path().to_string_lossy(),
-            "--skip-fetch-latest-git-deps",
-        ])
-        .context(
-            "failed to parse framework package args for compiling scripts, this should not happen",
-        )?;
+        let write_set = WriteSetMut::new(vec![(state_key, WriteOp::legacy_deletion())])
+            .freeze()
+            .unwrap();
+        state_view.apply_write_set(write_set);
+
+        let changes = collect_resource_changes(&state_view, &baseline).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].address, AccountAddress::ONE);
+        assert!(changes[0].before.is_some());
+        assert!(changes[0].after.is_none());
+
+        let diff = render_resource_diff(&state_view, &changes);
+        assert!(diff.starts_with("0x1\n    - 0x1::chain_id::ChainId\n"));
+    }
+
+    #[test]
+    fn test_patch_missing_resolve_function() {
+        let (mut m, create_signer_handle_idx) = make_resolve_function_stub(vec![]);
 
-        let (blob, hash) = compile_in_temp_dir(
-            "script",
-            path,
-            &framework_package_args,
-            PromptOptions::yes(),
-            None, // bytecode_version
-            None, // language_version
-            None, // compiler_version
+        let err = patch_resolve_function(
+            &mut IndexedModule::new(&mut m),
+            ident_str!("resolve_multi_step_proposal_v2"),
+            create_signer_handle_idx,
+            false,
+            false,
         )
-        .with_context(|| format!("failed to compile script {}", path.display()))?;
+        .unwrap_err();
+        // The function of the stub is suggested.
+        assert!(err.to_string().contains(&format!(
+            "defines: {}",
+            &*FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL
+        )));
+    }
+
+    #[test]
+    fn test_add_simple_native_function_not_native() {
+        let mut module = empty_module();
+        let mut m = IndexedModule::new(&mut module);
+
+        let func_handle_idx = add_simple_native_function(
+            &mut m,
+            FUNC_NAME_CREATE_SIGNER.clone(),
+            vec![SignatureToken::Address],
+            vec![SignatureToken::Signer],
+        )
+        .unwrap();
+        let locals = get_or_add_signature(&mut m, vec![]);
+        let func_def = m
+            .function_defs
+            .iter_mut()
+            .find(|func_def| func_def.function == func_handle_idx)
+            .unwrap();
+        func_def.code = Some(CodeUnit {
+            locals,
+            code: vec![Bytecode::Abort],
+        });
 
-        compiled_scripts.push((blob, hash));
+        // A function with the same signature, but a body, is rejected.
+        let err = add_simple_native_function(
+            &mut m,
+            FUNC_NAME_CREATE_SIGNER.clone(),
+            vec![SignatureToken::Address],
+            vec![SignatureToken::Signer],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("is not native"));
     }
 
-    // Set up the simulation state view.
-    let client = Client::new(remote_url);
-    let debugger =
-        AptosDebugger::rest_client(client.clone()).context("failed to create AptosDebugger")?;
-    let state = client.get_ledger_information().await?.into_inner();
+    #[test]
+    fn test_add_simple_native_function_signature_mismatch() {
+        let mut module = empty_module();
+        let mut m = IndexedModule::new(&mut module);
 
-    let state_view = SimulationStateView {
-        remote: &debugger.state_view_at_version(state.version),
-        states: Mutex::new(HashMap::new()),
-    };
+        add_simple_native_function(
+            &mut m,
+            FUNC_NAME_CREATE_SIGNER.clone(),
+            vec![SignatureToken::U64],
+            vec![SignatureToken::Signer],
+        )
+        .unwrap();
 
-    // Create and fund a sender account that is used to send the governance scripts.
-    print!("Creating and funding sender account.. ");
-    std::io::stdout().flush()?;
-    let mut rng = aptos_keygen::KeyGen::from_seed([0; 32]);
-    let balance = 100 * 1_0000_0000; // 100 APT
-    let account = AccountData::new_from_seed(&mut rng, balance, 0);
-    state_view.apply_write_set(account.to_writeset());
-    // TODO: should update coin info (total supply)
-    println!("done");
+        // The same signature reuses the existing function, another one is rejected.
+        add_simple_native_function(
+            &mut m,
+            FUNC_NAME_CREATE_SIGNER.clone(),
+            vec![SignatureToken::U64],
+            vec![SignatureToken::Signer],
+        )
+        .unwrap();
+        assert!(add_simple_native_function(
+            &mut m,
+            FUNC_NAME_CREATE_SIGNER.clone(),
+            vec![SignatureToken::Address],
+            vec![SignatureToken::Signer],
+        )
+        .is_err());
+    }
 
-    // Execute the governance scripts in sorted order.
-    println!("Executing governance scripts...");
+    #[test]
+    fn test_patch_multisig_account() {
+        let executor = FakeExecutor::from_head_genesis();
+        let (state_view, deserializer_config) = genesis_state_view(&executor);
+        patch_multisig_account(&state_view, &deserializer_config).unwrap();
 
-    for (script_idx, (script_path, (script_blob, script_hash))) in
-        proposal_scripts.iter().zip(compiled_scripts).enumerate()
-    {
-        // Force-end the epoch so that buffered configuration changes get applied.
-        force_end_epoch(&state_view).context("failed to force end epoch")?;
+        let blob = state_view
+            .get_state_value_bytes(&StateKey::module_id(&MODULE_ID_MULTISIG_ACCOUNT))
+            .unwrap()
+            .unwrap();
+        let mut m = CompiledModule::deserialize_with_config(&blob, &deserializer_config).unwrap();
+        for (func_name, code) in [
+            ("validate_multisig_transaction", vec![Bytecode::Ret]),
+            ("get_next_transaction_payload", vec![
+                Bytecode::MoveLoc(1),
+                Bytecode::Ret,
+            ]),
+            ("successful_transaction_execution_cleanup", vec![
+                Bytecode::Ret,
+            ]),
+            ("failed_transaction_execution_cleanup", vec![
+                Bytecode::LdU64(MAGIC_FAILED_MULTISIG_PAYLOAD),
+                Bytecode::Abort,
+            ]),
+        ] {
+            let func_def =
+                find_function_def_by_name(&mut m, &Identifier::new(func_name).unwrap()).unwrap();
+            assert_eq!(func_def.code.as_ref().unwrap().code, code, "{}", func_name);
+        }
+    }
 
-        // Fetch the on-chain configs that are needed for the simulation.
-        let chain_id =
-            ChainIdResource::fetch_config(&state_view).context("failed to fetch chain id")?;
+    fn make_multisig_payload(function: &str, args: Vec<TransactionArgument>) -> EntryFunction {
+        MultisigPayload {
+            multisig_address: AccountAddress::ONE,
+            function: function.to_string(),
+            type_args: vec![],
+            args,
+        }
+        .entry_function()
+        .unwrap()
+    }
 
-        let gas_schedule =
-            GasScheduleV2::fetch_config(&state_view).context("failed to fetch gas schedule v2")?;
-        let gas_feature_version = gas_schedule.feature_version;
-        let gas_params = AptosGasParameters::from_on_chain_gas_schedule(
-            &gas_schedule.into_btree_map(),
-            gas_feature_version,
+    #[test]
+    fn test_execute_multisig_payload() {
+        let executor = FakeExecutor::from_head_genesis();
+        let mut session = new_session(&executor, &SimulationOptions::default()).unwrap();
+        let module_state_key = StateKey::module_id(&MODULE_ID_MULTISIG_ACCOUNT);
+        let unpatched_module = executor
+            .data_store()
+            .get_state_value(&module_state_key)
+            .unwrap();
+
+        session
+            .execute_multisig_payload(
+                AccountAddress::ONE,
+                make_multisig_payload("0x1::aptos_account::set_allow_direct_coin_transfers", vec![
+                    TransactionArgument::Bool(true),
+                ]),
+            )
+            .unwrap();
+        let state_key = StateKey::resource(
+            &AccountAddress::ONE,
+            &"0x1::aptos_account::DirectTransferConfig".parse().unwrap(),
         )
-        .map_err(|err| {
-            anyhow!(
-                "failed to construct gas params at gas version {}: {}",
-                gas_feature_version,
-                err
+        .unwrap();
+        assert!(session
+            .state_view
+            .get_state_value(&state_key)
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            session
+                .state_view
+                .get_state_value(&module_state_key)
+                .unwrap(),
+            unpatched_module
+        );
+
+        // The failure is reported by executing the payload directly, and the patch is reverted
+        // all the same.
+        let err = session
+            .execute_multisig_payload(
+                AccountAddress::ONE,
+                make_multisig_payload("0x1::aptos_account::transfer", vec![
+                    TransactionArgument::Address(
+                        AccountAddress::from_hex_literal("0xcafe").unwrap(),
+                    ),
+                    TransactionArgument::U64(u64::MAX),
+                ]),
             )
-        })?;
+            .unwrap_err();
+        assert!(err.to_string().contains("the multisig payload failed"));
+        assert_eq!(
+            session
+                .state_view
+                .get_state_value(&module_state_key)
+                .unwrap(),
+            unpatched_module
+        );
+    }
 
-        // Patch framework functions to skip the governance process.
-        // This is redone every time we execute a script because the previous script could have
-        // overwritten the framework.
-        let features =
-            Features::fetch_config(&state_view).context("failed to fetch feature flags")?;
-        let deserializer_config = aptos_prod_deserializer_config(&features);
+    #[test]
+    fn test_takes_signer() {
+        let executor = FakeExecutor::from_head_genesis();
+        let (state_view, deserializer_config) = genesis_state_view(&executor);
+        let module_id = ModuleId::new(AccountAddress::ONE, ident_str!("account").to_owned());
 
-        // If the script is the last step of the proposal, it MUST NOT have a next execution hash.
-        // Set the boolean flag to true to use a modified patch to catch this.
-        let forbid_next_execution_hash = script_idx == proposal_scripts.len() - 1;
+        assert!(takes_signer(
+            &state_view,
+            &deserializer_config,
+            &module_id,
+            ident_str!("rotate_authentication_key_call"),
+        )
+        .unwrap());
+        assert!(!takes_signer(
+            &state_view,
+            &deserializer_config,
+            &module_id,
+            ident_str!("exists_at"),
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_inject_create_signer() {
+        let executor = FakeExecutor::from_head_genesis();
+        let (state_view, deserializer_config) = genesis_state_view(&executor);
+        // Injecting twice must not add a second definition.
+        inject_create_signer(&state_view, &deserializer_config).unwrap();
+        inject_create_signer(&state_view, &deserializer_config).unwrap();
+
+        let blob = state_view
+            .get_state_value_bytes(&StateKey::module_id(&MODULE_ID_APTOS_GOVERNANCE))
+            .unwrap()
+            .unwrap();
+        let mut m = CompiledModule::deserialize_with_config(&blob, &deserializer_config).unwrap();
+        let func_def = find_function_def_by_name(&mut m, &FUNC_NAME_CREATE_SIGNER).unwrap();
+        assert_eq!(func_def.visibility, Visibility::Public);
+    }
+
+    #[test]
+    fn test_setup_calls_revert_create_signer() {
+        let executor = FakeExecutor::from_head_genesis();
+        let options = SimulationOptions {
+            setup_calls: vec![SetupCall {
+                module_id: ModuleId::new(
+                    AccountAddress::ONE,
+                    Identifier::new("aptos_account").unwrap(),
+                ),
+                function: Identifier::new("set_allow_direct_coin_transfers").unwrap(),
+                ty_args: vec![],
+                args: vec![
+                    MoveValue::Signer(AccountAddress::ONE),
+                    MoveValue::Bool(true),
+                ],
+            }],
+            ..SimulationOptions::default()
+        };
+        let session = new_session(&executor, &options).unwrap();
+
+        // The scripts of the proposal cannot obtain arbitrary signers.
+        assert!(!session
+            .state_view
+            .states
+            .lock()
+            .contains_key(&StateKey::module_id(&MODULE_ID_APTOS_GOVERNANCE)));
+    }
+
+    #[test]
+    fn test_try_read_resource() {
+        let executor = FakeExecutor::from_head_genesis();
+        let state_view = SimulationStateView::new(executor.data_store());
+
+        assert!(state_view
+            .try_read_resource::<ChainIdResource>(&AccountAddress::ONE)
+            .unwrap()
+            .is_some());
+        assert!(state_view
+            .try_read_resource::<ChainIdResource>(
+                &AccountAddress::from_hex_literal("0xcafe").unwrap()
+            )
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_recorded_resolve_args() {
+        let executor = FakeExecutor::from_head_genesis();
+        let (state_view, deserializer_config) = genesis_state_view(&executor);
+
+        let patch_plan = PatchPlan {
+            record_resolve_args: true,
+            ..PatchPlan::default()
+        };
         patch_aptos_governance(
             &state_view,
             &deserializer_config,
-            forbid_next_execution_hash,
+            &patch_plan,
+            false,
+            false,
+            None,
         )
-        .context("failed to patch resolve_multistep_proposal")?;
+        .unwrap();
 
-        // Add the hash of the script to the list of approved hashes, so that the
-        // alternative (usually higher) execution limits can be used.
-        add_script_execution_hash(&state_view, script_hash)
-            .context("failed to add script execution hash")?;
+        // Call the patched function twice, as the recorded arguments must be overwritten.
+        for (proposal_id, signer_address) in [
+            (DEFAULT_DUMMY_PROPOSAL_ID, AccountAddress::ONE),
+            (42, AccountAddress::ONE),
+        ] {
+            let write_set = execute_function_unmetered(
+                &state_view,
+                &patch_plan.governance_module,
+                &patch_plan.resolve_function,
+                vec![],
+                vec![
+                    MoveValue::U64(proposal_id),
+                    MoveValue::Address(signer_address),
+                    MoveValue::Vector(vec![]),
+                ],
+            )
+            .unwrap();
 
-        let script_name = script_path.file_name().unwrap().to_string_lossy();
-        println!("    {}", script_name);
+            let resolve_args =
+                find_recorded_resolve_args(&patch_plan.governance_module, &write_set).unwrap();
+            assert_eq!(
+                resolve_args,
+                Some(ResolveArgs {
+                    proposal_id,
+                    signer_address,
+                })
+            );
+            state_view.apply_write_set(write_set);
+        }
+    }
 
-        // Create a new VM to ensure the loader is clean.
-        let env = AptosEnvironment::new_with_injected_create_signer_for_gov_sim(&state_view);
-        let vm = AptosVM::new(&env, &state_view);
-        let log_context = AdapterLogSchema::new(state_view.id(), 0);
+    /// Returns a compiled script that takes the proposal id and does nothing.
+    fn make_noop_script() -> (Vec<u8>, HashValue) {
+        let mut script = empty_script();
+        script.signatures.push(Signature(vec![SignatureToken::U64]));
+        script.parameters = SignatureIndex(1);
+        script.code.code = vec![Bytecode::Ret];
+        let mut blob = vec![];
+        script.serialize(&mut blob).unwrap();
+        let hash = HashValue::sha3_256_of(&blob);
+        (blob, hash)
+    }
 
-        let resolver = state_view.as_move_resolver();
-        let code_storage = state_view.as_aptos_code_storage(&env);
+    /// Starts a session for a proposal without a manifest on top of the genesis of the executor.
+    fn new_session<'a>(
+        executor: &'a FakeExecutor,
+        options: &SimulationOptions,
+    ) -> Result<SimulationSession<&'a FakeDataStore>> {
+        SimulationSession::new(executor.data_store(), Path::new("noop-proposal"), options)
+    }
 
-        let txn = account
-            .account()
-            .transaction()
-            .script(Script::new(script_blob, vec![], vec![
-                TransactionArgument::U64(DUMMY_PROPOSAL_ID), // dummy proposal id, ignored by the patched function
-            ]))
-            .chain_id(chain_id.chain_id())
-            .sequence_number(script_idx as u64)
-            .gas_unit_price(gas_params.vm.txn.min_price_per_gas_unit.into())
-            .max_gas_amount(100000)
-            .ttl(u64::MAX)
-            .sign();
+    /// Returns a state view on top of the genesis of the executor, along with the deserializer
+    /// config matching its features.
+    fn genesis_state_view(
+        executor: &FakeExecutor,
+    ) -> (SimulationStateView<&FakeDataStore>, DeserializerConfig) {
+        let state_view = SimulationStateView::new(executor.data_store());
+        let features = Features::fetch_config(&state_view).unwrap();
+        (state_view, aptos_prod_deserializer_config(&features))
+    }
 
-        let vm_output = if !profile_gas {
-            let (_vm_status, vm_output) =
-                vm.execute_user_transaction(&resolver, &code_storage, &txn, &log_context);
-            vm_output
-        } else {
-            let (_vm_status, vm_output, gas_profiler) = vm
-                .execute_user_transaction_with_modified_gas_meter(
-                    &resolver,
-                    &code_storage,
-                    &txn,
-                    &log_context,
-                    GasProfiler::new_script,
-                )?;
+    #[test]
+    fn test_sender_account() {
+        let executor = FakeExecutor::from_head_genesis();
+        let supply_keys = [
+            StateKey::resource_typed::<CoinInfoResource<AptosCoinType>>(&AccountAddress::ONE)
+                .unwrap(),
+            StateKey::resource_group(&AccountAddress::TEN, &ObjectGroupResource::struct_tag()),
+        ];
+        let supply_changed = |session: &SimulationSession<_>| {
+            supply_keys.iter().any(|state_key| {
+                session.state_view.get_state_value(state_key).unwrap()
+                    != executor.data_store().get_state_value(state_key).unwrap()
+            })
+        };
+        let script = make_noop_script();
+        let script_path = Path::new("noop.move");
 
-            let gas_log = gas_profiler.finish();
-            let report_path = proposal_dir
-                .join("gas-profiling")
-                .join(script_path.file_stem().unwrap());
-            gas_log.generate_html_report(&report_path, format!("Gas Report - {}", script_name))?;
+        // A new account is funded out of thin air.
+        let session = new_session(&executor, &SimulationOptions::default()).unwrap();
+        assert!(supply_changed(&session));
 
-            println!("        Gas report saved to {}", report_path.display());
+        // An existing account is taken over along with its balance, which is not minted again.
+        let address = aptos_types::account_config::aptos_test_root_address();
+        let options = SimulationOptions {
+            sender_account: SenderAccount::Address(address),
+            ..SimulationOptions::default()
+        };
+        let mut session = new_session(&executor, &options).unwrap();
+        assert!(!supply_changed(&session));
+        session.execute_script(script_path, &script, true).unwrap();
 
-            vm_output
+        // A given account must not overwrite an existing one.
+        let (privkey, pubkey) = aptos_keygen::KeyGen::from_seed([1; 32]).generate_ed25519_keypair();
+        let options = SimulationOptions {
+            sender_account: SenderAccount::Account(AccountData::with_account(
+                Account::new_validator(address, privkey, pubkey),
+                0,
+                0,
+                false,
+                false,
+            )),
+            ..SimulationOptions::default()
         };
-        // TODO: ensure all scripts trigger reconfiguration.
+        let err = new_session(&executor, &options).err().unwrap();
+        assert!(format!("{:#}", err).contains("already exists"));
+    }
 
-        println!(
-            "{}",
-            format!("Fee statement: {:#?}", vm_output.fee_statement())
-                .lines()
-                .map(|line| format!("        {}", line))
-                .collect::<Vec<_>>()
-                .join("\n")
-        );
+    #[test]
+    fn test_session_restore() {
+        let executor = FakeExecutor::from_head_genesis();
+        let mut session = new_session(&executor, &SimulationOptions::default()).unwrap();
+        let script = make_noop_script();
+        let script_path = Path::new("noop.move");
 
-        let txn_output = vm_output
-            .try_materialize_into_transaction_output(&resolver)
-            .context("failed to materialize transaction output")?;
+        let snapshot = session.snapshot();
+        let sequence_number = session.next_sequence_number().unwrap();
+        let overlay = session.state_view.overlay();
+        session.execute_script(script_path, &script, true).unwrap();
+        session.execute_script(script_path, &script, true).unwrap();
+        assert_eq!(session.next_sequence_number().unwrap(), sequence_number + 2);
 
-        let txn_status = txn_output.status();
-        match txn_status {
-            TransactionStatus::Keep(ExecutionStatus::Success) => {
-                println!("        Success")
-            },
-            TransactionStatus::Keep(ExecutionStatus::MoveAbort { code, .. })
-                if *code == MAGIC_FAILED_NEXT_EXECUTION_HASH_CHECK =>
-            {
-                bail!("the last script has a non-zero next execution hash")
-            },
-            _ => {
-                println!(
-                    "{}",
-                    format!("{:#?}", txn_status)
-                        .lines()
-                        .map(|line| format!("        {}", line))
-                        .collect::<Vec<_>>()
-                        .join("\n")
+        // Restoring the snapshot also rewinds the sequence number of the sender.
+        session.restore(snapshot);
+        assert_eq!(session.next_sequence_number().unwrap(), sequence_number);
+        assert_eq!(session.state_view.overlay(), overlay);
+        session.execute_script(script_path, &script, true).unwrap();
+    }
+
+    #[test]
+    fn test_collect_transaction_outputs() {
+        let executor = FakeExecutor::from_head_genesis();
+        let script = make_noop_script();
+        let script_path = Path::new("noop.move");
+
+        for collect_transaction_outputs in [false, true] {
+            let options = SimulationOptions {
+                collect_transaction_outputs,
+                ..SimulationOptions::default()
+            };
+            let mut session = new_session(&executor, &options).unwrap();
+            session.execute_script(script_path, &script, true).unwrap();
+            let outputs = session.take_transaction_outputs();
+            assert_eq!(outputs.len(), collect_transaction_outputs as usize);
+            for output in outputs {
+                assert_eq!(
+                    output.status,
+                    TransactionStatus::Keep(ExecutionStatus::Success)
                 );
-                bail!("failed to execute governance script: {}", script_name)
-            },
+            }
         }
+    }
 
-        let (write_set, _events) = txn_output.into();
-        state_view.apply_write_set(write_set);
+    #[test]
+    fn test_simulation_context() {
+        let context = SimulationContext {
+            version: Some(5),
+            chain_id: 4,
+            epoch_before: 3,
+            epoch_after: None,
+        };
+
+        // A failure carries the context recorded before the scripts were executed.
+        let err = anyhow!("failed to execute").context(context.clone());
+        assert_eq!(
+            format!("{:#}", err),
+            "chain id 4, version 5, epoch 3: failed to execute"
+        );
+        assert_eq!(
+            err.downcast_ref::<SimulationContext>()
+                .unwrap()
+                .epoch_before,
+            3
+        );
+
+        let context = SimulationContext {
+            epoch_after: Some(4),
+            ..context
+        };
+        assert_eq!(context.to_string(), "chain id 4, version 5, epoch 3 -> 4");
+    }
+
+    #[test]
+    fn test_dump_patched_modules() {
+        let executor = FakeExecutor::from_head_genesis();
+        let proposal_dir = aptos_temppath::TempPath::new();
+        proposal_dir.create_as_dir().unwrap();
+        let options = SimulationOptions {
+            dump_patched_modules: true,
+            ..SimulationOptions::default()
+        };
+        let mut session =
+            SimulationSession::new(executor.data_store(), proposal_dir.path(), &options).unwrap();
+        let script = make_noop_script();
+        session
+            .execute_script(Path::new("first.move"), &script, true)
+            .unwrap();
+        session
+            .execute_script(Path::new("second.move"), &script, true)
+            .unwrap();
+
+        // Each script gets its own directory, which are registered even if the proposal failed.
+        let report = ReleaseSimulationReport {
+            proposals: vec![ProposalReport {
+                proposal_dir: proposal_dir.path().to_path_buf(),
+                duration: Duration::ZERO,
+                script_results: vec![],
+                failure: Some("failed".to_string()),
+                skipped: false,
+                context: None,
+            }],
+            ..ReleaseSimulationReport::default()
+        };
+        let patched_modules_dirs = report
+            .artifacts(&options)
+            .into_iter()
+            .filter(|artifact| artifact.kind == ArtifactKind::PatchedModules)
+            .map(|artifact| artifact.path)
+            .collect::<Vec<_>>();
+        let patched_modules_dir = proposal_dir.path().join(PATCHED_MODULES_DIR_NAME);
+        assert_eq!(patched_modules_dirs, vec![
+            patched_modules_dir.join("0-first"),
+            patched_modules_dir.join("1-second"),
+        ]);
     }
 
-    println!("All scripts succeeded!");
+    #[test]
+    fn test_reuse_environment() {
+        let executor = FakeExecutor::from_head_genesis();
+        let mut session = new_session(&executor, &SimulationOptions::default()).unwrap();
+        let script = make_noop_script();
+        let script_path = Path::new("noop.move");
 
-    Ok(())
-}
+        // The epoch is forced to end before each script, which must not invalidate the cache.
+        session.execute_script(script_path, &script, true).unwrap();
+        let env = session.cached_env.as_ref().unwrap().1.clone();
+        session.execute_script(script_path, &script, true).unwrap();
+        let reused_env = &session.cached_env.as_ref().unwrap().1;
+        assert!(std::ptr::eq(
+            env.runtime_environment(),
+            reused_env.runtime_environment()
+        ));
+    }
 
-pub fn collect_proposals(root_dir: &Path) -> Result<Vec<(PathBuf, Vec<PathBuf>)>> {
-    let mut result = Vec::new();
-
-    for entry in WalkDir::new(root_dir) {
-        let entry = entry.unwrap();
-        if entry.path().is_dir() {
-            let sub_dir = entry.path();
-            let mut move_files = Vec::new();
-
-            for sub_entry in WalkDir::new(sub_dir).min_depth(1).max_depth(1) {
-                let sub_entry = sub_entry.unwrap();
-                if sub_entry.path().is_file()
-                    && sub_entry.path().extension() == Some(std::ffi::OsStr::new("move"))
-                {
-                    move_files.push(sub_entry.path().to_path_buf());
-                }
-            }
+    #[test]
+    fn test_config_override_reset() {
+        let executor = FakeExecutor::from_head_genesis();
+        let options = SimulationOptions {
+            config_overrides: vec![
+                ConfigOverride::new(|gas_schedule: &mut GasScheduleV2| {
+                    gas_schedule.feature_version += 1;
+                    Ok(())
+                })
+                .unwrap(),
+                ConfigOverride::reset::<GasScheduleV2>().unwrap(),
+            ],
+            ..SimulationOptions::default()
+        };
+        let session = new_session(&executor, &options).unwrap();
 
-            if !move_files.is_empty() {
-                move_files.sort();
-                result.push((sub_dir.to_path_buf(), move_files));
-            }
-        }
+        // The reset discards the earlier override, so the remote value is used.
+        let state_key = StateKey::on_chain_config::<GasScheduleV2>().unwrap();
+        assert_eq!(
+            session.state_view.get_state_value(&state_key).unwrap(),
+            executor.data_store().get_state_value(&state_key).unwrap()
+        );
     }
 
-    result.sort_by(|(path1, _), (path2, _)| path1.cmp(path2));
+    #[test]
+    fn test_unknown_governance_module() {
+        let executor = FakeExecutor::from_head_genesis();
+        let mut options = SimulationOptions::default();
+        options.patch_plan.governance_module = ModuleId::new(
+            AccountAddress::ONE,
+            Identifier::new("no_governance").unwrap(),
+        );
+        let err = new_session(&executor, &options).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("unknown governance module 0x1::no_governance"));
+    }
 
-    Ok(result)
-}
+    #[test]
+    fn test_patch_custom_governance_module() {
+        let executor = FakeExecutor::from_head_genesis();
+        let (state_view, deserializer_config) = genesis_state_view(&executor);
 
-pub async fn simulate_all_proposals(
-    remote_url: Url,
-    output_dir: &Path,
-    profile_gas: bool,
-) -> Result<()> {
-    let proposals =
-        collect_proposals(output_dir).context("failed to collect proposals for simulation")?;
+        // Publish a governance module whose resolve function aborts until it is patched.
+        let governance_module = ModuleId::new(
+            AccountAddress::ONE,
+            Identifier::new("experimental_governance").unwrap(),
+        );
+        let mut module = empty_module();
+        module.version = VERSION_6;
+        module.identifiers[0] = governance_module.name().to_owned();
+        module.address_identifiers[0] = *governance_module.address();
+        let mut m = IndexedModule::new(&mut module);
+        let resolve_handle_idx = add_simple_native_function(
+            &mut m,
+            FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL.clone(),
+            vec![SignatureToken::U64, SignatureToken::Address],
+            vec![SignatureToken::Signer],
+        )
+        .unwrap();
+        let locals = get_or_add_signature(&mut m, vec![]);
+        let func_def = m
+            .function_defs
+            .iter_mut()
+            .find(|func_def| func_def.function == resolve_handle_idx)
+            .unwrap();
+        func_def.code = Some(CodeUnit {
+            locals,
+            code: vec![Bytecode::LdU64(0), Bytecode::Abort],
+        });
+        let mut blob = vec![];
+        module.serialize(&mut blob).unwrap();
+        state_view.set_state_value(
+            StateKey::module_id(&governance_module),
+            StateValue::new_legacy(blob.into()),
+        );
 
-    if proposals.is_empty() {
-        bail!("failed to simulate proposals: no proposals found")
+        let patch_plan = PatchPlan {
+            governance_module: governance_module.clone(),
+            ..PatchPlan::default()
+        };
+        patch_aptos_governance(
+            &state_view,
+            &deserializer_config,
+            &patch_plan,
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+        execute_function_unmetered(
+            &state_view,
+            &governance_module,
+            &FUNC_NAME_RESOLVE_MULTI_STEP_PROPOSAL,
+            vec![],
+            vec![MoveValue::U64(42), MoveValue::Address(AccountAddress::ONE)],
+        )
+        .unwrap();
+
+        // `create_signer` is only exposed to the governance module.
+        let blob = state_view
+            .get_state_value_bytes(&StateKey::module_id(&MODULE_ID_APTOS_GOVERNANCE))
+            .unwrap()
+            .unwrap();
+        let mut m = CompiledModule::deserialize_with_config(&blob, &deserializer_config).unwrap();
+        let func_def = find_function_def_by_name(&mut m, &FUNC_NAME_CREATE_SIGNER).unwrap();
+        assert_eq!(func_def.visibility, Visibility::Friend);
+        assert!(m.immediate_friends().contains(&governance_module));
+
+        // Modules at other addresses cannot be friends.
+        let patch_plan = PatchPlan {
+            governance_module: ModuleId::new(
+                AccountAddress::from_hex_literal("0xcafe").unwrap(),
+                governance_module.name().to_owned(),
+            ),
+            ..PatchPlan::default()
+        };
+        assert!(patch_aptos_governance(
+            &state_view,
+            &deserializer_config,
+            &patch_plan,
+            false,
+            false,
+            None,
+        )
+        .is_err());
     }
 
-    println!(
-        "Found {} proposal{}",
-        proposals.len(),
-        if proposals.len() == 1 { "" } else { "s" }
-    );
-    for (proposal_dir, proposal_scripts) in &proposals {
-        println!("    {}", proposal_dir.display());
+    /// Returns a script that calls the given function of the governance module of the patch plan.
+    fn make_governance_call_script(patch_plan: &PatchPlan, func_name: &IdentStr) -> CompiledScript {
+        let mut script = empty_script();
+        script.address_identifiers.push(AccountAddress::ONE);
+        script.identifiers.extend([
+            patch_plan.governance_module.name().to_owned(),
+            func_name.to_owned(),
+        ]);
+        script.module_handles.push(ModuleHandle {
+            address: AddressIdentifierIndex(0),
+            name: IdentifierIndex(0),
+        });
+        script.function_handles.push(FunctionHandle {
+            module: ModuleHandleIndex(0),
+            name: IdentifierIndex(1),
+            parameters: SignatureIndex(0),
+            return_: SignatureIndex(0),
+            type_parameters: vec![],
+            access_specifiers: None,
+            attributes: vec![],
+        });
+        script
+    }
 
-        for script_path in proposal_scripts {
-            println!(
-                "        {}",
-                script_path.file_name().unwrap().to_string_lossy()
-            );
-        }
+    #[test]
+    fn test_is_single_step_script() {
+        let patch_plan = PatchPlan::default();
+
+        assert!(is_single_step_script(
+            &make_governance_call_script(&patch_plan, &FUNC_NAME_RESOLVE),
+            &patch_plan
+        ));
+        assert!(!is_single_step_script(
+            &make_governance_call_script(&patch_plan, &patch_plan.resolve_function),
+            &patch_plan
+        ));
+        assert!(!is_single_step_script(&empty_script(), &patch_plan));
     }
 
-    for (proposal_dir, proposal_scripts) in &proposals {
-        simulate_multistep_proposal(
-            remote_url.clone(),
-            proposal_dir,
-            proposal_scripts,
-            profile_gas,
+    #[test]
+    fn test_patch_aptos_governance_single_step() {
+        let executor = FakeExecutor::from_head_genesis();
+        let (state_view, deserializer_config) = genesis_state_view(&executor);
+
+        patch_aptos_governance(
+            &state_view,
+            &deserializer_config,
+            &PatchPlan::default(),
+            true,
+            true,
+            None,
         )
-        .await
-        .with_context(|| format!("failed to simulate proposal at {}", proposal_dir.display()))?;
-    }
+        .unwrap();
 
-    println!("All proposals succeeded!");
+        let blob = state_view
+            .get_state_value_bytes(&StateKey::module_id(&MODULE_ID_APTOS_GOVERNANCE))
+            .unwrap()
+            .unwrap();
+        let mut m = CompiledModule::deserialize_with_config(&blob, &deserializer_config).unwrap();
+        let create_signer_handle_idx = m
+            .function_handles
+            .iter()
+            .position(|handle| {
+                m.identifier_at(handle.name) == FUNC_NAME_CREATE_SIGNER.as_ident_str()
+            })
+            .unwrap();
 
-    Ok(())
+        // `resolve` returns the signer directly, without the check of the next execution hash
+        // requested for multi-step proposals.
+        let func_def = find_function_def_by_name(&mut m, &FUNC_NAME_RESOLVE).unwrap();
+        assert_eq!(func_def.code.as_ref().unwrap().code, vec![
+            Bytecode::MoveLoc(1),
+            Bytecode::Call(FunctionHandleIndex(create_signer_handle_idx as u16)),
+            Bytecode::Ret
+        ]);
+    }
 }
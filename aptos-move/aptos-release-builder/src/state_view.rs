@@ -0,0 +1,303 @@
+// Copyright © Aptos Foundation
+// SPDX-License-Identifier: Apache-2.0
+
+//! A state view that stacks local changes, e.g. the side effects of governance scripts, on top of
+//! a remote state, which is fetched on demand.
+
+use anyhow::{anyhow, Context, Result};
+use aptos_types::{
+    account_address::AccountAddress,
+    on_chain_config::OnChainConfig,
+    state_store::{
+        state_key::StateKey,
+        state_storage_usage::StateStorageUsage,
+        state_value::{PersistedStateValueMetadata, StateValue, StateValueMetadata},
+        StateView, StateViewResult as StateStoreResult, TStateView,
+    },
+    write_set::{TransactionWrite, WriteSet},
+};
+use move_core_types::{
+    identifier::Identifier, language_storage::StructTag, move_resource::MoveResource,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+/// A state view specifically designed for managing the side effects generated by
+///  the governance scripts.
+///
+/// It comprises two components:
+/// - A remote state view to enable on-demand data fetching, e.g. one of the remote debugger.
+/// - A local state store to allow new changes to be stacked on top of the remote state.
+pub struct SimulationStateView<S> {
+    pub(crate) remote: S,
+    pub(crate) states: Mutex<HashMap<StateKey, Option<StateValue>>>,
+    /// State keys read from the remote state view, i.e. that missed the local changes.
+    pub(crate) remote_reads: Mutex<BTreeSet<StateKey>>,
+}
+
+impl<S> SimulationStateView<S> {
+    /// Creates a state view with no local changes on top of the given remote state view.
+    pub fn new(remote: S) -> Self {
+        Self {
+            remote,
+            states: Mutex::new(HashMap::new()),
+            remote_reads: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Returns the remote state view the local changes are stacked on top of.
+    pub fn remote(&self) -> &S {
+        &self.remote
+    }
+
+    /// Returns the local changes, i.e. everything that differs from the remote state, sorted by
+    /// state key. `None` denotes a deleted state item.
+    pub fn overlay(&self) -> Vec<(StateKey, Option<StateValue>)> {
+        let mut overlay = self
+            .states
+            .lock()
+            .iter()
+            .map(|(state_key, state_value)| (state_key.clone(), state_value.clone()))
+            .collect::<Vec<_>>();
+        overlay.sort_by(|(key1, _), (key2, _)| key1.cmp(key2));
+        overlay
+    }
+
+    /// Returns the state keys read from the remote state so far, sorted, i.e. the read footprint
+    /// of everything executed on top of this state view.
+    pub fn remote_reads(&self) -> Vec<StateKey> {
+        self.remote_reads.lock().iter().cloned().collect()
+    }
+
+    /// Writes the local changes to the given path in the given format, as a list of state keys
+    /// and values in the order of [`Self::overlay`], and returns the number of state items
+    /// written.
+    pub fn export_overlay(&self, path: &Path, format: OverlayFormat) -> Result<usize> {
+        let overlay = self.overlay();
+        let bytes = match format {
+            OverlayFormat::Bcs => bcs::to_bytes(&overlay)?,
+            OverlayFormat::Json => {
+                let entries = overlay
+                    .iter()
+                    .map(|(state_key, state_value)| JsonOverlayEntry::new(state_key, state_value))
+                    .collect::<Result<Vec<_>>>()?;
+                serde_json::to_vec_pretty(&entries)?
+            },
+        };
+        std::fs::write(path, bytes)
+            .with_context(|| format!("failed to write state overlay to {}", path.display()))?;
+        Ok(overlay.len())
+    }
+
+    /// Reads local changes exported by [`Self::export_overlay`] and stacks them on top of the
+    /// current ones, e.g. to continue from the state left behind by another simulation. The
+    /// format is detected from the file extension unless given.
+    ///
+    /// Returns the number of state items read.
+    pub fn import_overlay(&self, path: &Path, format: Option<OverlayFormat>) -> Result<usize> {
+        let format = match format {
+            Some(format) => format,
+            None => OverlayFormat::from_path(path)?,
+        };
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("failed to read state overlay {}", path.display()))?;
+        let overlay: Vec<(StateKey, Option<StateValue>)> = match format {
+            OverlayFormat::Bcs => bcs::from_bytes(&bytes).map_err(anyhow::Error::from),
+            OverlayFormat::Json => serde_json::from_slice::<Vec<JsonOverlayEntry>>(&bytes)
+                .map_err(anyhow::Error::from)
+                .and_then(|entries| {
+                    entries
+                        .into_iter()
+                        .map(JsonOverlayEntry::into_parts)
+                        .collect()
+                }),
+        }
+        .with_context(|| format!("failed to parse state overlay {}", path.display()))?;
+
+        let num_state_items = overlay.len();
+        self.states.lock().extend(overlay);
+        Ok(num_state_items)
+    }
+}
+
+/// Encoding of an exported state overlay.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverlayFormat {
+    /// A BCS-encoded list of state keys and values, which round-trips exactly.
+    #[default]
+    Bcs,
+    /// A JSON list of state keys and hex-encoded values, for reviewing the changes.
+    Json,
+}
+
+impl OverlayFormat {
+    /// Returns the file extension of the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Bcs => "bcs",
+            Self::Json => "json",
+        }
+    }
+
+    /// Detects the format from the extension of the given path.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("bcs") => Ok(Self::Bcs),
+            Some("json") => Ok(Self::Json),
+            _ => Err(anyhow!(
+                "cannot detect the format of state overlay {}, expected a .bcs or .json file",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// A state item of an overlay exported as JSON.
+///
+/// The state key is stored BCS-encoded to round-trip exactly, along with a readable description.
+#[derive(Deserialize, Serialize)]
+struct JsonOverlayEntry {
+    /// Hex-encoded BCS of the state key.
+    key: String,
+    /// Debug representation of the state key, ignored when importing.
+    description: String,
+    /// Hex-encoded bytes of the state value, or `None` for a deleted state item.
+    value: Option<String>,
+    metadata: Option<PersistedStateValueMetadata>,
+}
+
+impl JsonOverlayEntry {
+    fn new(state_key: &StateKey, state_value: &Option<StateValue>) -> Result<Self> {
+        Ok(Self {
+            key: hex::encode(bcs::to_bytes(state_key)?),
+            description: format!("{:?}", state_key),
+            value: state_value
+                .as_ref()
+                .map(|state_value| hex::encode(state_value.bytes())),
+            metadata: state_value
+                .as_ref()
+                .and_then(|state_value| state_value.metadata().clone().into_persistable()),
+        })
+    }
+
+    fn into_parts(self) -> Result<(StateKey, Option<StateValue>)> {
+        let state_key: StateKey = bcs::from_bytes(&hex::decode(&self.key)?)
+            .with_context(|| format!("invalid state key {}", self.description))?;
+        let state_value = match self.value {
+            Some(value) => Some(StateValue::new_with_metadata(
+                hex::decode(value)?.into(),
+                self.metadata
+                    .map(PersistedStateValueMetadata::into_in_mem_form)
+                    .unwrap_or_else(StateValueMetadata::none),
+            )),
+            None => None,
+        };
+        Ok((state_key, state_value))
+    }
+}
+
+impl<S> SimulationStateView<S>
+where
+    S: StateView,
+{
+    pub fn set_state_value(&self, state_key: StateKey, state_val: StateValue) {
+        self.states.lock().insert(state_key, Some(state_val));
+    }
+
+    pub fn set_on_chain_config<C>(&self, config: &C) -> Result<()>
+    where
+        C: OnChainConfig + Serialize,
+    {
+        let addr = AccountAddress::from_hex_literal(C::ADDRESS).unwrap();
+
+        self.set_state_value(
+            StateKey::resource(&addr, &StructTag {
+                address: addr,
+                module: Identifier::new(C::MODULE_IDENTIFIER).unwrap(),
+                name: Identifier::new(C::TYPE_IDENTIFIER).unwrap(),
+                type_args: vec![],
+            })?,
+            StateValue::new_legacy(bcs::to_bytes(&config)?.into()),
+        );
+
+        Ok(())
+    }
+
+    pub fn modify_on_chain_config<C, F>(&self, modify: F) -> Result<()>
+    where
+        C: OnChainConfig + Serialize,
+        F: FnOnce(&mut C) -> Result<()>,
+    {
+        let mut config = C::fetch_config(self).ok_or_else(|| {
+            anyhow!(
+                "failed to fetch on-chain config: {:?}",
+                std::any::type_name::<C>()
+            )
+        })?;
+
+        modify(&mut config)?;
+
+        self.set_on_chain_config(&config)?;
+
+        Ok(())
+    }
+
+    pub fn remove_state_value(&self, state_key: &StateKey) {
+        self.states.lock().remove(state_key);
+    }
+
+    /// Applies the write set on top of the local changes. Deletions are recorded as `None`
+    /// rather than dropping the local change, so that they shadow the remote state.
+    pub fn apply_write_set(&self, write_set: WriteSet) {
+        let mut states = self.states.lock();
+
+        for (state_key, write_op) in write_set {
+            states.insert(state_key, write_op.as_state_value());
+        }
+    }
+
+    /// Reads a resource, returning `None` if the account does not hold it. Failures to fetch or
+    /// deserialize the resource are returned as errors, e.g. for asserting on the state a proposal
+    /// leaves behind.
+    pub fn try_read_resource<T: MoveResource>(&self, addr: &AccountAddress) -> Result<Option<T>> {
+        let state_key = StateKey::resource_typed::<T>(addr)
+            .with_context(|| format!("failed to create state key of {}", T::STRUCT_NAME))?;
+        let Some(data_blob) = self
+            .get_state_value_bytes(&state_key)
+            .with_context(|| format!("failed to fetch {} resource for {}", T::STRUCT_NAME, addr))?
+        else {
+            return Ok(None);
+        };
+        let resource = bcs::from_bytes(&data_blob).with_context(|| {
+            format!(
+                "failed to deserialize {} resource for {}",
+                T::STRUCT_NAME,
+                addr
+            )
+        })?;
+        Ok(Some(resource))
+    }
+}
+
+impl<S> TStateView for SimulationStateView<S>
+where
+    S: StateView,
+{
+    type Key = StateKey;
+
+    fn get_state_value(&self, state_key: &Self::Key) -> StateStoreResult<Option<StateValue>> {
+        if let Some(res) = self.states.lock().get(state_key) {
+            return Ok(res.clone());
+        }
+        self.remote_reads.lock().insert(state_key.clone());
+        self.remote.get_state_value(state_key)
+    }
+
+    fn get_usage(&self) -> StateStoreResult<StateStorageUsage> {
+        Ok(StateStorageUsage::Untracked)
+    }
+}
@@ -55,6 +55,15 @@ impl AptosEnvironment {
         Self(Arc::new(Environment::new(state_view, true, None)))
     }
 
+    /// Like [Self::new_with_injected_create_signer_for_gov_sim], but with paranoid type checks
+    /// enabled regardless of the process-wide setting. Should not be used for regular execution.
+    pub fn new_with_injected_create_signer_and_paranoid_type_checks_for_gov_sim(
+        state_view: &impl StateView,
+    ) -> Self {
+        let env = Environment::new(state_view, true, None).enable_paranoid_type_checks();
+        Self(Arc::new(env))
+    }
+
     /// Returns new environment but with delayed field optimization enabled. Should only be used by
     /// block executor where this optimization is needed. Note: whether the optimization will be
     /// enabled or not depends on the feature flag.
@@ -251,6 +260,16 @@ impl Environment {
         }
     }
 
+    fn enable_paranoid_type_checks(mut self) -> Self {
+        self.runtime_environment.enable_paranoid_type_checks();
+        // Environments with and without the checks must not compare equal.
+        let mut sha3_256 = Sha3_256::new();
+        sha3_256.update(self.hash);
+        sha3_256.update(b"paranoid_type_checks");
+        self.hash = sha3_256.finalize().into();
+        self
+    }
+
     fn try_enable_delayed_field_optimization(mut self) -> Self {
         if self.features.is_aggregator_v2_delayed_fields_enabled() {
             self.runtime_environment.enable_delayed_field_optimization();
@@ -96,6 +96,12 @@ impl RuntimeEnvironment {
         self.vm_config.delayed_field_optimization_enabled = true;
     }
 
+    /// Enables paranoid type checks for this environment, regardless of the VM config it was
+    /// created with.
+    pub fn enable_paranoid_type_checks(&mut self) {
+        self.vm_config.paranoid_type_checks = true;
+    }
+
     /// Creates a locally verified compiled script by running:
     ///   1. Move bytecode verifier,
     ///   2. Verifier extension, if provided.